@@ -0,0 +1,1197 @@
+//! Ready-made [`Action`]s for common operations.
+//!
+//! The query and command actions take their SQL and parameters by value so they
+//! can be sent to other threads, as required by
+//! [`TokioVault`](crate::tokio::TokioVault). For more complex operations,
+//! implement [`Action`] directly.
+
+#[cfg(feature = "serde")]
+use std::marker::PhantomData;
+use std::{
+    error, fmt,
+    time::{Duration, Instant},
+};
+
+use rusqlite::Connection;
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, ser::SerializeStruct, Serialize, Serializer};
+
+use crate::{Action, OwnedParams};
+
+/// Run a query and deserialize all resulting rows via
+/// [`from_row_via_name`](crate::from_row_via_name).
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone)]
+pub struct QueryAll<T> {
+    sql: String,
+    params: OwnedParams,
+    _output: PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "serde")]
+impl<T> QueryAll<T> {
+    pub fn new(sql: impl Into<String>, params: impl Into<OwnedParams>) -> Self {
+        Self {
+            sql: sql.into(),
+            params: params.into(),
+            _output: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: DeserializeOwned> Action for QueryAll<T> {
+    type Output = Vec<T>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let mut stmt = conn.prepare(&self.sql)?;
+        self.params.bind(&mut stmt)?;
+        let mut rows = stmt.raw_query();
+        let mut result = vec![];
+        while let Some(row) = rows.next()? {
+            result.push(crate::serde::from_row_via_name(row)?);
+        }
+        Ok(result)
+    }
+}
+
+/// Run a query and deserialize the first resulting row, if any, via
+/// [`from_row_via_name`](crate::from_row_via_name).
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone)]
+pub struct QueryOpt<T> {
+    sql: String,
+    params: OwnedParams,
+    _output: PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "serde")]
+impl<T> QueryOpt<T> {
+    pub fn new(sql: impl Into<String>, params: impl Into<OwnedParams>) -> Self {
+        Self {
+            sql: sql.into(),
+            params: params.into(),
+            _output: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: DeserializeOwned> Action for QueryOpt<T> {
+    type Output = Option<T>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let mut stmt = conn.prepare(&self.sql)?;
+        self.params.bind(&mut stmt)?;
+        let mut rows = stmt.raw_query();
+        match rows.next()? {
+            Some(row) => crate::serde::from_row_via_name(row).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Execute a statement with a `RETURNING` clause, like `INSERT ... RETURNING
+/// ...`, and deserialize all returned rows via
+/// [`from_row_via_name`](crate::from_row_via_name).
+///
+/// This is useful for retrieving columns that were generated while inserting,
+/// like rowids or default values, without a second query.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone)]
+pub struct InsertReturning<T> {
+    sql: String,
+    params: OwnedParams,
+    _output: PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "serde")]
+impl<T> InsertReturning<T> {
+    pub fn new(sql: impl Into<String>, params: impl Into<OwnedParams>) -> Self {
+        Self {
+            sql: sql.into(),
+            params: params.into(),
+            _output: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: DeserializeOwned> Action for InsertReturning<T> {
+    type Output = Vec<T>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        // The statement only finishes once all returned rows have been read,
+        // which QueryAll already does.
+        QueryAll::new(self.sql, self.params).run(conn)
+    }
+}
+
+/// Execute a statement with a `RETURNING` clause that must return exactly one
+/// row, like `INSERT ... RETURNING id`, and deserialize that row via
+/// [`from_row_via_name`](crate::from_row_via_name).
+///
+/// Returns [`rusqlite::Error::QueryReturnedNoRows`] if the statement returns no
+/// rows and an error if it returns more than one row. In both cases, the
+/// statement's changes are rolled back, since the statement runs inside a
+/// savepoint.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone)]
+pub struct InsertReturningOne<T> {
+    sql: String,
+    params: OwnedParams,
+    _output: PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "serde")]
+impl<T> InsertReturningOne<T> {
+    pub fn new(sql: impl Into<String>, params: impl Into<OwnedParams>) -> Self {
+        Self {
+            sql: sql.into(),
+            params: params.into(),
+            _output: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: DeserializeOwned> Action for InsertReturningOne<T> {
+    type Output = T;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let sp = conn.savepoint()?;
+        let result = {
+            let mut stmt = sp.prepare(&self.sql)?;
+            self.params.bind(&mut stmt)?;
+            crate::serde::single_row_into(stmt.raw_query())?
+        };
+        sp.commit()?;
+        Ok(result)
+    }
+}
+
+/// Execute a statement and return the number of rows that were changed.
+#[derive(Debug, Clone)]
+pub struct Exec {
+    sql: String,
+    params: OwnedParams,
+}
+
+impl Exec {
+    pub fn new(sql: impl Into<String>, params: impl Into<OwnedParams>) -> Self {
+        Self {
+            sql: sql.into(),
+            params: params.into(),
+        }
+    }
+}
+
+impl Action for Exec {
+    type Output = usize;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let mut stmt = conn.prepare(&self.sql)?;
+        self.params.bind(&mut stmt)?;
+        stmt.raw_execute()
+    }
+}
+
+/// Execute an `INSERT` statement and return the rowid of the inserted row.
+#[derive(Debug, Clone)]
+pub struct Insert {
+    sql: String,
+    params: OwnedParams,
+}
+
+impl Insert {
+    pub fn new(sql: impl Into<String>, params: impl Into<OwnedParams>) -> Self {
+        Self {
+            sql: sql.into(),
+            params: params.into(),
+        }
+    }
+}
+
+impl Action for Insert {
+    type Output = i64;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let mut stmt = conn.prepare(&self.sql)?;
+        self.params.bind(&mut stmt)?;
+        stmt.raw_execute()?;
+        Ok(conn.last_insert_rowid())
+    }
+}
+
+/// Run a query like `SELECT COUNT(*) FROM ...` and return the integer in the
+/// first column of the first row.
+#[derive(Debug, Clone)]
+pub struct Count {
+    sql: String,
+    params: OwnedParams,
+}
+
+impl Count {
+    pub fn new(sql: impl Into<String>, params: impl Into<OwnedParams>) -> Self {
+        Self {
+            sql: sql.into(),
+            params: params.into(),
+        }
+    }
+}
+
+impl Action for Count {
+    type Output = u64;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let mut stmt = conn.prepare(&self.sql)?;
+        self.params.bind(&mut stmt)?;
+        let mut rows = stmt.raw_query();
+        let row = rows.next()?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        row.get(0)
+    }
+}
+
+/// Error returned by [`BulkInsert`].
+#[derive(Debug)]
+pub struct BulkInsertError {
+    /// The number of rows that were inserted and committed before the error
+    /// occurred.
+    pub committed: usize,
+    pub source: rusqlite::Error,
+}
+
+impl fmt::Display for BulkInsertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bulk insert failed after {} rows: {}",
+            self.committed, self.source
+        )
+    }
+}
+
+impl error::Error for BulkInsertError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Execute an `INSERT` statement once for every set of parameters and return
+/// the number of inserted rows.
+///
+/// The rows are inserted in chunks of `chunk_size` rows, each in its own
+/// transaction, which is much faster than inserting every row in a separate
+/// transaction. If a row fails, its chunk is rolled back and the action stops.
+/// All previous chunks stay committed, and their row count is reported in the
+/// [`BulkInsertError`].
+///
+/// The rows are pulled from their iterator lazily, one chunk at a time, so they
+/// never have to be held in memory all at once. The iterator runs on the
+/// vault's thread while the action is executed. A `chunk_size` of zero results
+/// in an error before any rows are inserted.
+///
+/// Since this action starts its own transactions, it fails if a transaction is
+/// already active on the connection.
+pub struct BulkInsert {
+    sql: String,
+    rows: Box<dyn Iterator<Item = rusqlite::Result<OwnedParams>> + Send>,
+    chunk_size: usize,
+}
+
+impl fmt::Debug for BulkInsert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BulkInsert")
+            .field("sql", &self.sql)
+            .field("chunk_size", &self.chunk_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BulkInsert {
+    /// Create a new action from rows of parameters.
+    pub fn new<I>(sql: impl Into<String>, rows: I, chunk_size: usize) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<OwnedParams>,
+        I::IntoIter: Send + 'static,
+    {
+        Self {
+            sql: sql.into(),
+            rows: Box::new(rows.into_iter().map(|row| Ok(row.into()))),
+            chunk_size,
+        }
+    }
+
+    /// Create a new action from rows that are serialized via
+    /// [`to_named_params`](crate::to_named_params).
+    ///
+    /// If a row can't be serialized, its chunk is rolled back like for any
+    /// other failing row.
+    #[cfg(feature = "serde")]
+    pub fn serialized<I>(sql: impl Into<String>, rows: I, chunk_size: usize) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Serialize,
+        I::IntoIter: Send + 'static,
+    {
+        Self {
+            sql: sql.into(),
+            rows: Box::new(rows.into_iter().map(|row| crate::to_named_params(&row))),
+            chunk_size,
+        }
+    }
+
+    /// Insert the next chunk of rows and return how many rows were inserted.
+    fn insert_chunk(&mut self, conn: &mut Connection) -> rusqlite::Result<usize> {
+        let mut rows = self.rows.by_ref().take(self.chunk_size).peekable();
+        if rows.peek().is_none() {
+            return Ok(0);
+        }
+
+        let mut inserted = 0;
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(&self.sql)?;
+            for params in rows {
+                params?.bind(&mut stmt)?;
+                stmt.raw_execute()?;
+                inserted += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(inserted)
+    }
+}
+
+impl Action for BulkInsert {
+    type Output = usize;
+    type Error = BulkInsertError;
+
+    fn run(mut self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        if self.chunk_size == 0 {
+            return Err(BulkInsertError {
+                committed: 0,
+                source: rusqlite::Error::SqliteFailure(
+                    rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_MISUSE),
+                    Some("chunk size must be greater than zero".to_string()),
+                ),
+            });
+        }
+
+        let mut committed = 0;
+        loop {
+            match self.insert_chunk(conn) {
+                Ok(0) => return Ok(committed),
+                Ok(inserted) => committed += inserted,
+                Err(source) => return Err(BulkInsertError { committed, source }),
+            }
+        }
+    }
+}
+
+/// Error returned by [`ChunkedDeleteAction`].
+#[derive(Debug)]
+pub struct ChunkedDeleteError {
+    /// The number of rows that were deleted and committed before the error
+    /// occurred.
+    pub committed: usize,
+    pub source: rusqlite::Error,
+}
+
+impl fmt::Display for ChunkedDeleteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "chunked delete failed after {} rows: {}",
+            self.committed, self.source
+        )
+    }
+}
+
+impl error::Error for ChunkedDeleteError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Delete all rows of a table matching a condition and return the number of
+/// deleted rows.
+///
+/// Instead of deleting all rows in a single statement, which holds the write
+/// lock for a long time when deleting many rows, the rows are deleted in chunks
+/// of at most `chunk_size` rows, each in its own transaction. Between chunks,
+/// other connections to the database get a chance to write. Actions queued on
+/// the same vault still have to wait until this action has finished. If a
+/// chunk fails, it is rolled back and the action stops. All previous chunks
+/// stay committed, and their row count is reported in the
+/// [`ChunkedDeleteError`].
+///
+/// The chunks are deleted via `DELETE FROM <table> WHERE rowid IN (SELECT rowid
+/// FROM <table> WHERE <condition> LIMIT <chunk_size>)`, so this doesn't require
+/// SQLite to be compiled with `SQLITE_ENABLE_UPDATE_DELETE_LIMIT`, but it
+/// doesn't work for `WITHOUT ROWID` tables. The condition is inserted into the
+/// statement as is and may use the parameters.
+///
+/// Since this action starts its own transactions, it fails if a transaction is
+/// already active on the connection.
+#[derive(Debug, Clone)]
+pub struct ChunkedDeleteAction {
+    table: String,
+    condition: String,
+    params: OwnedParams,
+    chunk_size: usize,
+}
+
+impl ChunkedDeleteAction {
+    /// Create a new action.
+    ///
+    /// A `chunk_size` of zero results in an error before any rows are deleted.
+    pub fn new(
+        table: impl Into<String>,
+        condition: impl Into<String>,
+        params: impl Into<OwnedParams>,
+        chunk_size: usize,
+    ) -> Self {
+        Self {
+            table: table.into(),
+            condition: condition.into(),
+            params: params.into(),
+            chunk_size,
+        }
+    }
+
+    fn delete_chunk(
+        conn: &mut Connection,
+        sql: &str,
+        params: &OwnedParams,
+    ) -> rusqlite::Result<usize> {
+        let tx = conn.transaction()?;
+        let deleted = {
+            let mut stmt = tx.prepare_cached(sql)?;
+            params.bind(&mut stmt)?;
+            stmt.raw_execute()?
+        };
+        tx.commit()?;
+        Ok(deleted)
+    }
+}
+
+impl Action for ChunkedDeleteAction {
+    type Output = usize;
+    type Error = ChunkedDeleteError;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        if self.chunk_size == 0 {
+            return Err(ChunkedDeleteError {
+                committed: 0,
+                source: rusqlite::Error::SqliteFailure(
+                    rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_MISUSE),
+                    Some("chunk size must be greater than zero".to_string()),
+                ),
+            });
+        }
+
+        let table = self.table.replace('"', "\"\"");
+        let sql = format!(
+            "
+            DELETE FROM \"{table}\" WHERE rowid IN (
+                SELECT rowid FROM \"{table}\" WHERE {} LIMIT {}
+            )
+            ",
+            self.condition, self.chunk_size
+        );
+
+        let mut committed = 0;
+        loop {
+            let deleted = Self::delete_chunk(conn, &sql, &self.params)
+                .map_err(|source| ChunkedDeleteError { committed, source })?;
+            committed += deleted;
+            if deleted < self.chunk_size {
+                return Ok(committed);
+            }
+        }
+    }
+}
+
+/// Attach an additional database to the vault's connection via `ATTACH
+/// DATABASE`.
+///
+/// Attached databases stay attached to the connection until they are detached
+/// again, which fits vaults that keep a single connection for their entire
+/// lifetime.
+///
+/// Each attached database has its own journal. In WAL mode, transactions
+/// spanning multiple databases are atomic for each individual database, but
+/// not across all of them. The journal mode of an attached database can be set
+/// via `PRAGMA <alias>.journal_mode`.
+#[derive(Debug, Clone)]
+pub struct AttachAction {
+    /// The file name or URI of the database to attach.
+    pub path: String,
+    /// The schema name under which the database will be available.
+    pub alias: String,
+}
+
+impl Action for AttachAction {
+    type Output = ();
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.execute("ATTACH DATABASE ? AS ?", (self.path, self.alias))?;
+        Ok(())
+    }
+}
+
+/// Detach a database previously attached via [`AttachAction`].
+#[derive(Debug, Clone)]
+pub struct DetachAction {
+    /// The schema name under which the database was attached.
+    pub alias: String,
+}
+
+impl Action for DetachAction {
+    type Output = ();
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.execute("DETACH DATABASE ?", (self.alias,))?;
+        Ok(())
+    }
+}
+
+/// Rebuild the database file via `VACUUM`, repacking it into a minimal amount
+/// of disk space.
+///
+/// `VACUUM` can't run inside a transaction, so this action fails if a
+/// transaction is active on the connection.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VacuumAction;
+
+impl Action for VacuumAction {
+    type Output = ();
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.execute_batch("VACUUM")
+    }
+}
+
+/// Update the query planner's statistics via `PRAGMA optimize`.
+///
+/// SQLite recommends running this periodically, or right before closing a
+/// connection. It is usually fast, since it only analyzes tables whose
+/// statistics are likely outdated.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OptimizeAction;
+
+impl Action for OptimizeAction {
+    type Output = ();
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.execute_batch("PRAGMA optimize")
+    }
+}
+
+/// A row of the `sqlite_stat1` table, as returned by [`AnalyzeAction`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnalyzeStat {
+    /// The name of the analyzed table.
+    pub table: String,
+    /// The name of the analyzed index, or [`None`] for rows describing the
+    /// table itself.
+    pub index: Option<String>,
+    /// The statistics, as a list of integers separated by spaces. The first
+    /// integer is the approximate number of rows in the table or index.
+    pub stat: String,
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for AnalyzeStat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("AnalyzeStat", 3)?;
+        s.serialize_field("table", &self.table)?;
+        s.serialize_field("index", &self.index)?;
+        s.serialize_field("stat", &self.stat)?;
+        s.end()
+    }
+}
+
+/// Gather statistics for the query planner via `ANALYZE`.
+///
+/// Unlike [`OptimizeAction`], this analyzes every table and index, which may
+/// take a while on large databases. If `return_stats` is set, the contents of
+/// `sqlite_stat1` are returned afterwards, which helps diagnosing bad query
+/// plans. Otherwise, an empty [`Vec`] is returned.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnalyzeAction {
+    pub return_stats: bool,
+}
+
+impl Action for AnalyzeAction {
+    type Output = Vec<AnalyzeStat>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.execute_batch("ANALYZE")?;
+        if !self.return_stats || !crate::table_exists(conn, "sqlite_stat1")? {
+            return Ok(vec![]);
+        }
+        conn.prepare("SELECT tbl, idx, stat FROM sqlite_stat1 ORDER BY tbl, idx")?
+            .query_map([], |r| {
+                Ok(AnalyzeStat {
+                    table: r.get(0)?,
+                    index: r.get(1)?,
+                    stat: r.get(2)?,
+                })
+            })?
+            .collect()
+    }
+}
+
+/// Read every user-defined table once to pull its pages into the page cache.
+///
+/// Running this right after launching a vault avoids slow first queries in
+/// latency-critical applications. Only as many pages as fit into the page cache
+/// stay cached, so its size should be increased first, for example via
+/// [`TokioVaultBuilder::with_cache_size`](crate::tokio::TokioVaultBuilder::with_cache_size).
+/// Indexes and virtual tables are not read.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WarmCacheAction;
+
+impl Action for WarmCacheAction {
+    type Output = ();
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let tables = conn
+            .prepare(
+                "
+                SELECT name FROM sqlite_schema
+                WHERE type = 'table'
+                AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\'
+                AND sql NOT LIKE 'CREATE VIRTUAL %'
+                ",
+            )?
+            .query_map([], |r| r.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for name in tables {
+            let name = name.replace('"', "\"\"");
+            let mut stmt = conn.prepare(&format!("SELECT * FROM \"{name}\""))?;
+            let mut rows = stmt.raw_query();
+            while rows.next()?.is_some() {}
+        }
+        Ok(())
+    }
+}
+
+/// Write a compacted copy of the database to a new file via `VACUUM INTO`.
+///
+/// The original database is left unchanged. The destination file must not
+/// exist yet or be empty. Like [`VacuumAction`], this can't run inside a
+/// transaction.
+#[derive(Debug, Clone)]
+pub struct VacuumIntoAction {
+    /// The file name or URI of the copy.
+    pub dest: String,
+}
+
+impl Action for VacuumIntoAction {
+    type Output = ();
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.execute("VACUUM INTO ?", (self.dest,))?;
+        Ok(())
+    }
+}
+
+/// Set the `application_id` in the database header via `PRAGMA
+/// application_id`.
+///
+/// The `application_id` identifies the file format of a database, similar to
+/// how `user_version` identifies its schema version.
+#[derive(Debug, Clone, Copy)]
+pub struct SetApplicationIdAction(pub i32);
+
+impl Action for SetApplicationIdAction {
+    type Output = ();
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.pragma_update(None, "application_id", self.0)
+    }
+}
+
+/// Read the `application_id` from the database header via `PRAGMA
+/// application_id`.
+///
+/// Databases whose `application_id` has never been set return `0`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GetApplicationIdAction;
+
+impl Action for GetApplicationIdAction {
+    type Output = i32;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.query_row("SELECT * FROM pragma_application_id", [], |r| r.get(0))
+    }
+}
+
+/// Drop all user-defined tables and views and reset the `user_version` to 0,
+/// so that all migrations run again the next time the vault is opened.
+///
+/// Indexes and triggers are dropped along with the tables and views they
+/// belong to, and the statistics tables created by `ANALYZE` are dropped as
+/// well. Afterwards, `sqlite_schema` is empty, except for `sqlite_sequence` if
+/// any table used `AUTOINCREMENT`. SQLite doesn't allow dropping it, but its
+/// rows are removed along with their tables, so it ends up empty. If
+/// `foreign_keys` is enabled, it is disabled while dropping and restored
+/// afterwards. All tables are dropped in a single transaction, so this action
+/// fails if a transaction is already active on the connection.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResetAction;
+
+impl ResetAction {
+    fn reset(conn: &mut Connection) -> rusqlite::Result<()> {
+        let tx = conn.transaction()?;
+
+        let objects = tx
+            .prepare(
+                "
+                SELECT type, name FROM sqlite_schema
+                WHERE type IN ('table', 'view')
+                AND (
+                    name NOT LIKE 'sqlite\\_%' ESCAPE '\\'
+                    OR name LIKE 'sqlite\\_stat%' ESCAPE '\\'
+                )
+                ORDER BY type = 'table'
+                ",
+            )?
+            .query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for (kind, name) in objects {
+            let kind = if kind == "view" { "VIEW" } else { "TABLE" };
+            let name = name.replace('"', "\"\"");
+            tx.execute_batch(&format!("DROP {kind} IF EXISTS \"{name}\""))?;
+        }
+
+        tx.pragma_update(None, "user_version", 0)?;
+        tx.commit()
+    }
+}
+
+impl Action for ResetAction {
+    type Output = ();
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let foreign_keys: bool =
+            conn.query_row("SELECT * FROM pragma_foreign_keys", [], |r| r.get(0))?;
+        if foreign_keys {
+            conn.pragma_update(None, "foreign_keys", false)?;
+        }
+
+        let result = Self::reset(conn);
+
+        if foreign_keys {
+            conn.pragma_update(None, "foreign_keys", true)?;
+        }
+        result
+    }
+}
+
+/// Define a struct implementing [`Action`] for a query with a fixed SQL
+/// statement.
+///
+/// The struct's fields are bound as positional parameters in the order they
+/// are declared, so their types must be convertible into
+/// [`rusqlite::types::Value`]. The resulting rows are deserialized via
+/// [`from_row_via_name`](crate::from_row_via_name), depending on the output
+/// type:
+/// - `Vec<T>` collects all rows like [`QueryAll`]
+/// - `Option<T>` returns the first row, if any, like [`QueryOpt`]
+/// - `T` requires exactly one row like [`QueryRowInto`](crate::QueryRowInto)
+///
+/// ```
+/// # use rusqlite::Connection;
+/// # use serde::Deserialize;
+/// # use vault::simple::SimpleVault;
+/// #[derive(Deserialize)]
+/// pub struct User {
+///     id: i64,
+///     name: String,
+/// }
+///
+/// #[derive(Deserialize)]
+/// pub struct UserStats {
+///     total: i64,
+/// }
+///
+/// vault::query_action! {
+///     /// Find all users with a given name.
+///     pub struct UsersByName { name: String } -> Vec<User>,
+///     "SELECT id, name FROM users WHERE name = ?"
+/// }
+///
+/// vault::query_action! {
+///     pub struct GetUserStats {} -> UserStats,
+///     "SELECT COUNT(*) AS total FROM users"
+/// }
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let conn = Connection::open_in_memory()?;
+/// # conn.execute_batch(
+/// #     "
+/// #     CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+/// #     INSERT INTO users (name) VALUES ('foo'), ('bar');
+/// #     ",
+/// # )?;
+/// # let mut vault = SimpleVault::new(conn, &[])?;
+/// let users = vault.execute(UsersByName { name: "foo".to_string() })?;
+/// let stats = vault.execute(GetUserStats {})?;
+/// # assert_eq!(users.len(), 1);
+/// # assert_eq!(stats.total, 2);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "serde")]
+#[macro_export]
+macro_rules! query_action {
+    (
+        @impl [$(#[$attr:meta])*] $vis:vis $name:ident { $($field:ident: $ty:ty),* }
+        $action:ty, $output:ty, $sql:expr
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $($vis $field: $ty),*
+        }
+
+        impl $crate::Action for $name {
+            type Output = $output;
+            type Error = $crate::__private::rusqlite::Error;
+
+            fn run(
+                self,
+                conn: &mut $crate::__private::rusqlite::Connection,
+            ) -> ::core::result::Result<Self::Output, Self::Error> {
+                let Self { $($field),* } = self;
+                #[allow(unused_mut)]
+                let mut params = $crate::OwnedParams::new();
+                $(params.push($field);)*
+                $crate::Action::run(<$action>::new($sql, params), conn)
+            }
+        }
+    };
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident { $($field:ident: $ty:ty),* $(,)? }
+        -> Vec<$out:ty>, $sql:expr $(,)?
+    ) => {
+        $crate::query_action! {
+            @impl [$(#[$attr])*] $vis $name { $($field: $ty),* }
+            $crate::actions::QueryAll<$out>, ::std::vec::Vec<$out>, $sql
+        }
+    };
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident { $($field:ident: $ty:ty),* $(,)? }
+        -> Option<$out:ty>, $sql:expr $(,)?
+    ) => {
+        $crate::query_action! {
+            @impl [$(#[$attr])*] $vis $name { $($field: $ty),* }
+            $crate::actions::QueryOpt<$out>, ::core::option::Option<$out>, $sql
+        }
+    };
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident { $($field:ident: $ty:ty),* $(,)? }
+        -> $out:ty, $sql:expr $(,)?
+    ) => {
+        $crate::query_action! {
+            @impl [$(#[$attr])*] $vis $name { $($field: $ty),* }
+            $crate::QueryRowInto<$out>, $out, $sql
+        }
+    };
+}
+
+/// Return the `CREATE` statements of all user-defined tables, views, indexes
+/// and triggers as a single string.
+///
+/// The statements are taken from `sqlite_schema` and each one is terminated by
+/// a `;` and a newline. Tables come first, followed by views, indexes and
+/// triggers, each in the order they were created in. Running the dump on an
+/// empty database recreates the schema, so it can be used to snapshot the
+/// schema after migrations and compare it against an expected one.
+///
+/// Internal objects like `sqlite_sequence` and automatically created indexes
+/// are left out. The shadow tables of virtual tables are included, so dumps of
+/// databases containing virtual tables can't be applied as is.
+///
+/// See also [`dump_schema`](crate::dump_schema) and
+/// [`verify_schema`](crate::verify_schema).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SchemaDumpAction;
+
+impl Action for SchemaDumpAction {
+    type Output = String;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        crate::dump_schema(conn)
+    }
+}
+
+/// Check whether a table with the given name exists.
+///
+/// See [`table_exists`](crate::table_exists) for more details.
+#[derive(Debug, Clone)]
+pub struct TableExistsAction(pub String);
+
+impl Action for TableExistsAction {
+    type Output = bool;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        crate::table_exists(conn, &self.0)
+    }
+}
+
+/// Check whether an index with the given name exists.
+///
+/// See [`index_exists`](crate::index_exists) for more details.
+#[derive(Debug, Clone)]
+pub struct IndexExistsAction(pub String);
+
+impl Action for IndexExistsAction {
+    type Output = bool;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        crate::index_exists(conn, &self.0)
+    }
+}
+
+/// The result of a [`HealthCheckAction`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthReport {
+    /// The database's current `user_version`, which is the number of applied
+    /// migrations.
+    pub user_version: usize,
+    /// The number of migrations known to the vault.
+    pub expected_version: usize,
+    /// Whether there are migrations that have not been applied yet.
+    pub migrations_pending: bool,
+    /// The rows returned by `PRAGMA quick_check`. A healthy database returns a
+    /// single row containing `ok`.
+    pub quick_check: Vec<String>,
+}
+
+impl HealthReport {
+    /// Whether the database is up to date and `PRAGMA quick_check` found no
+    /// problems.
+    pub fn is_healthy(&self) -> bool {
+        !self.migrations_pending && self.quick_check == ["ok"]
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for HealthReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("HealthReport", 5)?;
+        s.serialize_field("user_version", &self.user_version)?;
+        s.serialize_field("expected_version", &self.expected_version)?;
+        s.serialize_field("migrations_pending", &self.migrations_pending)?;
+        s.serialize_field("quick_check", &self.quick_check)?;
+        s.serialize_field("healthy", &self.is_healthy())?;
+        s.end()
+    }
+}
+
+/// Check the schema version and integrity of the database via `PRAGMA
+/// user_version` and `PRAGMA quick_check`.
+///
+/// `PRAGMA quick_check` reads the entire database, so this action may take a
+/// while on large databases.
+#[derive(Debug, Clone, Copy)]
+pub struct HealthCheckAction {
+    /// The number of migrations known to the vault.
+    pub expected_version: usize,
+}
+
+impl Action for HealthCheckAction {
+    type Output = HealthReport;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let user_version = crate::applied_migration_count(conn)?;
+        let quick_check = conn
+            .prepare("PRAGMA quick_check")?
+            .query_map([], |r| r.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(HealthReport {
+            user_version,
+            expected_version: self.expected_version,
+            migrations_pending: user_version < self.expected_version,
+            quick_check,
+        })
+    }
+}
+
+/// Return the number of migrations that have been applied to the database via
+/// [`applied_migration_count`](crate::applied_migration_count).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AppliedMigrationCount;
+
+impl Action for AppliedMigrationCount {
+    type Output = usize;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        crate::applied_migration_count(conn)
+    }
+}
+
+/// The result of a [`DbStatsAction`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DbStats {
+    /// The number of pages in the database file, from `PRAGMA page_count`.
+    pub page_count: u64,
+    /// The size of a page in bytes, from `PRAGMA page_size`.
+    pub page_size: u64,
+    /// The number of unused pages in the database file, from `PRAGMA
+    /// freelist_count`. These can be reclaimed via [`VacuumAction`].
+    pub freelist_count: u64,
+}
+
+impl DbStats {
+    /// The size of the database in bytes.
+    pub fn total_bytes(&self) -> u64 {
+        self.page_count * self.page_size
+    }
+
+    /// The size of the unused pages in bytes.
+    pub fn free_bytes(&self) -> u64 {
+        self.freelist_count * self.page_size
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for DbStats {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("DbStats", 5)?;
+        s.serialize_field("page_count", &self.page_count)?;
+        s.serialize_field("page_size", &self.page_size)?;
+        s.serialize_field("freelist_count", &self.freelist_count)?;
+        s.serialize_field("total_bytes", &self.total_bytes())?;
+        s.serialize_field("free_bytes", &self.free_bytes())?;
+        s.end()
+    }
+}
+
+/// Read the size of the database via `PRAGMA page_count`, `PRAGMA page_size`
+/// and `PRAGMA freelist_count`.
+///
+/// Only the main database is inspected. The size doesn't include the WAL file,
+/// if any.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DbStatsAction;
+
+impl Action for DbStatsAction {
+    type Output = DbStats;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let pragma = |name| conn.pragma_query_value(None, name, |r| r.get::<_, u64>(0));
+        Ok(DbStats {
+            page_count: pragma("page_count")?,
+            page_size: pragma("page_size")?,
+            freelist_count: pragma("freelist_count")?,
+        })
+    }
+}
+
+/// A single step of a query plan returned by [`ExplainAction`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryPlanStep {
+    pub id: i64,
+    /// The id of the step this step is nested in, or 0 for top-level steps.
+    pub parent: i64,
+    /// A human-readable description of the step, for example `SCAN users` or
+    /// `SEARCH users USING INDEX users_name (name=?)`.
+    pub detail: String,
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for QueryPlanStep {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("QueryPlanStep", 3)?;
+        s.serialize_field("id", &self.id)?;
+        s.serialize_field("parent", &self.parent)?;
+        s.serialize_field("detail", &self.detail)?;
+        s.end()
+    }
+}
+
+/// Run `EXPLAIN QUERY PLAN` for a statement and return the steps of its query
+/// plan.
+///
+/// The statement itself is not executed. Its parameters don't need to be
+/// bound, since they don't affect the query plan.
+#[derive(Debug, Clone)]
+pub struct ExplainAction {
+    pub sql: String,
+}
+
+impl Action for ExplainAction {
+    type Output = Vec<QueryPlanStep>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", self.sql))?;
+        let mut rows = stmt.raw_query();
+        let mut steps = vec![];
+        while let Some(row) = rows.next()? {
+            steps.push(QueryPlanStep {
+                id: row.get("id")?,
+                parent: row.get("parent")?,
+                detail: row.get("detail")?,
+            });
+        }
+        Ok(steps)
+    }
+}
+
+/// Wrap an [`Action`] and measure how long it takes to run.
+///
+/// The duration is measured on the thread the action runs on, so it only
+/// includes the time spent in [`Action::run`], not the time spent waiting for
+/// the vault.
+#[derive(Debug, Clone)]
+pub struct Timed<A>(pub A);
+
+impl<A: Action> Action for Timed<A> {
+    type Output = (A::Output, Duration);
+    type Error = A::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let start = Instant::now();
+        let output = self.0.run(conn)?;
+        Ok((output, start.elapsed()))
+    }
+
+    fn name(&self) -> &'static str {
+        self.0.name()
+    }
+}
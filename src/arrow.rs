@@ -0,0 +1,173 @@
+//! Reading query results into Arrow record batches.
+
+use std::{error, fmt, sync::Arc};
+
+use arrow_array::{
+    ArrayRef, BinaryArray, Float64Array, Int64Array, NullArray, RecordBatch, RecordBatchOptions,
+    StringArray,
+};
+use arrow_schema::{DataType, Field, Schema};
+use rusqlite::{
+    types::{Type, Value},
+    Rows,
+};
+
+/// Error that can occur while reading rows into a [`RecordBatch`].
+#[derive(Debug)]
+pub enum ArrowError {
+    /// A value doesn't match the Arrow type of its column.
+    TypeMismatch {
+        column: String,
+        expected: DataType,
+        actual: Type,
+    },
+    /// Building the record batch failed.
+    Arrow(arrow_schema::ArrowError),
+    /// Reading the rows failed.
+    Rusqlite(rusqlite::Error),
+}
+
+impl fmt::Display for ArrowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TypeMismatch {
+                column,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "column {column:?} of type {expected} contains value of type {actual}"
+            ),
+            Self::Arrow(err) => err.fmt(f),
+            Self::Rusqlite(err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for ArrowError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::TypeMismatch { .. } => None,
+            Self::Arrow(err) => err.source(),
+            Self::Rusqlite(err) => err.source(),
+        }
+    }
+}
+
+impl From<arrow_schema::ArrowError> for ArrowError {
+    fn from(value: arrow_schema::ArrowError) -> Self {
+        Self::Arrow(value)
+    }
+}
+
+impl From<rusqlite::Error> for ArrowError {
+    fn from(value: rusqlite::Error) -> Self {
+        Self::Rusqlite(value)
+    }
+}
+
+/// The Arrow type corresponding to a column's type.
+fn data_type(ty: Type) -> DataType {
+    match ty {
+        Type::Null => DataType::Null,
+        Type::Integer => DataType::Int64,
+        Type::Real => DataType::Float64,
+        Type::Text => DataType::Utf8,
+        Type::Blob => DataType::Binary,
+    }
+}
+
+fn values_to_array(
+    name: &str,
+    data_type: &DataType,
+    values: Vec<Value>,
+) -> Result<ArrayRef, ArrowError> {
+    let mismatch = |value: Value| ArrowError::TypeMismatch {
+        column: name.to_string(),
+        expected: data_type.clone(),
+        actual: value.data_type(),
+    };
+
+    let array: ArrayRef = match data_type {
+        DataType::Int64 => Arc::new(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Null => Ok(None),
+                    Value::Integer(v) => Ok(Some(v)),
+                    value => Err(mismatch(value)),
+                })
+                .collect::<Result<Int64Array, _>>()?,
+        ),
+        DataType::Float64 => Arc::new(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Null => Ok(None),
+                    Value::Integer(v) => Ok(Some(v as f64)),
+                    Value::Real(v) => Ok(Some(v)),
+                    value => Err(mismatch(value)),
+                })
+                .collect::<Result<Float64Array, _>>()?,
+        ),
+        DataType::Utf8 => Arc::new(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Null => Ok(None),
+                    Value::Text(v) => Ok(Some(v)),
+                    value => Err(mismatch(value)),
+                })
+                .collect::<Result<StringArray, _>>()?,
+        ),
+        DataType::Binary => Arc::new(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Null => Ok(None),
+                    Value::Blob(v) => Ok(Some(v)),
+                    value => Err(mismatch(value)),
+                })
+                .collect::<Result<BinaryArray, _>>()?,
+        ),
+        _ => Arc::new(NullArray::new(values.len())),
+    };
+    Ok(array)
+}
+
+/// Read the result of a query into a single [`RecordBatch`].
+///
+/// Since SQLite is dynamically typed, the Arrow type of each column has to be
+/// determined from its contents. If the column has a declared type, the type
+/// is determined using SQLite's rules for column affinity:
+///
+/// - `INTEGER` affinity becomes [`DataType::Int64`]
+/// - `TEXT` affinity becomes [`DataType::Utf8`]
+/// - a declared type containing `BLOB` becomes [`DataType::Binary`]
+/// - `REAL` affinity becomes [`DataType::Float64`]
+///
+/// Otherwise, for example for `NUMERIC` columns or expressions, the type is
+/// inferred from the first non-`NULL` value in the column, except that a column
+/// containing both integers and reals becomes [`DataType::Float64`]. Columns
+/// containing only `NULL`s become [`DataType::Null`].
+///
+/// All fields are nullable. Integers are accepted in [`DataType::Float64`]
+/// columns, but any other value not matching its column's type results in an
+/// [`ArrowError::TypeMismatch`].
+///
+/// All rows are kept in memory until the batch is built.
+pub fn rows_to_record_batch(rows: Rows<'_>) -> Result<RecordBatch, ArrowError> {
+    let (columns, row_count) = crate::columnar::read_typed_columns(rows)?;
+
+    let mut fields = vec![];
+    let mut arrays = vec![];
+    for column in columns {
+        let data_type = data_type(column.ty);
+        arrays.push(values_to_array(&column.name, &data_type, column.values)?);
+        fields.push(Field::new(column.name, data_type, true));
+    }
+
+    let options = RecordBatchOptions::new().with_row_count(Some(row_count));
+    let batch = RecordBatch::try_new_with_options(Arc::new(Schema::new(fields)), arrays, &options)?;
+    Ok(batch)
+}
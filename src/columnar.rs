@@ -0,0 +1,95 @@
+//! Reading query results column by column, for exporting them to columnar
+//! formats.
+
+use rusqlite::{
+    types::{Type, Value},
+    Rows,
+};
+
+/// A column of a query result, along with the type determined for it.
+pub(crate) struct TypedColumn {
+    pub(crate) name: String,
+    pub(crate) ty: Type,
+    pub(crate) values: Vec<Value>,
+}
+
+/// Determine the type of a column from its declared type, following SQLite's
+/// rules for determining column affinity.
+///
+/// Returns [`None`] for columns with `NUMERIC` affinity, since they can contain
+/// both integers and reals.
+fn declared_type(decl_type: &str) -> Option<Type> {
+    let decl_type = decl_type.to_ascii_uppercase();
+    let contains_any = |needles: &[&str]| needles.iter().any(|n| decl_type.contains(n));
+    if contains_any(&["INT"]) {
+        Some(Type::Integer)
+    } else if contains_any(&["CHAR", "CLOB", "TEXT"]) {
+        Some(Type::Text)
+    } else if contains_any(&["BLOB"]) {
+        Some(Type::Blob)
+    } else if contains_any(&["REAL", "FLOA", "DOUB"]) {
+        Some(Type::Real)
+    } else {
+        None
+    }
+}
+
+/// Determine the type of a column from its values.
+///
+/// The type is determined by the first non-`NULL` value. Integer columns that
+/// also contain reals become [`Type::Real`].
+fn inferred_type(values: &[Value]) -> Type {
+    let mut ty = Type::Null;
+    for value in values {
+        match (value.data_type(), ty) {
+            (Type::Null, _) => {}
+            (Type::Real, Type::Integer) => ty = Type::Real,
+            (value_ty, Type::Null) => ty = value_ty,
+            _ => {}
+        }
+    }
+    ty
+}
+
+/// Read all rows of a query and determine the type of each column.
+///
+/// If a column has a declared type, its type is determined using SQLite's
+/// rules for column affinity. Otherwise, for example for `NUMERIC` columns or
+/// expressions, the type is inferred from the first non-`NULL` value in the
+/// column, except that a column containing both integers and reals becomes
+/// [`Type::Real`]. Columns containing only `NULL`s become [`Type::Null`].
+///
+/// The values are returned as they are, so they may not match the type of
+/// their column. Also returns the number of rows read.
+pub(crate) fn read_typed_columns(
+    mut rows: Rows<'_>,
+) -> rusqlite::Result<(Vec<TypedColumn>, usize)> {
+    let columns = match rows.as_ref() {
+        Some(stmt) => stmt
+            .columns()
+            .into_iter()
+            .map(|c| (c.name().to_string(), c.decl_type().and_then(declared_type)))
+            .collect::<Vec<_>>(),
+        None => vec![],
+    };
+
+    let mut values = vec![vec![]; columns.len()];
+    let mut row_count = 0;
+    while let Some(row) = rows.next()? {
+        for (i, column) in values.iter_mut().enumerate() {
+            column.push(row.get::<_, Value>(i)?);
+        }
+        row_count += 1;
+    }
+
+    let columns = columns
+        .into_iter()
+        .zip(values)
+        .map(|((name, ty), values)| TypedColumn {
+            name,
+            ty: ty.unwrap_or_else(|| inferred_type(&values)),
+            values,
+        })
+        .collect();
+    Ok((columns, row_count))
+}
@@ -0,0 +1,89 @@
+//! Exporting query results as CSV.
+
+use std::{error, fmt, io};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use csv::Writer;
+use rusqlite::{types::ValueRef, Rows};
+
+/// Error that can occur while writing CSV.
+#[derive(Debug)]
+pub enum CsvError {
+    /// Writing the CSV failed.
+    Csv(csv::Error),
+    /// Reading the rows failed.
+    Rusqlite(rusqlite::Error),
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Csv(err) => err.fmt(f),
+            Self::Rusqlite(err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for CsvError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Csv(err) => err.source(),
+            Self::Rusqlite(err) => err.source(),
+        }
+    }
+}
+
+impl From<csv::Error> for CsvError {
+    fn from(value: csv::Error) -> Self {
+        Self::Csv(value)
+    }
+}
+
+impl From<rusqlite::Error> for CsvError {
+    fn from(value: rusqlite::Error) -> Self {
+        Self::Rusqlite(value)
+    }
+}
+
+fn value_to_field(value: ValueRef<'_>) -> Vec<u8> {
+    match value {
+        ValueRef::Null => vec![],
+        ValueRef::Integer(v) => v.to_string().into_bytes(),
+        ValueRef::Real(v) => v.to_string().into_bytes(),
+        ValueRef::Text(v) => v.to_vec(),
+        ValueRef::Blob(v) => STANDARD.encode(v).into_bytes(),
+    }
+}
+
+/// Write the result of a query as CSV and return the number of rows written.
+///
+/// The first line is a header containing the column names. It is written even
+/// if there are no rows. The individual values are written as follows:
+///
+/// - `NULL` is written as an empty field
+/// - `INTEGER` and `REAL` are written as numbers
+/// - `TEXT` is written as-is
+/// - `BLOB` is written as the standard base64 encoding (with padding) of its
+///   bytes
+///
+/// Fields are quoted where necessary.
+pub fn rows_to_csv<W: io::Write>(mut rows: Rows<'_>, writer: W) -> Result<usize, CsvError> {
+    let mut writer = Writer::from_writer(writer);
+
+    if let Some(stmt) = rows.as_ref() {
+        writer.write_record(stmt.column_names())?;
+    }
+
+    let mut count = 0;
+    while let Some(row) = rows.next()? {
+        let stmt = row.as_ref();
+        for i in 0..stmt.column_count() {
+            writer.write_field(value_to_field(row.get_ref(i)?))?;
+        }
+        writer.write_record(None::<&[u8]>)?;
+        count += 1;
+    }
+
+    writer.flush().map_err(csv::Error::from)?;
+    Ok(count)
+}
@@ -0,0 +1,95 @@
+//! Reading rows whose columns aren't known at compile time.
+
+use std::collections::HashMap;
+
+use rusqlite::{
+    types::{FromSql, FromSqlResult, Value, ValueRef},
+    Row,
+};
+
+/// Read every column of a row into a map from column name to value.
+///
+/// Unlike the serde-based functions, this doesn't require knowing the columns
+/// in advance, which makes it useful for generic tooling like exporters or
+/// admin interfaces. If multiple columns share the same name, the last one
+/// wins.
+pub fn from_row_dynamic(row: &Row<'_>) -> rusqlite::Result<HashMap<String, Value>> {
+    let stmt = row.as_ref();
+    let mut result = HashMap::with_capacity(stmt.column_count());
+    for i in 0..stmt.column_count() {
+        let name = stmt.column_name(i)?.to_string();
+        let value = row.get::<_, Value>(i)?;
+        result.insert(name, value);
+    }
+    Ok(result)
+}
+
+/// A row read via [`from_row_dynamic`] with typed getters for its columns.
+///
+/// The getters return [`None`] if the column is missing, `NULL` or of a
+/// different type. Use [`DynamicRow::try_get`] to distinguish type mismatches
+/// from missing values.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DynamicRow(HashMap<String, Value>);
+
+impl DynamicRow {
+    /// Read every column of a row. See [`from_row_dynamic`] for more details.
+    pub fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        from_row_dynamic(row).map(Self)
+    }
+
+    /// The raw value of a column, if it exists.
+    pub fn get(&self, col: &str) -> Option<&Value> {
+        self.0.get(col)
+    }
+
+    /// Convert the value of a column via [`FromSql`].
+    ///
+    /// Returns [`None`] if the column is missing or `NULL`, and an error if the
+    /// value can't be converted.
+    pub fn try_get<T: FromSql>(&self, col: &str) -> FromSqlResult<Option<T>> {
+        match self.0.get(col) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => T::column_result(ValueRef::from(value)).map(Some),
+        }
+    }
+
+    pub fn get_i64(&self, col: &str) -> Option<i64> {
+        match self.0.get(col)? {
+            Value::Integer(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn get_f64(&self, col: &str) -> Option<f64> {
+        match self.0.get(col)? {
+            Value::Real(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn get_str(&self, col: &str) -> Option<&str> {
+        match self.0.get(col)? {
+            Value::Text(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn get_blob(&self, col: &str) -> Option<&[u8]> {
+        match self.0.get(col)? {
+            Value::Blob(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Return the underlying map from column name to value.
+    pub fn into_map(self) -> HashMap<String, Value> {
+        self.0
+    }
+}
+
+impl From<HashMap<String, Value>> for DynamicRow {
+    fn from(value: HashMap<String, Value>) -> Self {
+        Self(value)
+    }
+}
@@ -0,0 +1,96 @@
+//! Converting rows to JSON.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rusqlite::{
+    types::{Type, ValueRef},
+    Row,
+};
+use serde_json::{Map, Number, Value};
+
+/// Convert a single SQLite value to a JSON value.
+///
+/// - `NULL` is converted to `null`
+/// - `INTEGER` is converted to a number
+/// - `REAL` is converted to a number, or `null` if it is not finite
+/// - `TEXT` is converted to a string
+/// - `BLOB` is converted to a string containing the standard base64 encoding
+///   (with padding) of its bytes
+pub fn value_to_json(value: ValueRef<'_>) -> rusqlite::Result<Value> {
+    Ok(match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(v) => Value::Number(v.into()),
+        ValueRef::Real(v) => Number::from_f64(v).map_or(Value::Null, Value::Number),
+        ValueRef::Text(_) => Value::String(value.as_str()?.to_string()),
+        ValueRef::Blob(v) => Value::String(STANDARD.encode(v)),
+    })
+}
+
+/// Convert a row to a JSON object mapping column names to values.
+///
+/// See [`value_to_json`] for how the individual values are converted. If
+/// multiple columns share the same name, the last one wins.
+pub fn row_to_json(row: &Row<'_>) -> rusqlite::Result<Value> {
+    let stmt = row.as_ref();
+    let mut result = Map::new();
+    for i in 0..stmt.column_count() {
+        let name = stmt.column_name(i)?.to_string();
+        let value = value_to_json(row.get_ref(i)?)?;
+        result.insert(name, value);
+    }
+    Ok(Value::Object(result))
+}
+
+/// A row returned by the `json_each` and `json_tree` table-valued functions.
+///
+/// See [`json_each_row`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonEachRow {
+    /// The array index or object key of the element, or `null` for the root.
+    pub key: Value,
+    /// The element itself.
+    pub value: Value,
+    /// The JSON type of the element, for example `"integer"` or `"object"`.
+    pub json_type: String,
+    /// The id of the element, which is unique within a query.
+    pub id: i64,
+    /// The id of the element's parent, if any.
+    pub parent: Option<i64>,
+    /// The path from the root to the element.
+    pub fullkey: String,
+    /// The path from the root to the element's parent.
+    pub path: String,
+}
+
+/// Read a row returned by `SELECT * FROM json_each(...)` or `SELECT * FROM
+/// json_tree(...)`.
+///
+/// The `value` column contains values of different SQLite types depending on
+/// the element's JSON type. This function uses the `type` column to convert it
+/// back to the original JSON value: `true` and `false` become booleans, and
+/// objects and arrays are parsed from their JSON text. All other values are
+/// converted via [`value_to_json`].
+pub fn json_each_row(row: &Row<'_>) -> rusqlite::Result<JsonEachRow> {
+    let json_type: String = row.get("type")?;
+    let value_index = row.as_ref().column_index("value")?;
+    let value = match json_type.as_str() {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "object" | "array" => {
+            let text = row.get_ref(value_index)?.as_str()?;
+            serde_json::from_str(text).map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(value_index, Type::Text, Box::new(err))
+            })?
+        }
+        _ => value_to_json(row.get_ref(value_index)?)?,
+    };
+
+    Ok(JsonEachRow {
+        key: value_to_json(row.get_ref("key")?)?,
+        value,
+        json_type,
+        id: row.get("id")?,
+        parent: row.get("parent")?,
+        fullkey: row.get("fullkey")?,
+        path: row.get("path")?,
+    })
+}
@@ -1,4 +1,6 @@
-#![forbid(unsafe_code)]
+#![cfg_attr(not(feature = "load_extension"), forbid(unsafe_code))]
+// Loading extensions is inherently unsafe, so it is allowed in that one place.
+#![cfg_attr(feature = "load_extension", deny(unsafe_code))]
 // Rustc lint groups
 #![warn(future_incompatible)]
 #![warn(rust_2018_idioms)]
@@ -9,17 +11,100 @@
 // Clippy lints
 #![warn(clippy::use_self)]
 
+// Logging macros that compile to nothing unless the `log` feature is enabled.
+// The arguments are still type-checked so that variables only used for logging
+// don't cause unused warnings.
+
+#[cfg(feature = "log")]
+macro_rules! info {
+    ($($arg:tt)*) => { log::info!($($arg)*) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "log")]
+macro_rules! warn {
+    ($($arg:tt)*) => { log::warn!($($arg)*) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "log")]
+macro_rules! debug {
+    ($($arg:tt)*) => { log::debug!($($arg)*) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+pub mod actions;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(any(feature = "arrow", feature = "polars"))]
+mod columnar;
+#[cfg(feature = "csv")]
+pub mod csv;
+pub mod dynamic;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod params;
+#[cfg(feature = "polars")]
+pub mod polars;
+#[cfg(feature = "r2d2")]
+pub mod r2d2;
+pub mod schema;
 #[cfg(feature = "serde")]
 pub mod serde;
 pub mod simple;
+#[cfg(feature = "testing")]
+pub mod testing;
 #[cfg(feature = "tokio")]
 pub mod tokio;
 
-use rusqlite::{Connection, Transaction};
+use std::{error, fmt, future::Future, ops::Range};
+
+use rusqlite::{Connection, OpenFlags, Transaction};
 
+#[cfg(feature = "arrow")]
+pub use self::arrow::*;
+#[cfg(feature = "csv")]
+pub use self::csv::*;
+pub use self::dynamic::*;
+#[cfg(feature = "json")]
+pub use self::json::*;
+pub use self::params::*;
+#[cfg(feature = "polars")]
+pub use self::polars::*;
+pub use self::schema::*;
 #[cfg(feature = "serde")]
 pub use self::serde::*;
 
+/// Re-exports used by macros.
+#[doc(hidden)]
+pub mod __private {
+    pub use rusqlite;
+}
+
 /// An action that can be performed on a [`Connection`].
 ///
 /// Both commands and queries are considered actions. Commands usually have a
@@ -31,6 +116,45 @@ pub trait Action {
     type Output;
     type Error;
     fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error>;
+
+    /// A human-readable name for the action, used for example in log messages.
+    ///
+    /// Defaults to the name of the action's type.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// A vault that executes [`Action`]s synchronously.
+///
+/// This allows writing code that is generic over the synchronous vaults. The
+/// vaults differ in how they report errors, so the error type is a wrapper
+/// around the [`Action`]'s error, which may be the error itself.
+pub trait SyncVault {
+    type Error<E>;
+
+    /// Execute an [`Action`] and return the result.
+    fn execute<A: Action>(&mut self, action: A) -> Result<A::Output, Self::Error<A::Error>>;
+}
+
+/// A vault that executes [`Action`]s asynchronously, usually on another
+/// thread.
+///
+/// This is the asynchronous counterpart to [`SyncVault`]. Since the
+/// [`Action`]s may be sent to another thread, they and their results must be
+/// [`Send`] and `'static`.
+pub trait AsyncVault {
+    type Error<E>;
+
+    /// Execute an [`Action`] and return the result.
+    fn execute<A>(
+        &self,
+        action: A,
+    ) -> impl Future<Output = Result<A::Output, Self::Error<A::Error>>> + Send
+    where
+        A: Action + Send + 'static,
+        A::Output: Send,
+        A::Error: Send;
 }
 
 /// A single database migration.
@@ -47,18 +171,348 @@ pub trait Action {
 /// transaction.
 pub type Migration = fn(&mut Transaction<'_>, usize, usize) -> rusqlite::Result<()>;
 
-fn migrate(conn: &mut Connection, migrations: &[Migration]) -> rusqlite::Result<()> {
-    let mut tx = conn.transaction()?;
+/// A single database migration that runs outside of a transaction.
+///
+/// Some operations like `VACUUM` or changing certain pragmas can't be performed
+/// inside a transaction. Migrations like these can be mixed with regular
+/// [`Migration`]s via [`MigrationStep`].
+///
+/// It receives the [`Connection`], its index in the migration array and the
+/// size of the migration array.
+pub type NonTransactionalMigration = fn(&mut Connection, usize, usize) -> rusqlite::Result<()>;
+
+/// A single step in a sequence of transactional and non-transactional
+/// migrations.
+///
+/// Consecutive [`MigrationStep::Transactional`] migrations share a single
+/// transaction, just like regular [`Migration`]s do. Before a
+/// [`MigrationStep::NonTransactional`] migration, the current transaction is
+/// committed, and afterwards, a new one is started. The `user_version` is
+/// updated after every non-transactional migration and at the end of every
+/// transaction.
+///
+/// Because of this, a failing migration can no longer roll back all migrations.
+/// Instead, only the migrations since the last non-transactional migration are
+/// rolled back, and the `user_version` reflects the migrations that have been
+/// applied successfully. The error of the failing migration is returned, and
+/// the next attempt continues from where the previous one stopped. A failing
+/// non-transactional migration may leave the database partially migrated, so
+/// these should be kept as small as possible.
+#[derive(Debug, Clone, Copy)]
+pub enum MigrationStep {
+    Transactional(Migration),
+    NonTransactional(NonTransactionalMigration),
+}
+
+/// Combines the migrations of multiple modules into a single migration array.
+///
+/// Since the `user_version` is a single counter for the entire database, the
+/// migrations of all modules must end up in one array with a fixed order. The
+/// modules' migrations are concatenated in the order they were registered via
+/// [`MigrationSet::extend`]. Each module is identified by a unique name.
+///
+/// Once a database has been migrated, the index of every migration is fixed.
+/// New migrations may only be added to the module that was registered last,
+/// and new modules may only be registered after all existing ones. Otherwise,
+/// the indices of existing migrations shift and migrations are skipped or
+/// applied twice.
+#[derive(Debug, Clone, Default)]
+pub struct MigrationSet {
+    sources: Vec<(&'static str, Vec<Migration>)>,
+}
+
+impl MigrationSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the migrations of a module.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a module with the same name has already been registered,
+    /// which would result in its migrations being applied twice.
+    pub fn extend(mut self, name: &'static str, migrations: &[Migration]) -> Self {
+        assert!(
+            self.sources.iter().all(|(n, _)| *n != name),
+            "migrations of {name:?} registered twice"
+        );
+        self.sources.push((name, migrations.to_vec()));
+        self
+    }
+
+    /// The indices of a module's migrations in the combined migration array.
+    ///
+    /// Useful for finding out which module a failing migration belongs to,
+    /// see [`MigrateError::Migration`].
+    pub fn range(&self, name: &str) -> Option<Range<usize>> {
+        let mut start = 0;
+        for (n, migrations) in &self.sources {
+            let end = start + migrations.len();
+            if *n == name {
+                return Some(start..end);
+            }
+            start = end;
+        }
+        None
+    }
+
+    /// The combined migration array.
+    pub fn build(self) -> Vec<Migration> {
+        self.sources
+            .into_iter()
+            .flat_map(|(_, migrations)| migrations)
+            .collect()
+    }
+}
+
+/// A function verifying that the schema of a partially migrated database
+/// matches its `user_version`.
+///
+/// It receives the [`Connection`] and the database's `user_version`, which is
+/// the number of migrations that have supposedly been applied. It is only
+/// called if some, but not all migrations have been applied, right before the
+/// remaining migrations are applied. If it returns an error, no migrations are
+/// applied.
+///
+/// This helps detect databases left in an inconsistent state, for example by a
+/// migration that committed a transaction on its own.
+pub type MigrationVerifier = fn(&Connection, usize) -> rusqlite::Result<()>;
+
+/// Error that can occur while opening a vault and applying its migrations.
+#[derive(Debug)]
+pub enum MigrateError {
+    /// A migration returned an error.
+    Migration {
+        /// The index of the failing migration in the migration array.
+        index: usize,
+        source: rusqlite::Error,
+    },
+    /// The [`MigrationVerifier`] rejected the database's schema.
+    Verification {
+        /// The database's `user_version` at the time of verification.
+        version: usize,
+        source: rusqlite::Error,
+    },
+    /// The database's `application_id` doesn't match the configured one, so
+    /// the database most likely belongs to a different application.
+    ApplicationIdMismatch { expected: i32, actual: i32 },
+    /// The vault was launched outside of a tokio runtime, but needs one to
+    /// spawn its periodic tasks.
+    #[cfg(feature = "periodic")]
+    NoRuntime,
+    /// Some other database operation failed.
+    Rusqlite(rusqlite::Error),
+}
+
+impl fmt::Display for MigrateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Migration { index, source } => write!(f, "migration {index} failed: {source}"),
+            Self::Verification { version, source } => {
+                write!(
+                    f,
+                    "schema verification at version {version} failed: {source}"
+                )
+            }
+            Self::ApplicationIdMismatch { expected, actual } => write!(
+                f,
+                "database has application_id {actual}, expected {expected}"
+            ),
+            #[cfg(feature = "periodic")]
+            Self::NoRuntime => write!(f, "periodic tasks require a tokio runtime"),
+            Self::Rusqlite(err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for MigrateError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Migration { source, .. } => Some(source),
+            Self::Verification { source, .. } => Some(source),
+            Self::ApplicationIdMismatch { .. } => None,
+            #[cfg(feature = "periodic")]
+            Self::NoRuntime => None,
+            Self::Rusqlite(err) => err.source(),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for MigrateError {
+    fn from(value: rusqlite::Error) -> Self {
+        Self::Rusqlite(value)
+    }
+}
+
+/// Set the key of an encrypted database and overwrite the key material
+/// afterwards.
+///
+/// This must happen before any other statement is run on the connection.
+#[cfg(feature = "sqlcipher")]
+fn apply_key(conn: &Connection, key: String) -> rusqlite::Result<()> {
+    let result = conn.pragma_update(None, "key", &key);
+
+    // The SQL statement built by rusqlite still contains a copy of the key, but
+    // we can at least clear our own copy.
+    let mut key = key.into_bytes();
+    key.fill(0);
+    std::hint::black_box(&key);
+
+    result
+}
+
+/// Open a named in-memory database that can be shared between connections.
+///
+/// Unlike [`Connection::open_in_memory`], which always creates a new private
+/// database, all connections opened with the same `name` in the same process
+/// see the same database. This is done via the URI
+/// `file:<name>?mode=memory&cache=shared`, so the connections use SQLite's
+/// [shared cache mode](https://www.sqlite.org/sharedcache.html), which comes
+/// with some caveats:
+///
+/// - The database only exists while at least one connection to it is open. Once
+///   the last connection is closed, its contents are lost.
+/// - Locking happens per table instead of per database. Conflicting accesses
+///   fail immediately with `SQLITE_LOCKED` instead of `SQLITE_BUSY`, so the
+///   busy timeout doesn't help.
+/// - Connections in the same process share a single page cache, and
+///   `PRAGMA read_uncommitted` allows them to read uncommitted changes.
+///
+/// Characters with a special meaning in URIs are escaped, so `name` can be any
+/// string.
+pub fn open_shared_memory(name: &str) -> rusqlite::Result<Connection> {
+    let mut uri = String::from("file:");
+    for c in name.chars() {
+        match c {
+            '%' | '?' | '#' => uri.push_str(&format!("%{:02X}", c as u32)),
+            c => uri.push(c),
+        }
+    }
+    uri.push_str("?mode=memory&cache=shared");
+    Connection::open_with_flags(uri, OpenFlags::default() | OpenFlags::SQLITE_OPEN_URI)
+}
+
+/// Return the number of migrations that have been applied to a database.
+///
+/// This is the database's `user_version`, which the vaults update after
+/// applying migrations. Migrations are identified only by their index, so no
+/// further history like names or timestamps is recorded. To keep one, have the
+/// migrations insert it into a table of their own.
+pub fn applied_migration_count(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.query_row("SELECT * FROM pragma_user_version", [], |r| r.get(0))
+}
 
-    let user_version: usize =
-        tx.query_row("SELECT * FROM pragma_user_version", [], |r| r.get(0))?;
+/// Ensure the database's `application_id` matches the expected one.
+///
+/// Returns `true` if the `application_id` is still `0`, in which case the
+/// database should be claimed by setting it to the expected value.
+fn check_application_id(conn: &Connection, expected: i32) -> Result<bool, MigrateError> {
+    let actual: i32 = conn.query_row("SELECT * FROM pragma_application_id", [], |r| r.get(0))?;
+    if actual != 0 && actual != expected {
+        return Err(MigrateError::ApplicationIdMismatch { expected, actual });
+    }
+    Ok(actual == 0)
+}
 
-    let total = migrations.len();
+/// Ensure the database's `application_id` matches the expected one, claiming
+/// the database if its `application_id` is still `0`.
+///
+/// Only for databases without pending migrations, since the database is
+/// claimed outside of a transaction.
+#[cfg(feature = "tokio")]
+fn claim_application_id(conn: &Connection, expected: Option<i32>) -> Result<(), MigrateError> {
+    if let Some(expected) = expected {
+        if check_application_id(conn, expected)? {
+            conn.pragma_update(None, "application_id", expected)?;
+        }
+    }
+    Ok(())
+}
+
+fn migration_failed(index: usize, total: usize, source: rusqlite::Error) -> MigrateError {
+    warn!("Migration {}/{total} failed: {source}", index + 1);
+    MigrateError::Migration { index, source }
+}
+
+fn migrate(
+    conn: &mut Connection,
+    migrations: &[Migration],
+    verifier: Option<MigrationVerifier>,
+    application_id: Option<i32>,
+) -> Result<(), MigrateError> {
+    let steps = migrations
+        .iter()
+        .map(|&migration| MigrationStep::Transactional(migration))
+        .collect::<Vec<_>>();
+    migrate_steps(conn, &steps, verifier, application_id)
+}
+
+/// Apply all migrations that haven't been applied yet.
+///
+/// If an `application_id` is given, it is checked before applying any
+/// migrations. Unclaimed databases are claimed together with the first
+/// successfully applied migrations, so a database is never claimed if they
+/// fail.
+fn migrate_steps(
+    conn: &mut Connection,
+    steps: &[MigrationStep],
+    verifier: Option<MigrationVerifier>,
+    application_id: Option<i32>,
+) -> Result<(), MigrateError> {
+    let mut claim = match application_id {
+        Some(expected) if check_application_id(conn, expected)? => Some(expected),
+        _ => None,
+    };
+
+    let user_version = applied_migration_count(conn)?;
+
+    let total = steps.len();
     assert!(user_version <= total, "malformed database schema");
-    for (i, migration) in migrations.iter().enumerate().skip(user_version) {
-        migration(&mut tx, i, total)?;
+    debug!("Database has {user_version}/{total} migrations applied");
+
+    if let Some(verifier) = verifier {
+        if 0 < user_version && user_version < total {
+            verifier(conn, user_version).map_err(|source| MigrateError::Verification {
+                version: user_version,
+                source,
+            })?;
+        }
     }
 
-    tx.pragma_update(None, "user_version", total)?;
-    tx.commit()
+    let mut i = user_version;
+    loop {
+        match steps.get(i) {
+            Some(MigrationStep::NonTransactional(migration)) => {
+                info!("Applying non-transactional migration {}/{total}", i + 1);
+                migration(conn, i, total).map_err(|source| migration_failed(i, total, source))?;
+                i += 1;
+                conn.pragma_update(None, "user_version", i)?;
+                if let Some(application_id) = claim.take() {
+                    conn.pragma_update(None, "application_id", application_id)?;
+                }
+            }
+            _ => {
+                // Group all consecutive transactional migrations into a single
+                // transaction. If there are no migrations left, this still
+                // ensures that the user_version is up to date.
+                let mut tx = conn.transaction()?;
+                while let Some(MigrationStep::Transactional(migration)) = steps.get(i) {
+                    info!("Applying migration {}/{total}", i + 1);
+                    migration(&mut tx, i, total)
+                        .map_err(|source| migration_failed(i, total, source))?;
+                    i += 1;
+                }
+                tx.pragma_update(None, "user_version", i)?;
+                if let Some(application_id) = claim.take() {
+                    tx.pragma_update(None, "application_id", application_id)?;
+                }
+                tx.commit()?;
+            }
+        }
+
+        if i >= total {
+            return Ok(());
+        }
+    }
 }
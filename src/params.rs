@@ -0,0 +1,194 @@
+//! Owned query parameters.
+
+use rusqlite::{types::Value, Statement};
+
+/// Owned parameters for a SQL statement.
+///
+/// Unlike rusqlite's [`Params`](rusqlite::Params), these own their values and
+/// can thus be stored in [`Action`](crate::Action)s and sent to other threads.
+/// They are bound to the statement when the action runs.
+///
+/// Positional parameters can be constructed from tuples, arrays, slices and
+/// [`Vec`]s of values convertible into [`Value`], or built incrementally via
+/// [`OwnedParams::push`]. Named parameters like `:id` can be constructed via
+/// [`OwnedParams::named`] or [`owned_named_params!`](crate::owned_named_params),
+/// or built incrementally via [`OwnedParams::push_named`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OwnedParams {
+    values: Vec<Value>,
+    named: Vec<(String, Value)>,
+}
+
+impl OwnedParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create named parameters from pairs of names and values.
+    ///
+    /// See [`OwnedParams::push_named`] for more details.
+    pub fn named<K, V>(params: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<Value>,
+    {
+        let mut result = Self::new();
+        for (name, value) in params {
+            result.push_named(name, value);
+        }
+        result
+    }
+
+    /// Append a positional parameter.
+    pub fn push(&mut self, value: impl Into<Value>) {
+        self.values.push(value.into());
+    }
+
+    /// Add a named parameter, replacing any previous parameter with the same
+    /// name.
+    ///
+    /// The name must include its prefix, just like in the SQL statement, for
+    /// example `:id`, `@id` or `$id`. Positional and named parameters can be
+    /// mixed, in which case the positional parameters are bound first, starting
+    /// at index 1. The named parameters must then appear after all positional
+    /// ones in the statement, since SQLite numbers named parameters in order of
+    /// appearance too.
+    pub fn push_named(&mut self, name: impl Into<String>, value: impl Into<Value>) {
+        let name = name.into();
+        let value = value.into();
+        match self.named.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, v)) => *v = value,
+            None => self.named.push((name, value)),
+        }
+    }
+
+    /// Bind the parameters to a freshly prepared statement.
+    ///
+    /// This is meant for custom [`Action`](crate::Action)s that store
+    /// [`OwnedParams`]. Afterwards, the statement can be run via
+    /// [`Statement::raw_execute`] or [`Statement::raw_query`].
+    ///
+    /// Returns [`rusqlite::Error::InvalidParameterCount`] if the number of
+    /// parameters doesn't match what the statement expects and
+    /// [`rusqlite::Error::InvalidParameterName`] if the statement has no
+    /// parameter with one of the names or if a named parameter has the index of
+    /// a positional one.
+    pub fn bind(&self, stmt: &mut Statement<'_>) -> rusqlite::Result<()> {
+        let expected = stmt.parameter_count();
+        let actual = self.values.len() + self.named.len();
+        if actual != expected {
+            return Err(rusqlite::Error::InvalidParameterCount(actual, expected));
+        }
+
+        for (i, value) in self.values.iter().enumerate() {
+            stmt.raw_bind_parameter(i + 1, value)?;
+        }
+        for (name, value) in &self.named {
+            let i = stmt
+                .parameter_index(name)?
+                .filter(|i| *i > self.values.len())
+                .ok_or_else(|| rusqlite::Error::InvalidParameterName(name.clone()))?;
+            stmt.raw_bind_parameter(i, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for OwnedParams {
+    fn from(value: Vec<T>) -> Self {
+        let values = value.into_iter().map(Into::into).collect();
+        Self {
+            values,
+            named: vec![],
+        }
+    }
+}
+
+impl<T: Into<Value>, const N: usize> From<[T; N]> for OwnedParams {
+    fn from(value: [T; N]) -> Self {
+        let values = value.into_iter().map(Into::into).collect();
+        Self {
+            values,
+            named: vec![],
+        }
+    }
+}
+
+impl<T: Clone + Into<Value>> From<&[T]> for OwnedParams {
+    fn from(value: &[T]) -> Self {
+        let values = value.iter().cloned().map(Into::into).collect();
+        Self {
+            values,
+            named: vec![],
+        }
+    }
+}
+
+impl From<()> for OwnedParams {
+    fn from(_value: ()) -> Self {
+        Self::new()
+    }
+}
+
+macro_rules! impl_from_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: Into<Value>),+> From<($($name,)+)> for OwnedParams {
+            #[allow(non_snake_case)]
+            fn from(value: ($($name,)+)) -> Self {
+                let ($($name,)+) = value;
+                let values = vec![$($name.into()),+];
+                Self {
+                    values,
+                    named: vec![],
+                }
+            }
+        }
+    };
+}
+
+impl_from_tuple!(A);
+impl_from_tuple!(A, B);
+impl_from_tuple!(A, B, C);
+impl_from_tuple!(A, B, C, D);
+impl_from_tuple!(A, B, C, D, E);
+impl_from_tuple!(A, B, C, D, E, F);
+impl_from_tuple!(A, B, C, D, E, F, G);
+impl_from_tuple!(A, B, C, D, E, F, G, H);
+impl_from_tuple!(A, B, C, D, E, F, G, H, I);
+impl_from_tuple!(A, B, C, D, E, F, G, H, I, J);
+impl_from_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+impl_from_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+/// Construct named [`OwnedParams`], just like rusqlite's
+/// [`named_params!`](rusqlite::named_params).
+///
+/// ```
+/// # use rusqlite::Connection;
+/// # use vault::{actions::Exec, simple::SimpleVault};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let conn = Connection::open_in_memory()?;
+/// # conn.execute_batch(
+/// #     "
+/// #     CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+/// #     INSERT INTO users (id, name) VALUES (1, 'bar');
+/// #     ",
+/// # )?;
+/// # let mut vault = SimpleVault::new(conn, &[])?;
+/// let params = vault::owned_named_params! { ":id": 1, ":name": "foo".to_string() };
+/// let sql = "UPDATE users SET name = :name WHERE id = :id";
+/// let changed = vault.execute(Exec::new(sql, params))?;
+/// # assert_eq!(changed, 1);
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! owned_named_params {
+    () => {
+        $crate::OwnedParams::new()
+    };
+    ($($name:literal: $value:expr),+ $(,)?) => {{
+        let mut params = $crate::OwnedParams::new();
+        $(params.push_named($name, $value);)+
+        params
+    }};
+}
@@ -0,0 +1,204 @@
+//! Reading query results into Polars data frames.
+
+use std::{error, fmt};
+
+use polars::prelude::{Column, DataFrame, DataType, PolarsError};
+use rusqlite::{
+    types::{Type, Value},
+    Connection, Params, Rows,
+};
+
+use crate::{Action, OwnedParams};
+
+/// Error that can occur while reading rows into a [`DataFrame`].
+#[derive(Debug)]
+pub enum DataFrameError {
+    /// A value doesn't match the Polars type of its column.
+    TypeMismatch {
+        column: String,
+        expected: DataType,
+        actual: Type,
+    },
+    /// Building the data frame failed.
+    Polars(PolarsError),
+    /// Reading the rows failed.
+    Rusqlite(rusqlite::Error),
+}
+
+impl fmt::Display for DataFrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TypeMismatch {
+                column,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "column {column:?} of type {expected} contains value of type {actual}"
+            ),
+            Self::Polars(err) => err.fmt(f),
+            Self::Rusqlite(err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for DataFrameError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::TypeMismatch { .. } => None,
+            Self::Polars(err) => err.source(),
+            Self::Rusqlite(err) => err.source(),
+        }
+    }
+}
+
+impl From<PolarsError> for DataFrameError {
+    fn from(value: PolarsError) -> Self {
+        Self::Polars(value)
+    }
+}
+
+impl From<rusqlite::Error> for DataFrameError {
+    fn from(value: rusqlite::Error) -> Self {
+        Self::Rusqlite(value)
+    }
+}
+
+/// The Polars type corresponding to a column's type.
+fn data_type(ty: Type) -> DataType {
+    match ty {
+        Type::Null => DataType::Null,
+        Type::Integer => DataType::Int64,
+        Type::Real => DataType::Float64,
+        Type::Text => DataType::String,
+        Type::Blob => DataType::Binary,
+    }
+}
+
+fn values_to_column(name: &str, ty: Type, values: Vec<Value>) -> Result<Column, DataFrameError> {
+    let mismatch = |value: Value| DataFrameError::TypeMismatch {
+        column: name.to_string(),
+        expected: data_type(ty),
+        actual: value.data_type(),
+    };
+
+    let column = match ty {
+        Type::Null => Column::full_null(name.into(), values.len(), &DataType::Null),
+        Type::Integer => Column::new(
+            name.into(),
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Null => Ok(None),
+                    Value::Integer(v) => Ok(Some(v)),
+                    value => Err(mismatch(value)),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        Type::Real => Column::new(
+            name.into(),
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Null => Ok(None),
+                    Value::Integer(v) => Ok(Some(v as f64)),
+                    Value::Real(v) => Ok(Some(v)),
+                    value => Err(mismatch(value)),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        Type::Text => Column::new(
+            name.into(),
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Null => Ok(None),
+                    Value::Text(v) => Ok(Some(v)),
+                    value => Err(mismatch(value)),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        Type::Blob => Column::new(
+            name.into(),
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Null => Ok(None),
+                    Value::Blob(v) => Ok(Some(v)),
+                    value => Err(mismatch(value)),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+    };
+    Ok(column)
+}
+
+/// Read the result of a query into a [`DataFrame`].
+///
+/// Since SQLite is dynamically typed, the Polars type of each column has to be
+/// determined from its contents. If the column has a declared type, the type
+/// is determined using SQLite's rules for column affinity:
+///
+/// - `INTEGER` affinity becomes [`DataType::Int64`]
+/// - `TEXT` affinity becomes [`DataType::String`]
+/// - a declared type containing `BLOB` becomes [`DataType::Binary`]
+/// - `REAL` affinity becomes [`DataType::Float64`]
+///
+/// Otherwise, for example for `NUMERIC` columns or expressions, the type is
+/// inferred from the first non-`NULL` value in the column, except that a column
+/// containing both integers and reals becomes [`DataType::Float64`]. Columns
+/// containing only `NULL`s become [`DataType::Null`].
+///
+/// `NULL` values become nulls in their column. Integers are accepted in
+/// [`DataType::Float64`] columns, but any other value not matching its column's
+/// type results in a [`DataFrameError::TypeMismatch`].
+///
+/// Column names must be unique, otherwise building the data frame fails.
+pub fn rows_to_dataframe(rows: Rows<'_>) -> Result<DataFrame, DataFrameError> {
+    let (columns, _) = crate::columnar::read_typed_columns(rows)?;
+    let columns = columns
+        .into_iter()
+        .map(|c| values_to_column(&c.name, c.ty, c.values))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Execute a query and read its result into a [`DataFrame`].
+///
+/// See [`rows_to_dataframe`] for more details.
+pub fn query_dataframe<P: Params>(
+    conn: &Connection,
+    sql: &str,
+    params: P,
+) -> Result<DataFrame, DataFrameError> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query(params)?;
+    rows_to_dataframe(rows)
+}
+
+/// An [`Action`] performing [`query_dataframe`].
+pub struct QueryDataFrame {
+    sql: String,
+    params: OwnedParams,
+}
+
+impl QueryDataFrame {
+    pub fn new(sql: impl Into<String>, params: impl Into<OwnedParams>) -> Self {
+        Self {
+            sql: sql.into(),
+            params: params.into(),
+        }
+    }
+}
+
+impl Action for QueryDataFrame {
+    type Output = DataFrame;
+    type Error = DataFrameError;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let mut stmt = conn.prepare(&self.sql)?;
+        self.params.bind(&mut stmt)?;
+        let rows = stmt.raw_query();
+        rows_to_dataframe(rows)
+    }
+}
@@ -0,0 +1,129 @@
+//! A vault backed by an [`r2d2`] connection pool.
+
+use std::{error, fmt};
+
+#[cfg(feature = "polars")]
+use polars::prelude::DataFrame;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+
+#[cfg(feature = "polars")]
+use crate::{
+    polars::{DataFrameError, QueryDataFrame},
+    OwnedParams,
+};
+use crate::{Action, MigrateError, Migration, MigrationStep, SyncVault};
+
+/// Error that can occur when using an [`R2d2Vault`].
+#[derive(Debug)]
+pub enum Error<E> {
+    /// No connection could be checked out of the pool.
+    Pool(r2d2::Error),
+    /// An error was returned by the [`Action`], or by the migrations while
+    /// creating the vault.
+    Action(E),
+}
+
+impl<E: fmt::Display> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pool(err) => err.fmt(f),
+            Self::Action(err) => err.fmt(f),
+        }
+    }
+}
+
+impl<E: error::Error> error::Error for Error<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Pool(err) => err.source(),
+            Self::Action(err) => err.source(),
+        }
+    }
+}
+
+/// A vault backed by an [`r2d2`] connection pool.
+///
+/// Instead of owning a single dedicated connection, this vault checks out a
+/// connection from the pool for every [`Action`] and returns it afterwards.
+/// This is useful for applications that already manage a pool but want to
+/// re-use existing [`Action`]s and [`Migration`]s.
+///
+/// Pragmas like `foreign_keys` are set per connection, so they should be set
+/// via [`SqliteConnectionManager::with_init`] rather than on a single
+/// connection.
+#[derive(Debug, Clone)]
+pub struct R2d2Vault {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl R2d2Vault {
+    /// Create a new vault from an existing [`Pool`], applying the migrations
+    /// in the process.
+    ///
+    /// The migrations are applied once, on a single connection checked out of
+    /// the pool.
+    pub fn new(
+        pool: Pool<SqliteConnectionManager>,
+        migrations: &[Migration],
+    ) -> Result<Self, Error<MigrateError>> {
+        let mut conn = pool.get().map_err(Error::Pool)?;
+        crate::migrate(&mut conn, migrations, None, None).map_err(Error::Action)?;
+        drop(conn);
+        Ok(Self { pool })
+    }
+
+    /// Create a new vault from an existing [`Pool`], applying a mix of
+    /// transactional and non-transactional migrations in the process.
+    ///
+    /// See [`MigrationStep`] for how failures are handled.
+    pub fn new_mixed(
+        pool: Pool<SqliteConnectionManager>,
+        steps: &[MigrationStep],
+    ) -> Result<Self, Error<MigrateError>> {
+        let mut conn = pool.get().map_err(Error::Pool)?;
+        crate::migrate_steps(&mut conn, steps, None, None).map_err(Error::Action)?;
+        drop(conn);
+        Ok(Self { pool })
+    }
+
+    /// The pool backing this vault.
+    pub fn pool(&self) -> &Pool<SqliteConnectionManager> {
+        &self.pool
+    }
+
+    /// Check out a connection from the pool, execute an [`Action`] on it and
+    /// return the result.
+    ///
+    /// The connection is returned to the pool afterwards.
+    pub fn execute<A: Action>(&self, action: A) -> Result<A::Output, Error<A::Error>> {
+        let mut conn = self.pool.get().map_err(Error::Pool)?;
+        action.run(&mut conn).map_err(Error::Action)
+    }
+
+    /// Execute a query and read its result into a [`DataFrame`].
+    ///
+    /// See [`rows_to_dataframe`](crate::rows_to_dataframe) for more details.
+    #[cfg(feature = "polars")]
+    pub fn query_dataframe(
+        &self,
+        sql: impl Into<String>,
+        params: impl Into<OwnedParams>,
+    ) -> Result<DataFrame, Error<DataFrameError>> {
+        self.execute(QueryDataFrame::new(sql, params))
+    }
+}
+
+impl SyncVault for R2d2Vault {
+    type Error<E> = Error<E>;
+
+    fn execute<A: Action>(&mut self, action: A) -> Result<A::Output, Error<A::Error>> {
+        Self::execute(self, action)
+    }
+}
+
+// Vaults are meant to be shared between threads.
+const _: () = {
+    fn assert_send_sync<T: Send + Sync>() {}
+    let _ = assert_send_sync::<R2d2Vault>;
+};
@@ -0,0 +1,274 @@
+//! Inspecting and verifying the database schema.
+
+use std::{error, fmt};
+
+use rusqlite::Connection;
+
+/// Read the name and `CREATE` statement of all user-defined schema objects.
+///
+/// Tables come first, followed by views, indexes and triggers, each in the
+/// order they were created in.
+fn schema_objects(conn: &Connection) -> rusqlite::Result<Vec<(String, String)>> {
+    conn.prepare(
+        "
+        SELECT name, sql FROM sqlite_schema
+        WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\'
+        ORDER BY
+            CASE type
+                WHEN 'table' THEN 0
+                WHEN 'view' THEN 1
+                WHEN 'index' THEN 2
+                ELSE 3
+            END,
+            rowid
+        ",
+    )?
+    .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+    .collect()
+}
+
+/// Return the `CREATE` statements of all user-defined tables, views, indexes
+/// and triggers as a single string.
+///
+/// See [`SchemaDumpAction`](crate::actions::SchemaDumpAction) for more details.
+pub fn dump_schema(conn: &Connection) -> rusqlite::Result<String> {
+    let mut dump = String::new();
+    for (_, sql) in schema_objects(conn)? {
+        dump.push_str(&sql);
+        dump.push_str(";\n");
+    }
+    Ok(dump)
+}
+
+fn object_exists(conn: &Connection, kind: &str, name: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_schema WHERE type = ? AND name = ? COLLATE NOCASE)",
+        (kind, name),
+        |r| r.get(0),
+    )
+}
+
+/// Check whether a table with the given name exists.
+///
+/// Like in SQL statements, the name is case-insensitive. Only the main
+/// database is searched, not temporary or attached ones. Since a
+/// [`Transaction`](rusqlite::Transaction) dereferences to a [`Connection`], this
+/// can be used from within migrations to make them idempotent.
+///
+/// See also [`TableExistsAction`](crate::actions::TableExistsAction).
+pub fn table_exists(conn: &Connection, name: &str) -> rusqlite::Result<bool> {
+    object_exists(conn, "table", name)
+}
+
+/// Check whether an index with the given name exists.
+///
+/// See [`table_exists`] for more details.
+///
+/// See also [`IndexExistsAction`](crate::actions::IndexExistsAction).
+pub fn index_exists(conn: &Connection, name: &str) -> rusqlite::Result<bool> {
+    object_exists(conn, "index", name)
+}
+
+/// Normalize a `CREATE` statement so that differences in whitespace and
+/// comments don't matter.
+///
+/// Runs of whitespace and comments are collapsed into a single space, which is
+/// removed entirely next to punctuation. Quoted strings and identifiers are
+/// kept as they are.
+fn normalize_sql(sql: &str) -> String {
+    let mut result = String::with_capacity(sql.len());
+    let mut pending_space = false;
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        // Comments and whitespace
+        if c.is_whitespace() {
+            pending_space = true;
+            continue;
+        }
+        if c == '-' && chars.peek() == Some(&'-') {
+            for c in chars.by_ref() {
+                if c == '\n' {
+                    break;
+                }
+            }
+            pending_space = true;
+            continue;
+        }
+        if c == '/' && chars.peek() == Some(&'*') {
+            chars.next();
+            let mut prev = ' ';
+            for c in chars.by_ref() {
+                if prev == '*' && c == '/' {
+                    break;
+                }
+                prev = c;
+            }
+            pending_space = true;
+            continue;
+        }
+
+        let is_punctuation = |c: char| "(),;=".contains(c);
+        let last = result.chars().next_back();
+        if pending_space && last.is_some_and(|l| !is_punctuation(l)) && !is_punctuation(c) {
+            result.push(' ');
+        }
+        pending_space = false;
+        result.push(c);
+
+        // Quoted strings and identifiers
+        let close = match c {
+            '\'' | '"' | '`' => c,
+            '[' => ']',
+            _ => continue,
+        };
+        for c in chars.by_ref() {
+            result.push(c);
+            if c == close {
+                // A doubled quote inside a string simply starts a new string
+                break;
+            }
+        }
+    }
+
+    result
+}
+
+/// A difference between the live schema and the expected schema found by
+/// [`verify_schema`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaDifference {
+    /// An object of the expected schema doesn't exist in the database.
+    Missing { name: String, expected: String },
+    /// An object in the database isn't part of the expected schema.
+    Unexpected { name: String, actual: String },
+    /// An object exists in both schemas, but its definition differs.
+    Changed {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+/// Write every line of `sql` prefixed with `prefix`, like in a diff.
+fn write_prefixed(f: &mut fmt::Formatter<'_>, prefix: char, sql: &str) -> fmt::Result {
+    for (i, line) in sql.lines().enumerate() {
+        if i > 0 {
+            writeln!(f)?;
+        }
+        write!(f, "{prefix}{line}")?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for SchemaDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing { expected, .. } => write_prefixed(f, '-', expected),
+            Self::Unexpected { actual, .. } => write_prefixed(f, '+', actual),
+            Self::Changed {
+                expected, actual, ..
+            } => {
+                write_prefixed(f, '-', expected)?;
+                writeln!(f)?;
+                write_prefixed(f, '+', actual)
+            }
+        }
+    }
+}
+
+/// Error that can occur while verifying the schema via [`verify_schema`].
+#[derive(Debug)]
+pub enum VerifySchemaError {
+    /// The live schema doesn't match the expected schema.
+    Mismatch(Vec<SchemaDifference>),
+    /// Reading the live schema or applying the expected schema failed.
+    Rusqlite(rusqlite::Error),
+}
+
+impl fmt::Display for VerifySchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mismatch(differences) => {
+                write!(f, "schema doesn't match expected schema")?;
+                for difference in differences {
+                    write!(f, "\n{difference}")?;
+                }
+                Ok(())
+            }
+            Self::Rusqlite(err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for VerifySchemaError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Mismatch(_) => None,
+            Self::Rusqlite(err) => err.source(),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for VerifySchemaError {
+    fn from(value: rusqlite::Error) -> Self {
+        Self::Rusqlite(value)
+    }
+}
+
+/// Compare the live schema of a database against an expected schema.
+///
+/// The expected schema is given as a string of `CREATE` statements, for
+/// example one produced by [`dump_schema`]. It is applied to a new in-memory
+/// database, and the resulting objects are compared by name with the database's
+/// user-defined tables, views, indexes and triggers. Differences in whitespace
+/// and comments are ignored, but everything else, including the case of
+/// keywords, must match.
+///
+/// If the schemas differ, all differences are returned in a
+/// [`VerifySchemaError::Mismatch`], whose [`Display`](fmt::Display)
+/// implementation formats them as a diff. Objects of the expected schema come
+/// first, in the order they are listed in [`dump_schema`].
+///
+/// Since the expected schema is applied to a plain in-memory database, it can't
+/// use custom functions, collations or extensions.
+pub fn verify_schema(conn: &Connection, expected_ddl: &str) -> Result<(), VerifySchemaError> {
+    let reference = Connection::open_in_memory()?;
+    reference.execute_batch(expected_ddl)?;
+    let expected = schema_objects(&reference)?;
+    let mut actual = schema_objects(conn)?;
+
+    let mut differences = vec![];
+    for (name, expected_sql) in expected {
+        let index = actual
+            .iter()
+            .position(|(n, _)| n.eq_ignore_ascii_case(&name));
+        let Some(index) = index else {
+            differences.push(SchemaDifference::Missing {
+                name,
+                expected: expected_sql,
+            });
+            continue;
+        };
+        let (_, actual_sql) = actual.remove(index);
+        if normalize_sql(&expected_sql) != normalize_sql(&actual_sql) {
+            differences.push(SchemaDifference::Changed {
+                name,
+                expected: expected_sql,
+                actual: actual_sql,
+            });
+        }
+    }
+    for (name, actual_sql) in actual {
+        differences.push(SchemaDifference::Unexpected {
+            name,
+            actual: actual_sql,
+        });
+    }
+
+    if differences.is_empty() {
+        Ok(())
+    } else {
+        Err(VerifySchemaError::Mismatch(differences))
+    }
+}
@@ -1,21 +1,62 @@
-use std::{error, fmt, str::Utf8Error};
+//! Deserializing rows via [`serde`].
+//!
+//! Values are deserialized based on their storage class, not the declared
+//! type of their column:
+//! - `NULL` as unit or [`None`]
+//! - `INTEGER` as any integer or float type, or as `bool` where `0` is `false`
+//!   and any other value is `true`
+//! - `REAL` as `f64` or `f32`
+//! - `TEXT` as string, or as `bool`, `i128`, `u128`, `f64` or `f32` if the
+//!   text can be parsed as such, or as the name of a unit enum variant, or with
+//!   the `json` feature as any type parsed from JSON if wrapped in `Json` (or
+//!   as sequence, tuple, map or struct if `FromRowOptions::parse_json_text` is
+//!   set)
+//! - `BLOB` as bytes or byte sequence
+//!
+//! This also applies to `STRICT` tables. Since they have no `BOOLEAN` type,
+//! booleans are usually stored in `INTEGER` columns and deserialize as
+//! described above. Note that `STRICT` tables convert integers stored in a
+//! `REAL` column to `REAL`, so these can't be deserialized as integers or
+//! `bool`s. Values of the wrong storage class result in an error naming the
+//! unexpected type.
+
+use std::{
+    error, fmt,
+    marker::PhantomData,
+    ops::Range,
+    str::Utf8Error,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use rusqlite::{
-    types::{FromSqlError, ValueRef},
-    Row,
+    types::{FromSqlError, ToSqlOutput, Value, ValueRef},
+    Connection, Params, Row, Rows, Statement, ToSql,
 };
 use serde::{
     de::{
-        self, value::BorrowedStrDeserializer, DeserializeSeed, Deserializer, MapAccess, SeqAccess,
-        Visitor,
+        self,
+        value::{BorrowedStrDeserializer, SeqDeserializer},
+        DeserializeOwned, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor,
     },
-    forward_to_deserialize_any, Deserialize,
+    forward_to_deserialize_any,
+    ser::{self, Impossible, SerializeMap, SerializeStruct},
+    Deserialize, Serialize, Serializer,
 };
 
+use crate::{Action, OwnedParams};
+
 #[derive(Debug)]
 enum Error {
     ExpectedTupleLikeBaseType,
-    ExpectedStructLikeBaseType,
+    InvalidBool(String),
+    InvalidBoolInteger(i64),
+    InvalidFloat(String),
+    InvalidInteger(String),
+    ColumnCount { expected: usize, actual: usize },
+    MultipleRows,
+    ExpectedStructOrMap,
+    UnsupportedParam(&'static str),
+    IntegerOutOfRange(u64),
     Utf8(Utf8Error),
     Rusqlite(rusqlite::Error),
     Custom(String),
@@ -25,7 +66,17 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::ExpectedTupleLikeBaseType => write!(f, "expected tuple-like base type"),
-            Self::ExpectedStructLikeBaseType => write!(f, "expected struct-like base type"),
+            Self::InvalidBool(v) => write!(f, "invalid boolean text {v:?}"),
+            Self::InvalidBoolInteger(v) => write!(f, "invalid boolean integer {v}"),
+            Self::InvalidFloat(v) => write!(f, "invalid float text {v:?}"),
+            Self::InvalidInteger(v) => write!(f, "invalid or out of range integer text {v:?}"),
+            Self::ColumnCount { expected, actual } => {
+                write!(f, "expected {expected} columns, found {actual}")
+            }
+            Self::MultipleRows => write!(f, "query returned more than one row"),
+            Self::ExpectedStructOrMap => write!(f, "expected struct or map of parameters"),
+            Self::UnsupportedParam(kind) => write!(f, "can't bind {kind} as parameter"),
+            Self::IntegerOutOfRange(v) => write!(f, "integer {v} out of range for i64"),
             Self::Utf8(err) => err.fmt(f),
             Self::Rusqlite(err) => err.fmt(f),
             Self::Custom(msg) => msg.fmt(f),
@@ -41,12 +92,25 @@ impl de::Error for Error {
     }
 }
 
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
 impl From<Utf8Error> for Error {
     fn from(value: Utf8Error) -> Self {
         Self::Utf8(value)
     }
 }
 
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Custom(value.to_string())
+    }
+}
+
 impl From<rusqlite::Error> for Error {
     fn from(value: rusqlite::Error) -> Self {
         Self::Rusqlite(value)
@@ -55,15 +119,48 @@ impl From<rusqlite::Error> for Error {
 
 struct ValueRefDeserializer<'de> {
     value: ValueRef<'de>,
+    options: FromRowOptions,
+}
+
+impl<'de> ValueRefDeserializer<'de> {
+    /// Deserialize a signed integer of at most 64 bits, parsing `TEXT` values
+    /// if [`FromRowOptions::parse_numeric_text`] is set.
+    fn deserialize_signed<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            ValueRef::Text(v) if self.options.parse_numeric_text => {
+                let v = std::str::from_utf8(v)?;
+                let v = v
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::InvalidInteger(v.to_string()))?;
+                visitor.visit_i64(v)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    /// Deserialize an unsigned integer of at most 64 bits, parsing `TEXT`
+    /// values if [`FromRowOptions::parse_numeric_text`] is set.
+    fn deserialize_unsigned<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            ValueRef::Text(v) if self.options.parse_numeric_text => {
+                let v = std::str::from_utf8(v)?;
+                let v = v
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::InvalidInteger(v.to_string()))?;
+                visitor.visit_u64(v)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
 }
 
 impl<'de> Deserializer<'de> for ValueRefDeserializer<'de> {
     type Error = Error;
 
     forward_to_deserialize_any! {
-        i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf
-        unit unit_struct seq tuple tuple_struct map struct identifier
-        ignored_any
+        char unit unit_struct tuple_struct identifier ignored_any
     }
 
     fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
@@ -79,14 +176,212 @@ impl<'de> Deserializer<'de> for ValueRefDeserializer<'de> {
     fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
         match self.value {
             ValueRef::Integer(0) => visitor.visit_bool(false),
+            ValueRef::Integer(1) => visitor.visit_bool(true),
+            ValueRef::Integer(v) if self.options.strict_bools => Err(Error::InvalidBoolInteger(v)),
             ValueRef::Integer(_) => visitor.visit_bool(true),
+            ValueRef::Text(v) => match std::str::from_utf8(v)? {
+                v if v.eq_ignore_ascii_case("false") || v == "0" => visitor.visit_bool(false),
+                v if v.eq_ignore_ascii_case("true") || v == "1" => visitor.visit_bool(true),
+                v => Err(Error::InvalidBool(v.to_string())),
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_signed(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_signed(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_signed(visitor)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_signed(visitor)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_unsigned(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_unsigned(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_unsigned(visitor)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_unsigned(visitor)
+    }
+
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            ValueRef::Text(v) => {
+                let v = std::str::from_utf8(v)?;
+                let v = v
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::InvalidInteger(v.to_string()))?;
+                visitor.visit_i128(v)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            ValueRef::Text(v) => {
+                let v = std::str::from_utf8(v)?;
+                let v = v
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::InvalidInteger(v.to_string()))?;
+                visitor.visit_u128(v)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            ValueRef::Text(v) => {
+                let v = std::str::from_utf8(v)?;
+                let v = v
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::InvalidFloat(v.to_string()))?;
+                visitor.visit_f32(v)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            ValueRef::Text(v) => {
+                let v = std::str::from_utf8(v)?;
+                let v = v
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::InvalidFloat(v.to_string()))?;
+                visitor.visit_f64(v)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            ValueRef::Text(v) => visitor.visit_borrowed_str(std::str::from_utf8(v)?),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            ValueRef::Text(v) => visitor.visit_string(std::str::from_utf8(v)?.to_string()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            ValueRef::Text(v) | ValueRef::Blob(v) => visitor.visit_borrowed_bytes(v),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            ValueRef::Text(v) | ValueRef::Blob(v) => visitor.visit_byte_buf(v.to_vec()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            // Types like Vec<u8> deserialize themselves as sequences, not bytes
+            ValueRef::Blob(v) => visitor.visit_seq(SeqDeserializer::new(v.iter().copied())),
+            #[cfg(feature = "json")]
+            ValueRef::Text(v) if self.options.parse_json_text => {
+                deserialize_json(std::str::from_utf8(v)?, |de| de.deserialize_seq(visitor))
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    #[cfg(feature = "json")]
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.value {
+            ValueRef::Text(v) if self.options.parse_json_text => {
+                deserialize_json(std::str::from_utf8(v)?, |de| {
+                    de.deserialize_tuple(len, visitor)
+                })
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    #[cfg(not(feature = "json"))]
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            #[cfg(feature = "json")]
+            ValueRef::Text(v) if self.options.parse_json_text => {
+                deserialize_json(std::str::from_utf8(v)?, |de| de.deserialize_map(visitor))
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    #[cfg(feature = "json")]
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.value {
+            ValueRef::Text(v) if self.options.parse_json_text => {
+                deserialize_json(std::str::from_utf8(v)?, |de| {
+                    de.deserialize_struct(name, fields, visitor)
+                })
+            }
             _ => self.deserialize_any(visitor),
         }
     }
 
+    #[cfg(not(feature = "json"))]
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_any(visitor)
+    }
+
     fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
         match self.value {
             ValueRef::Null => visitor.visit_none(),
+            ValueRef::Text(b"") if self.options.empty_string_as_null => visitor.visit_none(),
             _ => visitor.visit_some(self),
         }
     }
@@ -107,16 +402,114 @@ impl<'de> Deserializer<'de> for ValueRefDeserializer<'de> {
     ) -> Result<V::Value, Self::Error> {
         match self.value {
             ValueRef::Text(v) => {
-                let v = BorrowedStrDeserializer::new(std::str::from_utf8(v)?);
-                v.deserialize_enum(name, variants, visitor)
+                let mut v = std::str::from_utf8(v)?;
+                if self.options.case_insensitive_enums && !variants.contains(&v) {
+                    if let Some(variant) = variants.iter().find(|n| n.eq_ignore_ascii_case(v)) {
+                        v = variant;
+                    }
+                }
+                BorrowedStrDeserializer::new(v).deserialize_enum(name, variants, visitor)
             }
             _ => self.deserialize_any(visitor),
         }
     }
 }
 
+/// Options for deserializing rows.
+///
+/// The default options are used by [`from_row_via_index`] and
+/// [`from_row_via_name`]. Use [`from_row_via_index_with`] and
+/// [`from_row_via_name_with`] to specify custom options.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FromRowOptions {
+    /// Require the number of columns to match the number of tuple elements or
+    /// struct fields exactly.
+    ///
+    /// By default, extra columns are ignored when deserializing tuples or
+    /// structs, both via [`from_row_via_index`] and [`from_row_via_name`].
+    /// Missing columns always result in an error.
+    pub strict_column_count: bool,
+
+    /// Deserialize empty `TEXT` values as [`None`] when deserializing an
+    /// [`Option`], just like `NULL` values.
+    ///
+    /// This is useful for legacy databases that store empty strings instead of
+    /// `NULL`.
+    pub empty_string_as_null: bool,
+
+    /// Parse `TEXT` values when deserializing integers like [`i64`] or
+    /// [`u32`].
+    ///
+    /// Columns with `NUMERIC` or `INTEGER` affinity store values as `TEXT` if
+    /// they can't be converted to a number losslessly, so a single column may
+    /// contain both. With this option, such values are parsed, ignoring
+    /// surrounding whitespace, and result in an error if they are not valid
+    /// integers of the requested type. By default, they always result in an
+    /// error, so that values of the wrong type aren't masked.
+    ///
+    /// `TEXT` values are always parsed when deserializing floats, [`i128`] or
+    /// [`u128`].
+    pub parse_numeric_text: bool,
+
+    /// Match `TEXT` values against the names of enum variants while ignoring
+    /// ASCII case.
+    ///
+    /// This is useful for databases that store differently-cased
+    /// discriminants, for example `ACTIVE` for a variant named `Active`. An
+    /// exact match is still preferred if one exists. Only unit variants stored
+    /// as plain `TEXT` are affected, not tagged rows.
+    pub case_insensitive_enums: bool,
+
+    /// Only accept the integers `0` and `1` when deserializing a [`bool`].
+    ///
+    /// By default, any nonzero integer is deserialized as `true`, like in SQL.
+    /// With this option, other integers result in an error instead, so that a
+    /// field accidentally reading from a non-boolean column is noticed.
+    /// `TEXT` values are handled the same either way.
+    pub strict_bools: bool,
+
+    /// Parse `TEXT` values as JSON when deserializing sequences, tuples, maps
+    /// and structs.
+    ///
+    /// With this option, fields like `Vec<i32>` or `HashMap<String, String>`
+    /// can be read from JSON text without wrapping them in [`Json`]. However,
+    /// this is ambiguous for types that accept both strings and containers,
+    /// for example custom types with a lenient [`Deserialize`] implementation.
+    /// These receive the parsed JSON instead of the plain text, and plain text
+    /// that isn't valid JSON results in an error. By default, such `TEXT`
+    /// values result in an error unless the field is wrapped in [`Json`].
+    #[cfg(feature = "json")]
+    pub parse_json_text: bool,
+
+    /// The column containing the variant name when deserializing an enum via
+    /// [`from_row_via_name`].
+    ///
+    /// Defaults to `kind` if [`None`].
+    #[cfg(feature = "json")]
+    pub enum_tag_column: Option<&'static str>,
+
+    /// The column containing the JSON-encoded variant data when deserializing
+    /// an enum via [`from_row_via_name`].
+    ///
+    /// Defaults to `data` if [`None`].
+    #[cfg(feature = "json")]
+    pub enum_content_column: Option<&'static str>,
+}
+
+impl FromRowOptions {
+    fn check_column_count(&self, actual: usize, expected: usize) -> Result<(), Error> {
+        if actual < expected || (self.strict_column_count && actual != expected) {
+            return Err(Error::ColumnCount { expected, actual });
+        }
+        Ok(())
+    }
+}
+
 struct IndexedRowDeserializer<'de, 'stmt> {
     row: &'de Row<'stmt>,
+    /// The range of columns to deserialize from.
+    columns: Range<usize>,
+    options: FromRowOptions,
 }
 
 impl<'de> Deserializer<'de> for IndexedRowDeserializer<'de, '_> {
@@ -140,23 +533,25 @@ impl<'de> Deserializer<'de> for IndexedRowDeserializer<'de, '_> {
     }
 
     fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-        visitor.visit_seq(IndexedRowSeq::new(self.row))
+        visitor.visit_seq(IndexedRowSeq::new(self.row, self.columns, self.options))
     }
 
     fn deserialize_tuple<V: Visitor<'de>>(
         self,
-        _len: usize,
+        len: usize,
         visitor: V,
     ) -> Result<V::Value, Self::Error> {
+        self.options.check_column_count(self.columns.len(), len)?;
         self.deserialize_seq(visitor)
     }
 
     fn deserialize_tuple_struct<V: Visitor<'de>>(
         self,
         _name: &'static str,
-        _len: usize,
+        len: usize,
         visitor: V,
     ) -> Result<V::Value, Self::Error> {
+        self.options.check_column_count(self.columns.len(), len)?;
         self.deserialize_seq(visitor)
     }
 
@@ -166,18 +561,26 @@ impl<'de> Deserializer<'de> for IndexedRowDeserializer<'de, '_> {
         fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error> {
-        visitor.visit_map(IndexedRowMap::new(self.row, fields))
+        self.options
+            .check_column_count(self.columns.len(), fields.len())?;
+        let start = self.columns.start;
+        visitor.visit_map(IndexedRowMap::new(self.row, start, fields, self.options))
     }
 }
 
 struct IndexedRowSeq<'de, 'stmt> {
     row: &'de Row<'stmt>,
-    next_index: usize,
+    columns: Range<usize>,
+    options: FromRowOptions,
 }
 
 impl<'de, 'stmt> IndexedRowSeq<'de, 'stmt> {
-    fn new(row: &'de Row<'stmt>) -> Self {
-        Self { row, next_index: 0 }
+    fn new(row: &'de Row<'stmt>, columns: Range<usize>, options: FromRowOptions) -> Self {
+        Self {
+            row,
+            columns,
+            options,
+        }
     }
 }
 
@@ -188,28 +591,37 @@ impl<'de> SeqAccess<'de> for IndexedRowSeq<'de, '_> {
     where
         T: DeserializeSeed<'de>,
     {
-        match self.row.get_ref(self.next_index) {
-            Ok(value) => {
-                self.next_index += 1;
-                seed.deserialize(ValueRefDeserializer { value }).map(Some)
-            }
-            Err(rusqlite::Error::InvalidColumnIndex(_)) => Ok(None),
-            Err(err) => Err(err)?,
-        }
+        let Some(index) = self.columns.next() else {
+            return Ok(None);
+        };
+        let value = self.row.get_ref(index)?;
+        let options = self.options;
+        seed.deserialize(ValueRefDeserializer { value, options })
+            .map(Some)
     }
 }
 
 struct IndexedRowMap<'de, 'stmt> {
     row: &'de Row<'stmt>,
+    /// The index of the column containing the first field.
+    start: usize,
     fields: &'static [&'static str],
+    options: FromRowOptions,
     next_index: usize,
 }
 
 impl<'de, 'stmt> IndexedRowMap<'de, 'stmt> {
-    fn new(row: &'de Row<'stmt>, fields: &'static [&'static str]) -> Self {
+    fn new(
+        row: &'de Row<'stmt>,
+        start: usize,
+        fields: &'static [&'static str],
+        options: FromRowOptions,
+    ) -> Self {
         Self {
             row,
+            start,
             fields,
+            options,
             next_index: 0,
         }
     }
@@ -235,21 +647,88 @@ impl<'de> MapAccess<'de> for IndexedRowMap<'de, '_> {
     where
         V: DeserializeSeed<'de>,
     {
-        let value = self.row.get_ref(self.next_index - 1)?;
-        seed.deserialize(ValueRefDeserializer { value })
+        let value = self.row.get_ref(self.start + self.next_index - 1)?;
+        let options = self.options;
+        seed.deserialize(ValueRefDeserializer { value, options })
     }
 }
 
+/// Deserialize a row by column index.
+///
+/// Tuples, tuple structs and sequences are filled with the row's columns in
+/// order. Struct fields are filled with the row's columns in the order they are
+/// declared, ignoring the column names.
+///
+/// `NULL` values deserialize into `None` for `Option` fields. A missing column
+/// is not treated like a `NULL` value though: If the row has fewer columns than
+/// a tuple or struct has fields, an error is returned, even if the remaining
+/// fields are `Option`s. Sequences like `Vec<Option<T>>` contain one element per
+/// column.
+///
+/// Extra columns are ignored. Use [`from_row_via_index_with`] and
+/// [`FromRowOptions::strict_column_count`] to reject rows whose column count
+/// doesn't match.
 pub fn from_row_via_index<'de, T>(row: &'de Row<'_>) -> rusqlite::Result<T>
 where
     T: Deserialize<'de>,
 {
-    T::deserialize(IndexedRowDeserializer { row })
-        .map_err(|err| FromSqlError::Other(Box::new(err)).into())
+    from_row_via_index_with(row, FromRowOptions::default())
+}
+
+/// Like [`from_row_via_index`], but with custom [`FromRowOptions`].
+pub fn from_row_via_index_with<'de, T>(
+    row: &'de Row<'_>,
+    options: FromRowOptions,
+) -> rusqlite::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    let columns = 0..row.as_ref().column_count();
+    from_columns_via_index(row, columns, options)
+}
+
+/// Deserialize a row by column index into two values, the first `a_columns`
+/// columns into `A` and the remaining columns into `B`.
+///
+/// This is useful when a query joins two tables and the columns of each table
+/// should end up in their own struct. Both halves are deserialized like in
+/// [`from_row_via_index`], so each value only sees its own columns, starting
+/// at index 0.
+///
+/// If the row has fewer than `a_columns` columns, `A` receives all of them and
+/// `B` receives none.
+pub fn from_row_split<'de, A, B>(row: &'de Row<'_>, a_columns: usize) -> rusqlite::Result<(A, B)>
+where
+    A: Deserialize<'de>,
+    B: Deserialize<'de>,
+{
+    let column_count = row.as_ref().column_count();
+    let split = a_columns.min(column_count);
+    let options = FromRowOptions::default();
+    let a = from_columns_via_index(row, 0..split, options)?;
+    let b = from_columns_via_index(row, split..column_count, options)?;
+    Ok((a, b))
+}
+
+fn from_columns_via_index<'de, T>(
+    row: &'de Row<'_>,
+    columns: Range<usize>,
+    options: FromRowOptions,
+) -> rusqlite::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    T::deserialize(IndexedRowDeserializer {
+        row,
+        columns,
+        options,
+    })
+    .map_err(|err| FromSqlError::Other(Box::new(err)).into())
 }
 
 struct NamedRowDeserializer<'de, 'stmt> {
     row: &'de Row<'stmt>,
+    options: FromRowOptions,
 }
 
 impl<'de> Deserializer<'de> for NamedRowDeserializer<'de, '_> {
@@ -257,12 +736,57 @@ impl<'de> Deserializer<'de> for NamedRowDeserializer<'de, '_> {
 
     forward_to_deserialize_any! {
         bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
-        byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct
-        map enum identifier ignored_any
+        byte_buf option unit unit_struct seq tuple tuple_struct map
+        identifier ignored_any
     }
 
-    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
-        Err(Error::ExpectedStructLikeBaseType)
+    // Types that don't know their structure in advance, like internally tagged
+    // or untagged enums, see the row as a map from column name to value.
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(ColumnMap::new(self.row, self.options))
+    }
+
+    #[cfg(not(feature = "json"))]
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_any(visitor)
+    }
+
+    #[cfg(feature = "json")]
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let tag_column = self.options.enum_tag_column.unwrap_or(TAG_COLUMN);
+        let content_column = self.options.enum_content_column.unwrap_or(CONTENT_COLUMN);
+
+        let stmt = self.row.as_ref();
+        let kind = self.row.get_ref(stmt.column_index(tag_column)?)?;
+        let kind = kind.as_str().map_err(rusqlite::Error::from)?;
+        let data = match stmt.column_index(content_column) {
+            Ok(i) => self.row.get_ref(i)?,
+            Err(_) => ValueRef::Null,
+        };
+        let data = data.as_str_or_null().map_err(rusqlite::Error::from)?;
+        visitor.visit_enum(TaggedRowEnum {
+            kind,
+            data,
+            content_column,
+        })
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
     }
 
     fn deserialize_struct<V: Visitor<'de>>(
@@ -271,21 +795,116 @@ impl<'de> Deserializer<'de> for NamedRowDeserializer<'de, '_> {
         fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error> {
-        visitor.visit_map(NamedRowMap::new(self.row, fields))
+        self.options
+            .check_column_count(self.row.as_ref().column_count(), fields.len())?;
+        visitor.visit_map(NamedRowMap::new(self.row, fields, self.options))
+    }
+}
+
+/// Deserialize a JSON-encoded value, requiring the entire text to be consumed.
+#[cfg(feature = "json")]
+fn deserialize_json<'de, T>(
+    text: &'de str,
+    f: impl FnOnce(&mut serde_json::Deserializer<serde_json::de::StrRead<'de>>) -> serde_json::Result<T>,
+) -> Result<T, Error> {
+    let mut de = serde_json::Deserializer::from_str(text);
+    let result = f(&mut de)?;
+    de.end()?;
+    Ok(result)
+}
+
+/// The default for [`FromRowOptions::enum_tag_column`].
+#[cfg(feature = "json")]
+const TAG_COLUMN: &str = "kind";
+
+/// The default for [`FromRowOptions::enum_content_column`].
+#[cfg(feature = "json")]
+const CONTENT_COLUMN: &str = "data";
+
+#[cfg(feature = "json")]
+struct TaggedRowEnum<'de> {
+    kind: &'de str,
+    data: Option<&'de str>,
+    content_column: &'static str,
+}
+
+#[cfg(feature = "json")]
+impl<'de> de::EnumAccess<'de> for TaggedRowEnum<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(BorrowedStrDeserializer::<Error>::new(self.kind))?;
+        Ok((variant, self))
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'de> TaggedRowEnum<'de> {
+    fn deserialize_data<T>(
+        self,
+        f: impl FnOnce(
+            &mut serde_json::Deserializer<serde_json::de::StrRead<'de>>,
+        ) -> serde_json::Result<T>,
+    ) -> Result<T, Error> {
+        let data = self.data.ok_or_else(|| {
+            let column = self.content_column;
+            Error::Custom(format!("expected enum data in column {column:?}"))
+        })?;
+        deserialize_json(data, f)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'de> de::VariantAccess<'de> for TaggedRowEnum<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.deserialize_data(|de| seed.deserialize(de))
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_data(|de| de.deserialize_tuple(len, visitor))
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_data(|de| de.deserialize_struct("", fields, visitor))
     }
 }
 
 struct NamedRowMap<'de, 'stmt> {
     row: &'de Row<'stmt>,
     fields: &'static [&'static str],
+    options: FromRowOptions,
     next_index: usize,
 }
 
 impl<'de, 'stmt> NamedRowMap<'de, 'stmt> {
-    fn new(row: &'de Row<'stmt>, fields: &'static [&'static str]) -> Self {
+    fn new(row: &'de Row<'stmt>, fields: &'static [&'static str], options: FromRowOptions) -> Self {
         Self {
             row,
             fields,
+            options,
             next_index: 0,
         }
     }
@@ -311,15 +930,854 @@ impl<'de> MapAccess<'de> for NamedRowMap<'de, '_> {
     where
         V: DeserializeSeed<'de>,
     {
-        let value = self.row.get_ref(self.next_index - 1)?;
-        seed.deserialize(ValueRefDeserializer { value })
+        let name = self.fields[self.next_index - 1];
+        let index = self.row.as_ref().column_index(name)?;
+        let value = self.row.get_ref(index)?;
+        let options = self.options;
+        seed.deserialize(ValueRefDeserializer { value, options })
     }
 }
 
-pub fn from_row_via_name<'de, T>(row: &'de Row<'_>) -> rusqlite::Result<T>
-where
-    T: Deserialize<'de>,
-{
-    T::deserialize(NamedRowDeserializer { row })
-        .map_err(|err| FromSqlError::Other(Box::new(err)).into())
+/// Presents all columns of a row as a map from column name to value.
+struct ColumnMap<'de, 'stmt> {
+    row: &'de Row<'stmt>,
+    options: FromRowOptions,
+    next_index: usize,
+}
+
+impl<'de, 'stmt> ColumnMap<'de, 'stmt> {
+    fn new(row: &'de Row<'stmt>, options: FromRowOptions) -> Self {
+        Self {
+            row,
+            options,
+            next_index: 0,
+        }
+    }
+}
+
+impl<'de> MapAccess<'de> for ColumnMap<'de, '_> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let stmt: &'de Statement<'_> = self.row.as_ref();
+        if self.next_index >= stmt.column_count() {
+            return Ok(None);
+        }
+        let key = stmt.column_name(self.next_index)?;
+        self.next_index += 1;
+        seed.deserialize(BorrowedStrDeserializer::new(key))
+            .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let value = self.row.get_ref(self.next_index - 1)?;
+        let options = self.options;
+        seed.deserialize(ValueRefDeserializer { value, options })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.row.as_ref().column_count() - self.next_index)
+    }
+}
+
+/// Deserialize a row into a struct.
+///
+/// Each of the struct's fields is filled with the column of the same name,
+/// regardless of the order the columns were selected in. Column names are
+/// matched case-insensitively, and computed columns like `COUNT(*)` can be
+/// given a name via `AS`. Every field needs a column, but columns without a
+/// field are ignored unless [`FromRowOptions::strict_column_count`] is set.
+///
+/// The result may borrow from the row, so `TEXT` and `BLOB` columns can be
+/// deserialized into `&str` and `&[u8]` fields without copying. `Cow<'a, str>`
+/// and `Cow<'a, [u8]>` fields only borrow if they are marked with
+/// `#[serde(borrow)]`, otherwise serde always copies the data. This is also
+/// the case for `Option<Cow<'a, str>>`, regardless of `#[serde(borrow)]`.
+///
+/// With the `json` feature, enums can be deserialized from two columns: The
+/// variant name is read from a `TEXT` column named `kind`, and the variant's
+/// data, if any, from a JSON-encoded `TEXT` column named `data`. The `data`
+/// column may be missing or `NULL` for unit variants. Both column names can
+/// be changed via [`FromRowOptions`].
+///
+/// Maps like [`HashMap`](std::collections::HashMap) are filled with all
+/// columns, keyed by column name. The same happens for structs containing a
+/// `#[serde(flatten)]` field: Columns are matched to the other fields by name
+/// and all remaining columns end up in the flattened field, for example a
+/// `HashMap<String, serde_json::Value>`. Unlike regular structs, the number of
+/// columns isn't checked in that case.
+///
+/// Internally tagged enums (`#[serde(tag = "...")]`) read their tag from the
+/// column of that name and their fields from the other columns, so all variants
+/// can share a single table. Columns not used by a variant, like the fields of
+/// other variants, are ignored. Since serde buffers the columns before
+/// deserializing the variant, only fields whose type matches the column's
+/// storage class work in this case. For example, a `bool` field can't be read
+/// from an `INTEGER` column.
+pub fn from_row_via_name<'de, T>(row: &'de Row<'_>) -> rusqlite::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    from_row_via_name_with(row, FromRowOptions::default())
+}
+
+/// Like [`from_row_via_name`], but with custom [`FromRowOptions`].
+pub fn from_row_via_name_with<'de, T>(
+    row: &'de Row<'_>,
+    options: FromRowOptions,
+) -> rusqlite::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    T::deserialize(NamedRowDeserializer { row, options })
+        .map_err(|err| FromSqlError::Other(Box::new(err)).into())
+}
+
+/// Execute a query that must return exactly one row and deserialize that row
+/// via [`from_row_via_name`].
+///
+/// Returns [`rusqlite::Error::QueryReturnedNoRows`] if the query returns no
+/// rows and an error if it returns more than one row.
+pub fn query_row_into<T, P>(conn: &Connection, sql: &str, params: P) -> rusqlite::Result<T>
+where
+    T: DeserializeOwned,
+    P: Params,
+{
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query(params)?;
+    single_row_into(rows)
+}
+
+pub(crate) fn single_row_into<T: DeserializeOwned>(mut rows: Rows<'_>) -> rusqlite::Result<T> {
+    let row = rows.next()?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+    let result = from_row_via_name(row)?;
+    if rows.next()?.is_some() {
+        return Err(FromSqlError::Other(Box::new(Error::MultipleRows)).into());
+    }
+    Ok(result)
+}
+
+/// An [`Action`] performing [`query_row_into`].
+pub struct QueryRowInto<T> {
+    sql: String,
+    params: OwnedParams,
+    _output: PhantomData<fn() -> T>,
+}
+
+impl<T> QueryRowInto<T> {
+    pub fn new(sql: impl Into<String>, params: impl Into<OwnedParams>) -> Self {
+        Self {
+            sql: sql.into(),
+            params: params.into(),
+            _output: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Action for QueryRowInto<T> {
+    type Output = T;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let mut stmt = conn.prepare(&self.sql)?;
+        self.params.bind(&mut stmt)?;
+        let rows = stmt.raw_query();
+        single_row_into(rows)
+    }
+}
+
+/// Execute a query and deserialize the first column of every resulting row.
+///
+/// This is the single-column counterpart to
+/// [`QueryAll`](crate::actions::QueryAll), useful for queries like `SELECT id
+/// FROM ...`. The values are deserialized like the elements of a tuple in
+/// [`from_row_via_index`]. Returns an error if the query has no columns.
+/// Additional columns are ignored.
+pub fn query_scalar_all<T, P>(conn: &Connection, sql: &str, params: P) -> rusqlite::Result<Vec<T>>
+where
+    T: DeserializeOwned,
+    P: Params,
+{
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query(params)?;
+    scalar_rows_into(rows)
+}
+
+fn scalar_rows_into<T: DeserializeOwned>(mut rows: Rows<'_>) -> rusqlite::Result<Vec<T>> {
+    let column_count = rows.as_ref().map_or(0, |stmt| stmt.column_count());
+    if column_count == 0 {
+        let err = Error::ColumnCount {
+            expected: 1,
+            actual: 0,
+        };
+        return Err(FromSqlError::Other(Box::new(err)).into());
+    }
+
+    let mut result = vec![];
+    while let Some(row) = rows.next()? {
+        let value = row.get_ref(0)?;
+        let options = FromRowOptions::default();
+        let value = T::deserialize(ValueRefDeserializer { value, options })
+            .map_err(|err| FromSqlError::Other(Box::new(err)))?;
+        result.push(value);
+    }
+    Ok(result)
+}
+
+/// An [`Action`] performing [`query_scalar_all`].
+pub struct QueryScalarAll<T> {
+    sql: String,
+    params: OwnedParams,
+    _output: PhantomData<fn() -> T>,
+}
+
+impl<T> QueryScalarAll<T> {
+    pub fn new(sql: impl Into<String>, params: impl Into<OwnedParams>) -> Self {
+        Self {
+            sql: sql.into(),
+            params: params.into(),
+            _output: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Action for QueryScalarAll<T> {
+    type Output = Vec<T>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let mut stmt = conn.prepare(&self.sql)?;
+        self.params.bind(&mut stmt)?;
+        let rows = stmt.raw_query();
+        scalar_rows_into(rows)
+    }
+}
+
+/// Serializes a single value into a [`Value`].
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = Impossible<Value, Error>;
+    type SerializeTuple = Impossible<Value, Error>;
+    type SerializeTupleStruct = Impossible<Value, Error>;
+    type SerializeTupleVariant = Impossible<Value, Error>;
+    type SerializeMap = Impossible<Value, Error>;
+    type SerializeStruct = Impossible<Value, Error>;
+    type SerializeStructVariant = Impossible<Value, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        let v = i64::try_from(v).map_err(|_| Error::IntegerOutOfRange(v))?;
+        Ok(Value::Integer(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Real(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Real(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Text(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Text(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Blob(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(Value::Text(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        Err(Error::UnsupportedParam("enum variant with data"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(Error::UnsupportedParam("sequence"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(Error::UnsupportedParam("tuple"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(Error::UnsupportedParam("tuple struct"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(Error::UnsupportedParam("enum variant with data"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(Error::UnsupportedParam("map"))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Err(Error::UnsupportedParam("struct"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(Error::UnsupportedParam("enum variant with data"))
+    }
+}
+
+/// Serialize a single parameter, falling back to JSON for values that can't be
+/// represented as a single SQLite value.
+fn serialize_param<T: ?Sized + Serialize>(value: &T) -> Result<Value, Error> {
+    match value.serialize(ValueSerializer) {
+        #[cfg(feature = "json")]
+        Err(Error::UnsupportedParam(_)) => Ok(Value::Text(serde_json::to_string(value)?)),
+        result => result,
+    }
+}
+
+/// Prefix a parameter name with `:`, unless it already has a prefix.
+fn param_name(name: &str) -> String {
+    if name.starts_with([':', '@', '$']) {
+        name.to_string()
+    } else {
+        format!(":{name}")
+    }
+}
+
+/// Serializes a struct or map into named parameters.
+struct ParamsSerializer {
+    params: OwnedParams,
+    key: Option<String>,
+}
+
+impl SerializeStruct for ParamsSerializer {
+    type Ok = OwnedParams;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.params
+            .push_named(param_name(key), serialize_param(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.params)
+    }
+}
+
+impl SerializeMap for ParamsSerializer {
+    type Ok = OwnedParams;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Self::Error> {
+        match key.serialize(ValueSerializer)? {
+            Value::Text(key) => self.key = Some(param_name(&key)),
+            _ => return Err(Error::Custom("parameter names must be strings".to_string())),
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self
+            .key
+            .take()
+            .expect("serialize_value called before serialize_key");
+        self.params.push_named(key, serialize_param(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.params)
+    }
+}
+
+/// Accepts only structs and maps, serializing them via [`ParamsSerializer`].
+struct ToNamedParams;
+
+impl Serializer for ToNamedParams {
+    type Ok = OwnedParams;
+    type Error = Error;
+
+    type SerializeSeq = Impossible<OwnedParams, Error>;
+    type SerializeTuple = Impossible<OwnedParams, Error>;
+    type SerializeTupleStruct = Impossible<OwnedParams, Error>;
+    type SerializeTupleVariant = Impossible<OwnedParams, Error>;
+    type SerializeMap = ParamsSerializer;
+    type SerializeStruct = ParamsSerializer;
+    type SerializeStructVariant = Impossible<OwnedParams, Error>;
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<Self::Ok, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<Self::Ok, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_char(self, _v: char) -> Result<Self::Ok, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<Self::Ok, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<Self::Ok, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(OwnedParams::new())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(ParamsSerializer {
+            params: OwnedParams::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(ParamsSerializer {
+            params: OwnedParams::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(Error::ExpectedStructOrMap)
+    }
+}
+
+/// Serialize a struct or map into named [`OwnedParams`].
+///
+/// Every field becomes a parameter named after the field and prefixed with
+/// `:`, so a field `name` is bound to `:name`. Map keys that already start with
+/// `:`, `@` or `$` are used as they are. Since all parameters are bound, every
+/// field must appear in the statement.
+///
+/// Values are converted similarly to how they are deserialized: `bool`s become
+/// `0` or `1`, [`None`] and unit become `NULL`, unit enum variants become their
+/// name and bytes become a `BLOB`. Newtype structs are serialized as their
+/// contents. With the `json` feature, sequences, maps, structs and enum
+/// variants with data are stored as JSON text. Otherwise, they result in an
+/// error, as do integers that don't fit into an [`i64`].
+pub fn to_named_params<T: ?Sized + Serialize>(value: &T) -> rusqlite::Result<OwnedParams> {
+    value
+        .serialize(ToNamedParams)
+        .map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))
+}
+
+/// A [`SystemTime`] stored as a unix timestamp in whole seconds since
+/// 1970-01-01 00:00:00 UTC.
+///
+/// Deserializes from an `INTEGER` column and can be bound as a parameter via
+/// [`ToSql`]. Timestamps before 1970 are stored as negative numbers. Sub-second
+/// precision is lost when storing, rounding towards the past.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnixTimestamp(pub SystemTime);
+
+impl UnixTimestamp {
+    /// Create a timestamp from a number of seconds since the unix epoch.
+    ///
+    /// Returns `None` if the time can't be represented as a [`SystemTime`].
+    pub fn from_secs(secs: i64) -> Option<Self> {
+        let offset = Duration::from_secs(secs.unsigned_abs());
+        let time = if secs >= 0 {
+            UNIX_EPOCH.checked_add(offset)
+        } else {
+            UNIX_EPOCH.checked_sub(offset)
+        };
+        time.map(Self)
+    }
+
+    /// The number of whole seconds since the unix epoch.
+    ///
+    /// Returns `None` if the number doesn't fit into an `i64`.
+    pub fn as_secs(&self) -> Option<i64> {
+        match self.0.duration_since(UNIX_EPOCH) {
+            Ok(after) => i64::try_from(after.as_secs()).ok(),
+            Err(err) => {
+                let before = err.duration();
+                let secs = before.as_secs() + u64::from(before.subsec_nanos() > 0);
+                0_i64.checked_sub_unsigned(secs)
+            }
+        }
+    }
+}
+
+impl From<SystemTime> for UnixTimestamp {
+    fn from(value: SystemTime) -> Self {
+        Self(value)
+    }
+}
+
+impl From<UnixTimestamp> for SystemTime {
+    fn from(value: UnixTimestamp) -> Self {
+        value.0
+    }
+}
+
+impl<'de> Deserialize<'de> for UnixTimestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let secs = i64::deserialize(deserializer)?;
+        Self::from_secs(secs)
+            .ok_or_else(|| de::Error::custom(format!("timestamp {secs} is out of range")))
+    }
+}
+
+impl ToSql for UnixTimestamp {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        let secs = self.as_secs().ok_or_else(|| {
+            rusqlite::Error::ToSqlConversionFailure("timestamp is out of range".into())
+        })?;
+        Ok(ToSqlOutput::from(secs))
+    }
+}
+
+/// A list of `f64`s stored as a `BLOB` of packed 8 byte little-endian floats.
+///
+/// This is more compact and faster to (de)serialize than storing the numbers as
+/// JSON, for example for embeddings or coordinate arrays. The byte order is
+/// always little-endian, regardless of the platform, so the blobs can be read
+/// on any machine. SQL functions operating on the blob must expect the same
+/// format.
+///
+/// Deserializes from a `BLOB` column whose length must be a multiple of 8. It
+/// serializes into the packed bytes and can be bound as a parameter via
+/// [`ToSql`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct F64Blob(pub Vec<f64>);
+
+impl F64Blob {
+    /// The packed bytes of the blob.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    /// Unpack a blob, or return `None` if its length isn't a multiple of 8.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let chunks = bytes.chunks_exact(8);
+        if !chunks.remainder().is_empty() {
+            return None;
+        }
+        let values = chunks
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        Some(Self(values))
+    }
+}
+
+impl From<Vec<f64>> for F64Blob {
+    fn from(value: Vec<f64>) -> Self {
+        Self(value)
+    }
+}
+
+impl From<F64Blob> for Vec<f64> {
+    fn from(value: F64Blob) -> Self {
+        value.0
+    }
+}
+
+impl<'de> Deserialize<'de> for F64Blob {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct F64BlobVisitor;
+
+        impl Visitor<'_> for F64BlobVisitor {
+            type Value = F64Blob;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "a blob of packed f64s")
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                F64Blob::from_bytes(v)
+                    .ok_or_else(|| E::invalid_length(v.len(), &"a multiple of 8 bytes"))
+            }
+        }
+
+        deserializer.deserialize_bytes(F64BlobVisitor)
+    }
+}
+
+impl Serialize for F64Blob {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.to_bytes())
+    }
+}
+
+impl ToSql for F64Blob {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.to_bytes()))
+    }
+}
+
+/// A value stored as JSON in a `TEXT` column.
+///
+/// Deserializes by parsing the JSON text of a `TEXT` or `BLOB` column. It
+/// serializes into the inner value and can be bound as a parameter via
+/// [`ToSql`], in which case it is encoded as JSON text.
+///
+/// This is the recommended way to store fields like `Vec<i32>` or
+/// `HashMap<String, String>` as JSON, since it states the intent explicitly and
+/// also works for other types, like numbers or strings stored as JSON. To parse
+/// sequences, tuples, maps and structs from JSON text without this wrapper, set
+/// [`FromRowOptions::parse_json_text`].
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "json")]
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Json<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct JsonVisitor<T>(PhantomData<T>);
+
+        impl<T: DeserializeOwned> Visitor<'_> for JsonVisitor<T> {
+            type Value = Json<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "JSON text")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                serde_json::from_str(v).map(Json).map_err(E::custom)
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                serde_json::from_slice(v).map(Json).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(JsonVisitor(PhantomData))
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T: Serialize> Serialize for Json<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T: Serialize> ToSql for Json<T> {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        let json = serde_json::to_string(&self.0)
+            .map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?;
+        Ok(ToSqlOutput::from(json))
+    }
 }
@@ -4,9 +4,20 @@
 //! [`Migration`]s but don't need the additional guarantees and overhead of the
 //! other vaults.
 
-use rusqlite::Connection;
+#[cfg(feature = "sqlcipher")]
+use std::path::Path;
 
-use crate::{Action, Migration};
+#[cfg(feature = "polars")]
+use polars::prelude::DataFrame;
+#[cfg(any(feature = "polars", feature = "serde"))]
+use rusqlite::Params;
+use rusqlite::{Connection, Transaction, TransactionBehavior};
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+
+#[cfg(feature = "polars")]
+use crate::polars::DataFrameError;
+use crate::{Action, MigrateError, Migration, MigrationStep, SyncVault};
 
 /// A simple, single-threaded vault.
 ///
@@ -25,10 +36,29 @@ impl SimpleVault {
     /// - `journal_mode` to `"wal"`
     /// - `foreign_keys` to `true`
     /// - `trusted_schema` to `false`
-    pub fn new(conn: Connection, migrations: &[Migration]) -> rusqlite::Result<Self> {
+    pub fn new(conn: Connection, migrations: &[Migration]) -> Result<Self, MigrateError> {
         Self::new_and_prepare(conn, migrations, |_| Ok(()))
     }
 
+    /// Open an encrypted database, apply the key and create a new vault from
+    /// the resulting connection, applying the migrations in the process.
+    ///
+    /// The key is set via `PRAGMA key` before any other statement is run. The
+    /// key material is overwritten afterwards where possible.
+    ///
+    /// Requires rusqlite to be built with SQLCipher support, which the
+    /// `sqlcipher` feature enables.
+    #[cfg(feature = "sqlcipher")]
+    pub fn open_encrypted(
+        path: impl AsRef<Path>,
+        key: String,
+        migrations: &[Migration],
+    ) -> Result<Self, MigrateError> {
+        let conn = Connection::open(path)?;
+        crate::apply_key(&conn, key)?;
+        Self::new(conn, migrations)
+    }
+
     /// Create a new vault from an existing [`Connection`], applying the
     /// migrations in the process.
     ///
@@ -42,17 +72,193 @@ impl SimpleVault {
     /// - `foreign_keys` to `true`
     /// - `trusted_schema` to `false`
     pub fn new_and_prepare(
-        mut conn: Connection,
+        conn: Connection,
         migrations: &[Migration],
         prepare: impl FnOnce(&mut Connection) -> rusqlite::Result<()>,
-    ) -> rusqlite::Result<Self> {
-        crate::migrate(&mut conn, migrations)?;
-        prepare(&mut conn)?;
-        Ok(Self(conn))
+    ) -> Result<Self, MigrateError> {
+        Self::builder().build_and_prepare(conn, migrations, prepare)
+    }
+
+    /// Create a new vault from an existing [`Connection`], applying a mix of
+    /// transactional and non-transactional migrations in the process.
+    ///
+    /// See [`MigrationStep`] for how failures are handled.
+    pub fn new_mixed(conn: Connection, steps: &[MigrationStep]) -> Result<Self, MigrateError> {
+        Self::builder().build_mixed(conn, steps)
+    }
+
+    /// Create a [`SimpleVaultBuilder`] for configuring a vault before creating
+    /// it.
+    pub fn builder() -> SimpleVaultBuilder {
+        SimpleVaultBuilder::new()
+    }
+
+    /// Return the vault's underlying [`Connection`].
+    ///
+    /// All migrations have been applied to the connection.
+    pub fn into_connection(self) -> Connection {
+        self.0
     }
 
     /// Execute an [`Action`] and return the result.
     pub fn execute<A: Action>(&mut self, action: A) -> Result<A::Output, A::Error> {
         action.run(&mut self.0)
     }
+
+    /// Run a closure inside a transaction.
+    ///
+    /// The transaction is committed if the closure returns [`Ok`] and rolled
+    /// back if it returns [`Err`].
+    pub fn transaction<F, T, E>(&mut self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&Transaction<'_>) -> Result<T, E>,
+        E: From<rusqlite::Error>,
+    {
+        self.transaction_with_behavior(TransactionBehavior::Deferred, f)
+    }
+
+    /// Run a closure inside a transaction with the given
+    /// [`TransactionBehavior`].
+    ///
+    /// A [`TransactionBehavior::Deferred`] transaction, as started by
+    /// [`Self::transaction`], only acquires locks once the database is first
+    /// read or written. If another connection writes in the meantime, upgrading
+    /// to a write lock fails with `SQLITE_BUSY`, regardless of the busy
+    /// timeout. Transactions that write should use
+    /// [`TransactionBehavior::Immediate`] to acquire the write lock right away,
+    /// waiting for other writers according to the busy timeout.
+    /// [`TransactionBehavior::Exclusive`] additionally prevents other
+    /// connections from reading outside of WAL mode and is rarely needed.
+    pub fn transaction_with_behavior<F, T, E>(
+        &mut self,
+        behavior: TransactionBehavior,
+        f: F,
+    ) -> Result<T, E>
+    where
+        F: FnOnce(&Transaction<'_>) -> Result<T, E>,
+        E: From<rusqlite::Error>,
+    {
+        let tx = self.0.transaction_with_behavior(behavior)?;
+        let result = f(&tx)?;
+        tx.commit()?;
+        Ok(result)
+    }
+
+    /// Execute a query that must return exactly one row and deserialize that
+    /// row via [`from_row_via_name`](crate::from_row_via_name).
+    ///
+    /// See [`query_row_into`](crate::query_row_into) for more details.
+    #[cfg(feature = "serde")]
+    pub fn query_row_into<T, P>(&mut self, sql: &str, params: P) -> rusqlite::Result<T>
+    where
+        T: DeserializeOwned,
+        P: Params,
+    {
+        crate::serde::query_row_into(&self.0, sql, params)
+    }
+
+    /// Execute a query and deserialize the first column of every resulting
+    /// row.
+    ///
+    /// See [`query_scalar_all`](crate::query_scalar_all) for more details.
+    #[cfg(feature = "serde")]
+    pub fn query_scalar_all<T, P>(&mut self, sql: &str, params: P) -> rusqlite::Result<Vec<T>>
+    where
+        T: DeserializeOwned,
+        P: Params,
+    {
+        crate::serde::query_scalar_all(&self.0, sql, params)
+    }
+
+    /// Execute a query and read its result into a [`DataFrame`].
+    ///
+    /// See [`rows_to_dataframe`](crate::rows_to_dataframe) for more details.
+    #[cfg(feature = "polars")]
+    pub fn query_dataframe<P: Params>(
+        &mut self,
+        sql: &str,
+        params: P,
+    ) -> Result<DataFrame, DataFrameError> {
+        crate::polars::query_dataframe(&self.0, sql, params)
+    }
+}
+
+impl SyncVault for SimpleVault {
+    type Error<E> = E;
+
+    fn execute<A: Action>(&mut self, action: A) -> Result<A::Output, A::Error> {
+        Self::execute(self, action)
+    }
+}
+
+// Since Connection isn't Sync, neither is this vault, but it can still be
+// moved to other threads.
+const _: () = {
+    fn assert_send<T: Send>() {}
+    let _ = assert_send::<SimpleVault>;
+};
+
+/// A builder for configuring a [`SimpleVault`] before creating it.
+#[derive(Debug, Clone, Default)]
+pub struct SimpleVaultBuilder {
+    application_id: Option<i32>,
+}
+
+impl SimpleVaultBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require the database to have a specific `application_id`.
+    ///
+    /// Before applying the migrations, the database's `application_id` is
+    /// compared to the given one. If it differs, creating the vault fails with
+    /// [`MigrateError::ApplicationIdMismatch`] and the database is left
+    /// untouched. If it has never been set, it is set to the given one in the
+    /// same transaction as the migrations, so databases whose migrations fail
+    /// are not claimed.
+    pub fn with_application_id(mut self, application_id: i32) -> Self {
+        self.application_id = Some(application_id);
+        self
+    }
+
+    /// Create a new vault from an existing [`Connection`], applying the
+    /// migrations in the process.
+    ///
+    /// See [`SimpleVault::new`] for more details.
+    pub fn build(
+        self,
+        conn: Connection,
+        migrations: &[Migration],
+    ) -> Result<SimpleVault, MigrateError> {
+        self.build_and_prepare(conn, migrations, |_| Ok(()))
+    }
+
+    /// Create a new vault from an existing [`Connection`], applying the
+    /// migrations in the process.
+    ///
+    /// See [`SimpleVault::new_and_prepare`] for more details.
+    pub fn build_and_prepare(
+        self,
+        mut conn: Connection,
+        migrations: &[Migration],
+        prepare: impl FnOnce(&mut Connection) -> rusqlite::Result<()>,
+    ) -> Result<SimpleVault, MigrateError> {
+        crate::migrate(&mut conn, migrations, None, self.application_id)?;
+        prepare(&mut conn)?;
+        Ok(SimpleVault(conn))
+    }
+
+    /// Create a new vault from an existing [`Connection`], applying a mix of
+    /// transactional and non-transactional migrations in the process.
+    ///
+    /// See [`SimpleVault::new_mixed`] for more details.
+    pub fn build_mixed(
+        self,
+        mut conn: Connection,
+        steps: &[MigrationStep],
+    ) -> Result<SimpleVault, MigrateError> {
+        crate::migrate_steps(&mut conn, steps, None, self.application_id)?;
+        Ok(SimpleVault(conn))
+    }
 }
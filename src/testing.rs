@@ -0,0 +1,115 @@
+//! Helpers for testing [`Action`]s and [`Migration`]s.
+//!
+//! The [`TestVault`] runs on a fresh in-memory database. Since every
+//! [`TestVault`] (and every [`TestVault::reset`]) starts with an empty
+//! database, rowids and other automatically assigned values are the same on
+//! every test run.
+
+use std::fmt;
+
+use rusqlite::Connection;
+
+use crate::{actions::Count, simple::SimpleVault, Action, MigrateError, Migration, SyncVault};
+
+/// A single-threaded vault backed by a fresh in-memory database, intended for
+/// tests.
+///
+/// The pragmas recommended for the other vaults are set before applying the
+/// migrations, except for `journal_mode`, which is always `memory` for
+/// in-memory databases.
+#[derive(Debug)]
+pub struct TestVault {
+    vault: SimpleVault,
+    migrations: Vec<Migration>,
+}
+
+impl TestVault {
+    /// Create a new vault on a fresh in-memory database, applying the
+    /// migrations in the process.
+    pub fn new(migrations: &[Migration]) -> Result<Self, MigrateError> {
+        let vault = Self::open(migrations)?;
+        Ok(Self {
+            vault,
+            migrations: migrations.to_vec(),
+        })
+    }
+
+    fn open(migrations: &[Migration]) -> Result<SimpleVault, MigrateError> {
+        let conn = Connection::open_in_memory()?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        conn.pragma_update(None, "trusted_schema", false)?;
+        SimpleVault::new(conn, migrations)
+    }
+
+    /// Replace the database with a fresh in-memory database and apply the
+    /// migrations again.
+    ///
+    /// This is useful for reusing a vault across multiple test cases.
+    pub fn reset(&mut self) -> Result<(), MigrateError> {
+        self.vault = Self::open(&self.migrations)?;
+        Ok(())
+    }
+
+    /// Execute an [`Action`] and return the result.
+    pub fn execute<A: Action>(&mut self, action: A) -> Result<A::Output, A::Error> {
+        self.vault.execute(action)
+    }
+
+    /// Execute an [`Action`] and return its output, panicking if it fails.
+    #[track_caller]
+    pub fn assert_ok<A>(&mut self, action: A) -> A::Output
+    where
+        A: Action,
+        A::Error: fmt::Debug,
+    {
+        match self.execute(action) {
+            Ok(output) => output,
+            Err(err) => panic!("expected action to succeed, but it failed: {err:?}"),
+        }
+    }
+
+    /// Execute an [`Action`] and return its error, panicking if it succeeds.
+    #[track_caller]
+    pub fn assert_err<A>(&mut self, action: A) -> A::Error
+    where
+        A: Action,
+        A::Output: fmt::Debug,
+    {
+        match self.execute(action) {
+            Ok(output) => panic!("expected action to fail, but it succeeded: {output:?}"),
+            Err(err) => err,
+        }
+    }
+
+    /// Assert that a table contains exactly `expected` rows.
+    #[track_caller]
+    pub fn assert_row_count(&mut self, table: &str, expected: u64) {
+        let table = table.replace('"', "\"\"");
+        let sql = format!("SELECT COUNT(*) FROM \"{table}\"");
+        let actual = self
+            .vault
+            .execute(Count::new(sql, ()))
+            .expect("failed to count rows");
+        assert_eq!(actual, expected, "unexpected row count in table {table:?}");
+    }
+}
+
+/// Create a [`TestVault`] on a fresh in-memory database, applying the
+/// migrations in the process.
+///
+/// Panics if the migrations fail.
+#[track_caller]
+pub fn in_memory_vault(migrations: &[Migration]) -> TestVault {
+    match TestVault::new(migrations) {
+        Ok(vault) => vault,
+        Err(err) => panic!("failed to create test vault: {err}"),
+    }
+}
+
+impl SyncVault for TestVault {
+    type Error<E> = E;
+
+    fn execute<A: Action>(&mut self, action: A) -> Result<A::Output, A::Error> {
+        Self::execute(self, action)
+    }
+}
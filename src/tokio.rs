@@ -1,21 +1,73 @@
 //! A vault for use with [`tokio`].
 
-use std::{any::Any, error, fmt, thread};
+#[cfg(feature = "collation")]
+use std::cmp::Ordering;
+#[cfg(any(feature = "functions", feature = "hooks"))]
+use std::ffi::c_int;
+#[cfg(feature = "functions")]
+use std::panic::{RefUnwindSafe, UnwindSafe};
+#[cfg(feature = "load_extension")]
+use std::path::PathBuf;
+#[cfg(any(feature = "functions", feature = "collation", feature = "hooks"))]
+use std::sync::MutexGuard;
+#[cfg(any(feature = "periodic", feature = "trace"))]
+use std::time::Duration;
+use std::{
+    any::{type_name, Any},
+    convert::Infallible,
+    error, fmt,
+    future::Future,
+    path::Path,
+    pin::pin,
+    sync::{
+        atomic::{self, AtomicBool, AtomicUsize},
+        Arc, Mutex,
+    },
+    thread,
+};
+#[cfg(feature = "blob")]
+use std::{io, mem};
 
-use rusqlite::Connection;
-use tokio::sync::{mpsc, oneshot};
+#[cfg(feature = "polars")]
+use polars::prelude::DataFrame;
+#[cfg(feature = "functions")]
+use rusqlite::functions::{Aggregate, Context, FunctionFlags, SqlFnOutput};
+#[cfg(feature = "blob")]
+use rusqlite::DatabaseName;
+#[cfg(feature = "load_extension")]
+use rusqlite::LoadExtensionGuard;
+use rusqlite::{ffi, Connection, ErrorCode, OpenFlags, Row, ToSql, TransactionBehavior};
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "periodic")]
+use tokio::{runtime::Handle, time::MissedTickBehavior};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::{self, Instant},
+};
+#[cfg(feature = "serde")]
+use tokio_stream::{wrappers::ReceiverStream, Stream};
 
-use crate::{Action, Migration};
+#[cfg(feature = "serde")]
+use crate::actions::{BulkInsert, BulkInsertError};
+#[cfg(feature = "polars")]
+use crate::polars::{DataFrameError, QueryDataFrame};
+use crate::{
+    actions::{HealthCheckAction, HealthReport, OptimizeAction},
+    simple::SimpleVault,
+    Action, AsyncVault, MigrateError, Migration, MigrationStep, MigrationVerifier, OwnedParams,
+};
+
+/// The type-erased result of an [`Action`] sent back by the vault's thread.
+type ActionResult = Result<Box<dyn Any + Send>, Box<dyn Any + Send>>;
 
 /// Wrapper trait around [`Action`] that turns `Box<Self>` into a `Self` and the
 /// action's return type into `Box<dyn Any + Send>`.
 ///
 /// This way, the trait that users of this crate interact with is kept simpler.
 trait ActionWrapper {
-    fn run(
-        self: Box<Self>,
-        conn: &mut Connection,
-    ) -> Result<Box<dyn Any + Send>, Box<dyn Any + Send>>;
+    fn run(self: Box<Self>, conn: &mut Connection) -> ActionResult;
+    fn name(&self) -> &'static str;
 }
 
 impl<T: Action> ActionWrapper for T
@@ -23,40 +75,77 @@ where
     T::Output: Send + 'static,
     T::Error: Send + 'static,
 {
-    fn run(
-        self: Box<Self>,
-        conn: &mut Connection,
-    ) -> Result<Box<dyn Any + Send>, Box<dyn Any + Send>> {
+    fn run(self: Box<Self>, conn: &mut Connection) -> ActionResult {
         match (*self).run(conn) {
             Ok(result) => Ok(Box::new(result)),
             Err(err) => Err(Box::new(err)),
         }
     }
+
+    fn name(&self) -> &'static str {
+        Action::name(self)
+    }
 }
 
 /// Command to be sent via the mpsc channel to the vault thread.
 enum Command {
     Action(
         Box<dyn ActionWrapper + Send>,
-        oneshot::Sender<Result<Box<dyn Any + Send>, Box<dyn Any + Send>>>,
+        oneshot::Sender<rusqlite::Result<ActionResult>>,
     ),
+    Detached(Box<dyn ActionWrapper + Send>),
     Stop(oneshot::Sender<()>),
+    /// Sent after every high priority command so the vault's thread notices it
+    /// while waiting for normal commands.
+    Wake,
+}
+
+/// Priority of an [`Action`] executed via [`TokioVault::execute_priority`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Actions are executed in the order they were queued in.
+    #[default]
+    Normal,
+    /// Actions are executed before any queued [`Priority::Normal`] actions.
+    High,
 }
 
 /// Error that can occur during execution of an [`Action`].
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error<E> {
     /// The vault's thread has been stopped and its sqlite connection closed.
     Stopped,
+    /// The vault already has as many actions in flight as it allows, so the
+    /// action was rejected. See [`TokioVault::try_execute`].
+    QueueFull,
     /// An error was returned by the [`Action`].
     Action(E),
+    /// The transaction wrapping the [`Action`] could not be started or
+    /// committed. See [`TokioVaultBuilder::with_transaction_per_action`].
+    Transaction(rusqlite::Error),
+    /// The deadline passed before the [`Action`] started running, so it was
+    /// not run at all. See [`TokioVault::execute_deadline`].
+    DeadlineExceeded,
+    /// The vault's thread returned a value whose type did not match the
+    /// [`Action`]'s output or error type.
+    ///
+    /// This indicates a bug in the vault and should never happen. The
+    /// contained string is the name of the expected type.
+    TypeMismatch(&'static str),
 }
 
 impl<E: fmt::Display> fmt::Display for Error<E> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Stopped => "vault has been stopped".fmt(f),
+            Self::QueueFull => "vault has too many actions in flight".fmt(f),
+            Self::DeadlineExceeded => "deadline exceeded before action started".fmt(f),
             Self::Action(err) => err.fmt(f),
+            Self::Transaction(err) => err.fmt(f),
+            Self::TypeMismatch(expected) => {
+                write!(f, "vault returned value of wrong type, expected {expected}")
+            }
         }
     }
 }
@@ -65,43 +154,849 @@ impl<E: error::Error> error::Error for Error<E> {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self {
             Self::Stopped => None,
+            Self::QueueFull => None,
+            Self::DeadlineExceeded => None,
             Self::Action(err) => err.source(),
+            Self::Transaction(err) => err.source(),
+            Self::TypeMismatch(_) => None,
+        }
+    }
+}
+
+impl<E> Error<E> {
+    /// The error returned by the [`Action`], if any.
+    pub fn action_ref(&self) -> Option<&E> {
+        match self {
+            Self::Action(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl Error<rusqlite::Error> {
+    /// Convert into a [`rusqlite::Error`], for actions whose error type is
+    /// [`rusqlite::Error`].
+    ///
+    /// Errors returned by the action or the transaction wrapping it are passed
+    /// through unchanged. The other
+    /// variants are converted into a [`rusqlite::Error::SqliteFailure`]
+    /// containing this error's message and the following error code:
+    /// - [`Error::Stopped`] becomes [`ErrorCode::ApiMisuse`]
+    /// - [`Error::QueueFull`] becomes [`ErrorCode::DatabaseBusy`]
+    /// - [`Error::DeadlineExceeded`] becomes [`ErrorCode::OperationInterrupted`]
+    /// - [`Error::TypeMismatch`] becomes [`ErrorCode::InternalMalfunction`]
+    pub fn into_rusqlite(self) -> rusqlite::Error {
+        let code = match self {
+            Self::Action(err) | Self::Transaction(err) => return err,
+            Self::Stopped => ffi::SQLITE_MISUSE,
+            Self::QueueFull => ffi::SQLITE_BUSY,
+            Self::DeadlineExceeded => ffi::SQLITE_INTERRUPT,
+            Self::TypeMismatch(_) => ffi::SQLITE_INTERNAL,
+        };
+        rusqlite::Error::SqliteFailure(ffi::Error::new(code), Some(self.to_string()))
+    }
+}
+
+impl From<Error<Self>> for rusqlite::Error {
+    fn from(value: Error<Self>) -> Self {
+        value.into_rusqlite()
+    }
+}
+
+/// An [`Action`] that streams the rows of a query over a bounded channel.
+///
+/// Instead of collecting all rows into a [`Vec`], each row is mapped and sent
+/// to the corresponding [`mpsc::Receiver`] as soon as it has been read. If the
+/// channel is full, the action waits until there is space again. If the
+/// receiver is dropped, the action stops early.
+///
+/// The vault's thread is occupied for the entire duration of the stream, so no
+/// other actions can run until all rows have been sent or the receiver has been
+/// dropped. Consume the stream promptly.
+///
+/// Errors are sent over the channel as well, after which the stream ends.
+pub struct StreamAction<T, F> {
+    sql: String,
+    params: OwnedParams,
+    buffer: usize,
+    map: F,
+    tx: mpsc::Sender<rusqlite::Result<T>>,
+}
+
+impl<T, F> StreamAction<T, F>
+where
+    F: FnMut(&Row<'_>) -> rusqlite::Result<T>,
+{
+    /// Create a new action along with the receiver its rows will be sent to.
+    ///
+    /// The `buffer` parameter is the capacity of the channel. A `buffer` of
+    /// zero results in a stream containing only an error.
+    pub fn new(
+        sql: impl Into<String>,
+        params: impl Into<OwnedParams>,
+        buffer: usize,
+        map: F,
+    ) -> (Self, mpsc::Receiver<rusqlite::Result<T>>) {
+        // mpsc::channel panics if its capacity is zero
+        let (tx, rx) = mpsc::channel(buffer.max(1));
+        let action = Self {
+            sql: sql.into(),
+            params: params.into(),
+            buffer,
+            map,
+            tx,
+        };
+        (action, rx)
+    }
+
+    fn stream(&mut self, conn: &mut Connection) -> rusqlite::Result<()> {
+        if self.buffer == 0 {
+            return Err(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_MISUSE),
+                Some("buffer size must be greater than zero".to_string()),
+            ));
+        }
+
+        let mut stmt = conn.prepare(&self.sql)?;
+        self.params.bind(&mut stmt)?;
+        let mut rows = stmt.raw_query();
+        while let Some(row) = rows.next()? {
+            let result = (self.map)(row);
+            let failed = result.is_err();
+            if self.tx.blocking_send(result).is_err() || failed {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T, F> Action for StreamAction<T, F>
+where
+    F: FnMut(&Row<'_>) -> rusqlite::Result<T>,
+{
+    type Output = ();
+    type Error = Infallible;
+
+    fn run(mut self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        if let Err(err) = self.stream(conn) {
+            let _ = self.tx.blocking_send(Err(err));
+        }
+        Ok(())
+    }
+}
+
+/// The number of chunks buffered between the vault's thread and a
+/// [`BlobReader`] or [`BlobWriter`].
+#[cfg(feature = "blob")]
+const BLOB_CHANNEL_CAPACITY: usize = 2;
+
+/// An [`Action`] that reads a blob incrementally and sends it to a
+/// [`BlobReader`] in chunks.
+///
+/// The blob is opened via [`Connection::blob_open`] and read in chunks of
+/// `chunk_size` bytes, so at most a few chunks are held in memory at a time.
+/// The last chunk may be smaller. Once the entire blob has been sent, the
+/// reader reaches its end. If an error occurs, it is returned by the reader
+/// and no further chunks are sent. If the reader is dropped, the action stops
+/// early.
+///
+/// The vault's thread is occupied until the blob has been read entirely or the
+/// reader has been dropped, so no other actions can run in the meantime.
+#[cfg(feature = "blob")]
+pub struct BlobReadAction {
+    table: String,
+    column: String,
+    row_id: i64,
+    chunk_size: usize,
+    tx: mpsc::Sender<rusqlite::Result<Vec<u8>>>,
+}
+
+#[cfg(feature = "blob")]
+impl BlobReadAction {
+    /// Create a new action reading the blob in `column` of the row with the
+    /// given `row_id` in `table`, along with the reader its chunks will be
+    /// sent to.
+    ///
+    /// The `chunk_size` parameter must be greater than zero.
+    pub fn new(
+        table: impl Into<String>,
+        column: impl Into<String>,
+        row_id: i64,
+        chunk_size: usize,
+    ) -> (Self, BlobReader) {
+        assert!(chunk_size > 0, "chunk size must be greater than zero");
+        let (tx, rx) = mpsc::channel(BLOB_CHANNEL_CAPACITY);
+        let action = Self {
+            table: table.into(),
+            column: column.into(),
+            row_id,
+            chunk_size,
+            tx,
+        };
+        let reader = BlobReader {
+            rx,
+            chunk: vec![],
+            pos: 0,
+        };
+        (action, reader)
+    }
+
+    fn stream(&self, conn: &mut Connection) -> rusqlite::Result<()> {
+        let blob = conn.blob_open(
+            DatabaseName::Main,
+            &self.table,
+            &self.column,
+            self.row_id,
+            true,
+        )?;
+        let mut offset = 0;
+        while offset < blob.len() {
+            let mut chunk = vec![0; self.chunk_size.min(blob.len() - offset)];
+            blob.read_at_exact(&mut chunk, offset)?;
+            offset += chunk.len();
+            if self.tx.blocking_send(Ok(chunk)).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "blob")]
+impl Action for BlobReadAction {
+    type Output = ();
+    type Error = Infallible;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        if let Err(err) = self.stream(conn) {
+            let _ = self.tx.blocking_send(Err(err));
+        }
+        Ok(())
+    }
+}
+
+/// Reads a blob sent in chunks by a [`BlobReadAction`].
+///
+/// Reading blocks until the next chunk is available, so it must not be done
+/// from within an asynchronous context. Use [`tokio::task::spawn_blocking`]
+/// instead.
+#[cfg(feature = "blob")]
+pub struct BlobReader {
+    rx: mpsc::Receiver<rusqlite::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+#[cfg(feature = "blob")]
+impl io::Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.chunk.len() {
+            match self.rx.blocking_recv() {
+                Some(Ok(chunk)) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                Some(Err(err)) => return Err(io::Error::other(err)),
+                None => return Ok(0),
+            }
+        }
+        let remaining = &self.chunk[self.pos..];
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// An [`Action`] that receives chunks from a [`BlobWriter`] and writes them to
+/// a blob incrementally.
+///
+/// The blob is opened via [`Connection::blob_open`] and must already exist.
+/// Incremental I/O can't change the size of a blob, so it should be created
+/// with the final size beforehand, for example via `zeroblob(n)`. Writing past
+/// the end of the blob fails.
+///
+/// The chunks are written in the order they were sent, starting at the
+/// beginning of the blob. Once the writer has been dropped, the action returns
+/// the total number of bytes written. If an error occurs, the action stops and
+/// further writes to the writer fail with [`io::ErrorKind::BrokenPipe`].
+///
+/// The vault's thread is occupied until the writer has been dropped, so no
+/// other actions can run in the meantime. The action must be executed
+/// concurrently with writing, otherwise the writer blocks once its buffer is
+/// full.
+#[cfg(feature = "blob")]
+pub struct BlobWriteAction {
+    table: String,
+    column: String,
+    row_id: i64,
+    rx: mpsc::Receiver<Vec<u8>>,
+}
+
+#[cfg(feature = "blob")]
+impl BlobWriteAction {
+    /// Create a new action writing to the blob in `column` of the row with
+    /// the given `row_id` in `table`, along with the writer its chunks are
+    /// received from.
+    ///
+    /// The writer sends a chunk whenever it has buffered `chunk_size` bytes,
+    /// which must be greater than zero.
+    pub fn new(
+        table: impl Into<String>,
+        column: impl Into<String>,
+        row_id: i64,
+        chunk_size: usize,
+    ) -> (Self, BlobWriter) {
+        assert!(chunk_size > 0, "chunk size must be greater than zero");
+        let (tx, rx) = mpsc::channel(BLOB_CHANNEL_CAPACITY);
+        let action = Self {
+            table: table.into(),
+            column: column.into(),
+            row_id,
+            rx,
+        };
+        let writer = BlobWriter {
+            tx,
+            buf: Vec::with_capacity(chunk_size),
+            chunk_size,
+        };
+        (action, writer)
+    }
+}
+
+#[cfg(feature = "blob")]
+impl Action for BlobWriteAction {
+    type Output = usize;
+    type Error = rusqlite::Error;
+
+    fn run(mut self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let mut blob = conn.blob_open(
+            DatabaseName::Main,
+            &self.table,
+            &self.column,
+            self.row_id,
+            false,
+        )?;
+        let mut offset = 0;
+        while let Some(chunk) = self.rx.blocking_recv() {
+            blob.write_all_at(&chunk, offset)?;
+            offset += chunk.len();
+        }
+        blob.close()?;
+        Ok(offset)
+    }
+}
+
+/// Writes a blob in chunks via a [`BlobWriteAction`].
+///
+/// Data is buffered until a full chunk is available or the writer is flushed.
+/// Dropping the writer flushes it and signals the action that the blob is
+/// complete. Writing blocks while the action is busy, so it must not be done
+/// from within an asynchronous context. Use [`tokio::task::spawn_blocking`]
+/// instead.
+#[cfg(feature = "blob")]
+pub struct BlobWriter {
+    tx: mpsc::Sender<Vec<u8>>,
+    buf: Vec<u8>,
+    chunk_size: usize,
+}
+
+#[cfg(feature = "blob")]
+impl BlobWriter {
+    fn send_chunk(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = mem::replace(&mut self.buf, Vec::with_capacity(self.chunk_size));
+        self.tx
+            .blocking_send(chunk)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "blob write action has stopped"))
+    }
+}
+
+#[cfg(feature = "blob")]
+impl io::Write for BlobWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.chunk_size - self.buf.len());
+        self.buf.extend_from_slice(&buf[..len]);
+        if self.buf.len() >= self.chunk_size {
+            self.send_chunk()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_chunk()
+    }
+}
+
+#[cfg(feature = "blob")]
+impl Drop for BlobWriter {
+    fn drop(&mut self) {
+        let _ = self.send_chunk();
+    }
+}
+
+/// The progress of an action executed via [`TokioVault::execute_deadline`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeadlineState {
+    Queued,
+    Running,
+    Finished,
+    /// The deadline passed while the action was still queued, so it must not
+    /// run anymore.
+    Expired,
+}
+
+/// An [`Action`] that keeps track of its [`DeadlineState`], used by
+/// [`TokioVault::execute_deadline`].
+struct DeadlineAction<A> {
+    action: A,
+    state: Arc<Mutex<DeadlineState>>,
+}
+
+impl<A: Action> Action for DeadlineAction<A> {
+    type Output = Option<A::Output>;
+    type Error = A::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        {
+            let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+            if *state == DeadlineState::Expired {
+                return Ok(None);
+            }
+            *state = DeadlineState::Running;
+        }
+        let result = self.action.run(conn);
+        *self.state.lock().unwrap_or_else(|err| err.into_inner()) = DeadlineState::Finished;
+        result.map(Some)
+    }
+
+    fn name(&self) -> &'static str {
+        self.action.name()
+    }
+}
+
+/// An [`Action`] running an arbitrary closure, used by
+/// [`TokioVault::with_connection`].
+struct ClosureAction<F>(F);
+
+impl<F, T> Action for ClosureAction<F>
+where
+    F: FnOnce(&mut Connection) -> T,
+{
+    type Output = T;
+    type Error = Infallible;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        Ok((self.0)(conn))
+    }
+}
+
+/// The error of an [`Action`] executed via [`TokioVault::execute_detached`].
+pub type DetachedError = Box<dyn error::Error + Send>;
+
+type ErrorSink = Box<dyn FnMut(DetachedError) + Send>;
+
+/// An [`Action`] whose error is boxed, used by [`TokioVault::execute_detached`].
+struct DetachedAction<A>(A);
+
+impl<A> Action for DetachedAction<A>
+where
+    A: Action,
+    A::Error: error::Error + Send + 'static,
+{
+    type Output = ();
+    type Error = DetachedError;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        match self.0.run(conn) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(Box::new(err)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        self.0.name()
+    }
+}
+
+/// Opens new connections for a [`TokioVault`] whose connection has failed.
+///
+/// See [`TokioVaultBuilder::with_connection_factory`].
+pub trait ConnectionFactory: Send {
+    /// Open a new connection to the vault's database.
+    ///
+    /// The connection is then configured via the settings of the
+    /// [`TokioVaultBuilder`], so only settings outside of the builder need to
+    /// be applied here. Migrations are not applied to the new connection.
+    fn open(&self) -> rusqlite::Result<Connection>;
+}
+
+impl<F> ConnectionFactory for F
+where
+    F: Fn() -> rusqlite::Result<Connection> + Send,
+{
+    fn open(&self) -> rusqlite::Result<Connection> {
+        self()
+    }
+}
+
+/// Whether an error indicates that the connection is no longer usable and
+/// should be reopened.
+///
+/// These are the errors `SQLITE_CORRUPT` and `SQLITE_NOTADB`, as well as
+/// `SQLITE_READONLY_DBMOVED`, which occurs when the database file has been
+/// moved or replaced. I/O errors are often transient, for example when the disk
+/// is temporarily full, so they don't cause a reopen.
+fn is_fatal(err: &rusqlite::Error) -> bool {
+    let rusqlite::Error::SqliteFailure(err, _) = err else {
+        return false;
+    };
+    matches!(
+        err.code,
+        ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase
+    ) || err.extended_code == ffi::SQLITE_READONLY_DBMOVED
+}
+
+/// Whether an error indicates that the disk is full or failing, so that the
+/// vault should be marked as degraded.
+///
+/// These are the errors `SQLITE_FULL` and `SQLITE_IOERR`.
+fn is_disk_error(err: &rusqlite::Error) -> bool {
+    let rusqlite::Error::SqliteFailure(err, _) = err else {
+        return false;
+    };
+    matches!(err.code, ErrorCode::DiskFull | ErrorCode::SystemIoFailure)
+}
+
+/// Settings for the vault's thread, configured via [`TokioVaultBuilder`].
+struct RunOptions {
+    error_sink: Option<ErrorSink>,
+    optimize_on_stop: bool,
+    checkpoint_on_stop: bool,
+    transaction_per_action: bool,
+    transaction_behavior: Option<TransactionBehavior>,
+    factory: Option<Box<dyn ConnectionFactory>>,
+    config: ConnectionConfig,
+    interrupt: InterruptHandle,
+    degraded: Arc<AtomicBool>,
+    #[cfg(feature = "debug_current_action")]
+    current_action: Arc<Mutex<Option<&'static str>>>,
+}
+
+impl RunOptions {
+    /// Clear the degraded flag if an action has successfully written to the
+    /// database.
+    ///
+    /// Writes are detected by comparing the connection's total number of
+    /// changes to the one before the action ran.
+    fn handle_success(&self, conn: &Connection, total_changes_before: u64) {
+        if conn.total_changes() != total_changes_before
+            && self.degraded.swap(false, atomic::Ordering::Relaxed)
+        {
+            info!("Vault recovered after successful write");
+        }
+    }
+
+    /// Mark the vault as degraded if the error is a disk error, and replace the
+    /// connection with a new one if the error is fatal and a
+    /// [`ConnectionFactory`] is available.
+    fn handle_error(&self, conn: &mut Connection, err: Option<&rusqlite::Error>) {
+        let Some(err) = err else {
+            return;
+        };
+
+        if is_disk_error(err) && !self.degraded.swap(true, atomic::Ordering::Relaxed) {
+            warn!("Vault degraded after disk error: {err}");
         }
+
+        let Some(factory) = &self.factory else {
+            return;
+        };
+        if !is_fatal(err) {
+            return;
+        }
+
+        warn!("Reopening connection after fatal error: {err}");
+        let mut new_conn = match factory.open() {
+            Ok(new_conn) => new_conn,
+            Err(err) => {
+                warn!("Failed to reopen connection: {err}");
+                return;
+            }
+        };
+        if let Err(err) = self.config.reopened(&mut new_conn) {
+            warn!("Failed to configure reopened connection: {err}");
+            return;
+        }
+        self.interrupt.replace(new_conn.get_interrupt_handle());
+        *conn = new_conn;
+    }
+}
+
+fn close(mut conn: Connection, options: &RunOptions) {
+    if options.optimize_on_stop {
+        if let Err(err) = OptimizeAction.run(&mut conn) {
+            warn!("Failed to optimize database: {err}");
+        }
+    }
+    if options.checkpoint_on_stop {
+        if let Err(err) = conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE)") {
+            warn!("Failed to checkpoint database: {err}");
+        }
+    }
+    drop(conn);
+}
+
+/// Run an action, wrapped in its own transaction if configured.
+///
+/// The outer error indicates that the transaction could not be started or
+/// committed.
+fn run_action(
+    conn: &mut Connection,
+    action: Box<dyn ActionWrapper + Send>,
+    options: &RunOptions,
+) -> rusqlite::Result<ActionResult> {
+    let name = action.name();
+    debug!("Running action {name}");
+    #[cfg(feature = "debug_current_action")]
+    let _current = CurrentActionGuard::set(&options.current_action, name);
+    if !options.transaction_per_action {
+        return Ok(action.run(conn));
     }
+
+    let begin = match options.transaction_behavior {
+        Some(TransactionBehavior::Immediate) => "BEGIN IMMEDIATE",
+        Some(TransactionBehavior::Exclusive) => "BEGIN EXCLUSIVE",
+        _ => "BEGIN DEFERRED",
+    };
+    conn.execute_batch(begin)?;
+    let result = action.run(conn);
+    // The action may have ended the transaction on its own
+    if !conn.is_autocommit() {
+        if result.is_ok() {
+            if let Err(err) = conn.execute_batch("COMMIT") {
+                let _ = conn.execute_batch("ROLLBACK");
+                return Err(err);
+            }
+        } else if let Err(err) = conn.execute_batch("ROLLBACK") {
+            warn!("Failed to roll back transaction of failed action {name}: {err}");
+        }
+    }
+    Ok(result)
 }
 
-fn run(mut conn: Connection, mut rx: mpsc::UnboundedReceiver<Command>) {
-    while let Some(command) = rx.blocking_recv() {
+fn run(
+    mut conn: Connection,
+    mut rx: mpsc::UnboundedReceiver<Command>,
+    mut priority_rx: mpsc::UnboundedReceiver<Command>,
+    mut options: RunOptions,
+) {
+    info!("Vault thread started");
+    let mut stopped = None;
+    loop {
+        let command = match priority_rx.try_recv() {
+            Ok(command) => command,
+            Err(_) => match rx.blocking_recv() {
+                Some(command) => command,
+                None => break,
+            },
+        };
         match command {
             Command::Action(action, tx) => {
-                let result = action.run(&mut conn);
+                let name = action.name();
+                let total_changes = conn.total_changes();
+                let result = run_action(&mut conn, action, &options);
+                match &result {
+                    Ok(Ok(_)) => options.handle_success(&conn, total_changes),
+                    Ok(Err(err)) => {
+                        // The error is returned to the caller, who may well
+                        // expect it, so it is only logged at debug level.
+                        match err.downcast_ref::<rusqlite::Error>() {
+                            Some(err) => debug!("Action {name} failed: {err}"),
+                            None => debug!("Action {name} failed"),
+                        }
+                        options.handle_error(&mut conn, err.downcast_ref());
+                    }
+                    Err(err) => {
+                        warn!("Transaction of action {name} failed: {err}");
+                        options.handle_error(&mut conn, Some(err));
+                    }
+                }
                 let _ = tx.send(result);
             }
+            Command::Detached(action) => {
+                let name = action.name();
+                let total_changes = conn.total_changes();
+                let result = match run_action(&mut conn, action, &options) {
+                    Ok(result) => result,
+                    Err(err) => {
+                        warn!("Transaction of detached action {name} failed: {err}");
+                        options.handle_error(&mut conn, Some(&err));
+                        if let Some(sink) = &mut options.error_sink {
+                            sink(Box::new(err));
+                        }
+                        continue;
+                    }
+                };
+                match result {
+                    Ok(_) => options.handle_success(&conn, total_changes),
+                    Err(err) => {
+                        if let Ok(err) = err.downcast::<DetachedError>() {
+                            warn!("Detached action {name} failed: {err}");
+                            options.handle_error(&mut conn, err.downcast_ref());
+                            if let Some(sink) = &mut options.error_sink {
+                                sink(*err);
+                            }
+                        }
+                    }
+                }
+            }
             Command::Stop(tx) => {
-                drop(conn);
-                drop(tx);
+                stopped = Some(tx);
                 break;
             }
+            Command::Wake => {}
+        }
+    }
+    close(conn, &options);
+    drop(stopped);
+    info!("Vault thread stopped");
+}
+
+/// A handle for interrupting the query currently running on a
+/// [`TokioVault`]'s thread.
+///
+/// Obtained via [`TokioVault::interrupt_handle`]. Unlike the vault's
+/// [`Connection`], which lives on the vault's thread, this handle can be used
+/// from anywhere.
+#[derive(Clone)]
+pub struct InterruptHandle(Arc<Mutex<rusqlite::InterruptHandle>>);
+
+impl InterruptHandle {
+    fn new(handle: rusqlite::InterruptHandle) -> Self {
+        Self(Arc::new(Mutex::new(handle)))
+    }
+
+    /// Point the handle at a new connection after the old one was reopened.
+    fn replace(&self, handle: rusqlite::InterruptHandle) {
+        *self.0.lock().unwrap_or_else(|err| err.into_inner()) = handle;
+    }
+
+    /// Interrupt the query currently running on the vault's thread, if any.
+    ///
+    /// The interrupted [`Action`] receives a
+    /// [`rusqlite::Error::SqliteFailure`] with the error code
+    /// [`rusqlite::ErrorCode::OperationInterrupted`]. If no statement is
+    /// running, for example because the action has finished in the meantime,
+    /// this has no effect, so actions running later are never interrupted.
+    pub fn interrupt(&self) {
+        self.0
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .interrupt();
+    }
+}
+
+impl fmt::Debug for InterruptHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InterruptHandle").finish_non_exhaustive()
+    }
+}
+
+/// Keeps track of an action in flight, decrementing the count when dropped.
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl<'a> InFlight<'a> {
+    fn acquire(count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, atomic::Ordering::Relaxed);
+        Self(count)
+    }
+
+    fn try_acquire(count: &'a AtomicUsize, limit: usize) -> Option<Self> {
+        count
+            .fetch_update(atomic::Ordering::Relaxed, atomic::Ordering::Relaxed, |n| {
+                (n < limit).then_some(n + 1)
+            })
+            .ok()?;
+        Some(Self(count))
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, atomic::Ordering::Relaxed);
+    }
+}
+
+/// Keeps track of the action currently running on the vault's thread, clearing
+/// it when dropped.
+#[cfg(feature = "debug_current_action")]
+struct CurrentActionGuard<'a>(&'a Mutex<Option<&'static str>>);
+
+#[cfg(feature = "debug_current_action")]
+impl<'a> CurrentActionGuard<'a> {
+    fn set(current: &'a Mutex<Option<&'static str>>, name: &'static str) -> Self {
+        *current.lock().unwrap_or_else(|err| err.into_inner()) = Some(name);
+        Self(current)
+    }
+}
+
+#[cfg(feature = "debug_current_action")]
+impl Drop for CurrentActionGuard<'_> {
+    fn drop(&mut self) {
+        *self.0.lock().unwrap_or_else(|err| err.into_inner()) = None;
+    }
+}
+
+/// Shared between all clones of a [`TokioVault`] to notice when the last one
+/// is dropped.
+#[derive(Debug, Default)]
+struct Lifecycle {
+    stopped: AtomicBool,
+}
+
+impl Drop for Lifecycle {
+    fn drop(&mut self) {
+        if !self.stopped.load(atomic::Ordering::Relaxed) {
+            debug!("Vault dropped without being stopped, its thread stops once its queue is empty");
         }
     }
 }
 
 /// A vault for use with [`tokio`].
+///
+/// The vault's thread runs until [`Self::stop`] is called or all clones of the
+/// vault have been dropped. In the latter case, all actions queued so far are
+/// still executed before the connection is closed, but nothing waits for that
+/// to happen. Call [`Self::stop`] to be sure the connection has been closed,
+/// for example before exiting the program.
 #[derive(Debug, Clone)]
 pub struct TokioVault {
     tx: mpsc::UnboundedSender<Command>,
+    priority_tx: mpsc::UnboundedSender<Command>,
+    lifecycle: Arc<Lifecycle>,
+    interrupt: InterruptHandle,
+    migrations: usize,
+    in_flight: Arc<AtomicUsize>,
+    max_in_flight: usize,
+    degraded: Arc<AtomicBool>,
+    #[cfg(feature = "debug_current_action")]
+    current_action: Arc<Mutex<Option<&'static str>>>,
 }
 
 impl TokioVault {
     /// Launch a new thread to run database queries on, and return a
     /// [`TokioVault`] for communication with that thread.
     ///
-    /// It is recommended to set a few pragmas before calling this function, for
-    /// example:
+    /// It is recommended to set a few pragmas before calling this function
+    /// (or via [`TokioVaultBuilder::with_pragma`]), for example:
     /// - `journal_mode` to `"wal"`
     /// - `foreign_keys` to `true`
     /// - `trusted_schema` to `false`
-    pub fn launch(conn: Connection, migrations: &[Migration]) -> rusqlite::Result<Self> {
+    pub fn launch(conn: Connection, migrations: &[Migration]) -> Result<Self, MigrateError> {
         Self::launch_and_prepare(conn, migrations, |_| Ok(()))
     }
 
@@ -114,61 +1009,1168 @@ impl TokioVault {
     /// will prevent the database connection from being usable. An example would
     /// be creating temporary tables based on existing data.
     ///
-    /// It is recommended to set a few pragmas before calling this function, for
-    /// example:
+    /// It is recommended to set a few pragmas before calling this function
+    /// (or via [`TokioVaultBuilder::with_pragma`]), for example:
     /// - `journal_mode` to `"wal"`
     /// - `foreign_keys` to `true`
     /// - `trusted_schema` to `false`
     pub fn launch_and_prepare(
-        mut conn: Connection,
+        conn: Connection,
         migrations: &[Migration],
         prepare: impl FnOnce(&mut Connection) -> rusqlite::Result<()>,
-    ) -> rusqlite::Result<Self> {
-        crate::migrate(&mut conn, migrations)?;
-        prepare(&mut conn)?;
-
-        let (tx, rx) = mpsc::unbounded_channel();
-        thread::spawn(move || run(conn, rx));
-        Ok(Self { tx })
+    ) -> Result<Self, MigrateError> {
+        Self::builder().launch_and_prepare(conn, migrations, prepare)
     }
 
-    /// Execute an [`Action`] and return the result.
-    pub async fn execute<A>(&self, action: A) -> Result<A::Output, Error<A::Error>>
+    /// Launch a new thread to run database queries on, running asynchronous
+    /// hooks before and after the migrations.
+    ///
+    /// This is useful for setup that involves IO besides the database, for
+    /// example fetching data from an API to backfill a new table with. The
+    /// hooks run in the following order:
+    ///
+    /// 1. `before` receives the connection before any migrations have been
+    ///    applied and returns it afterwards. It runs outside of any transaction,
+    ///    so changes it makes are committed immediately, even if the
+    ///    migrations fail later.
+    /// 2. The migrations are applied in a single transaction, just like in
+    ///    [`TokioVault::launch`]. This blocks the current thread.
+    /// 3. The vault's thread is launched.
+    /// 4. `after` receives a handle to the new vault and can execute actions
+    ///    on it like on any other vault. If it fails, the vault is stopped
+    ///    again. Changes made by previously executed actions are kept.
+    ///
+    /// Errors returned by the migrations are converted into the hooks' error
+    /// type via [`From`].
+    pub async fn launch_with_hooks<E, B, BF, A, AF>(
+        conn: Connection,
+        migrations: &[Migration],
+        before: B,
+        after: A,
+    ) -> Result<Self, E>
     where
-        A: Action + Send + 'static,
-        A::Output: Send,
-        A::Error: Send,
+        E: From<MigrateError>,
+        B: FnOnce(Connection) -> BF,
+        BF: Future<Output = Result<Connection, E>>,
+        A: FnOnce(Self) -> AF,
+        AF: Future<Output = Result<(), E>>,
     {
-        let (tx, rx) = oneshot::channel();
-        self.tx
-            .send(Command::Action(Box::new(action), tx))
-            .map_err(|_| Error::Stopped)?;
+        Self::builder()
+            .launch_with_hooks(conn, migrations, before, after)
+            .await
+    }
 
-        let result = rx.await.map_err(|_| Error::Stopped)?;
+    /// Open an encrypted database, apply the key and launch a new thread to
+    /// run database queries on.
+    ///
+    /// The key is set via `PRAGMA key` before any other statement is run. The
+    /// key material is overwritten afterwards where possible.
+    ///
+    /// Requires rusqlite to be built with SQLCipher support, which the
+    /// `sqlcipher` feature enables.
+    #[cfg(feature = "sqlcipher")]
+    pub fn launch_encrypted(
+        path: impl AsRef<Path>,
+        key: String,
+        migrations: &[Migration],
+    ) -> Result<Self, MigrateError> {
+        let conn = Connection::open(path)?;
+        crate::apply_key(&conn, key)?;
+        Self::launch(conn, migrations)
+    }
 
-        // The ActionWrapper runs Action::run, which returns
-        // Result<Action::Result, Action::Error>. It then wraps the
-        // Action::Result and Action::Error into Any, which we're now trying to
-        // downcast again to Action::Result and Action::Error. This should
-        // always work.
-        match result {
-            Ok(result) => {
-                let result = *result.downcast::<A::Output>().unwrap();
-                Ok(result)
-            }
-            Err(err) => {
-                let err = *err.downcast::<A::Error>().unwrap();
-                Err(Error::Action(err))
-            }
-        }
+    /// Open a database using a specific VFS and launch a new thread to run
+    /// database queries on.
+    ///
+    /// The VFS is looked up by name, for example `"unix"` or `"win32"` for the
+    /// default VFS of the platform, or `"memdb"` for SQLite's in-memory VFS.
+    /// Custom VFSs, for example ones simulating IO errors for testing, must be
+    /// registered via `sqlite3_vfs_register` before the vault is launched. If
+    /// no VFS with the name exists, launching the vault fails with
+    /// `SQLITE_ERROR`. The database is opened with the default
+    /// [`OpenFlags`].
+    pub fn launch_with_vfs(
+        path: impl AsRef<Path>,
+        vfs: &str,
+        migrations: &[Migration],
+    ) -> Result<Self, MigrateError> {
+        Self::builder().launch_with_vfs(path, vfs, migrations)
     }
 
-    /// Stop the vault's thread and close its sqlite connection.
+    /// Open a named in-memory database and launch a new thread to run
+    /// database queries on.
     ///
-    /// Returns once the vault has been stopped.
-    pub async fn stop(&self) {
-        let (tx, rx) = oneshot::channel();
-        let _ = self.tx.send(Command::Stop(tx));
-        let _ = rx.await;
+    /// Other connections opened via [`open_shared_memory`](crate::open_shared_memory)
+    /// with the same `name`, for example those of another vault, see the same
+    /// database. See there for the caveats of shared cache mode. The database
+    /// lives at least as long as the vault.
+    pub fn launch_shared_memory(
+        name: &str,
+        migrations: &[Migration],
+    ) -> Result<Self, MigrateError> {
+        let conn = crate::open_shared_memory(name)?;
+        Self::launch(conn, migrations)
     }
-}
+
+    /// Launch a new thread to run database queries on, applying a mix of
+    /// transactional and non-transactional migrations in the process.
+    ///
+    /// See [`MigrationStep`] for how failures are handled.
+    pub fn launch_mixed(conn: Connection, steps: &[MigrationStep]) -> Result<Self, MigrateError> {
+        Self::builder().launch_mixed(conn, steps)
+    }
+
+    /// Launch a new thread to run database queries on, using the connection of
+    /// an existing [`SimpleVault`].
+    ///
+    /// The [`SimpleVault`] has already applied its migrations, so they are not
+    /// applied again. The database's `user_version` is taken as the number of
+    /// migrations, for example by [`TokioVault::health_check`].
+    pub fn from_simple(vault: SimpleVault) -> Result<Self, MigrateError> {
+        Self::builder().launch_simple(vault)
+    }
+
+    /// Create a [`TokioVaultBuilder`] for configuring a vault before launching
+    /// it.
+    pub fn builder() -> TokioVaultBuilder {
+        TokioVaultBuilder::new()
+    }
+
+    /// Obtain a handle for interrupting the query currently running on the
+    /// vault's thread.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Whether a write has recently failed because the disk is full or
+    /// failing.
+    ///
+    /// The vault becomes degraded once an action fails with `SQLITE_FULL` or
+    /// `SQLITE_IOERR`, and recovers once an action successfully changes the
+    /// database. The vault keeps running actions while degraded, so reads still
+    /// work, but the application may want to stop accepting writes until then.
+    ///
+    /// Only errors of type [`rusqlite::Error`] are detected, that is, errors of
+    /// actions whose [`Action::Error`] is [`rusqlite::Error`] and errors of the
+    /// transactions set up via [`TokioVaultBuilder::with_transaction_per_action`].
+    /// If such a transaction fails, it is always rolled back.
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(atomic::Ordering::Relaxed)
+    }
+
+    /// The [name](Action::name) of the action currently running on the
+    /// vault's thread, or [`None`] if it is idle.
+    ///
+    /// This is useful for finding out what the vault is stuck on. Keeping track
+    /// of the current action adds a little overhead to every action, so this
+    /// requires the `debug_current_action` feature.
+    #[cfg(feature = "debug_current_action")]
+    pub fn current_action_name(&self) -> Option<&'static str> {
+        *self
+            .current_action
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Execute an [`Action`] and return the result.
+    pub async fn execute<A>(&self, action: A) -> Result<A::Output, Error<A::Error>>
+    where
+        A: Action + Send + 'static,
+        A::Output: Send,
+        A::Error: Send,
+    {
+        let _in_flight = InFlight::acquire(&self.in_flight);
+        self.execute_unchecked(action, Priority::Normal).await
+    }
+
+    /// Execute an [`Action`] with the given [`Priority`] and return the result.
+    ///
+    /// Actions with [`Priority::High`] are run before any queued actions with
+    /// [`Priority::Normal`], which includes those passed to [`Self::execute`].
+    /// Among themselves, actions of the same priority run in the order they
+    /// were queued in. An action that is already running is not interrupted,
+    /// so a high priority action may still have to wait for it to finish.
+    ///
+    /// Detached actions and actions queued by periodic tasks always have
+    /// [`Priority::Normal`].
+    pub async fn execute_priority<A>(
+        &self,
+        action: A,
+        priority: Priority,
+    ) -> Result<A::Output, Error<A::Error>>
+    where
+        A: Action + Send + 'static,
+        A::Output: Send,
+        A::Error: Send,
+    {
+        let _in_flight = InFlight::acquire(&self.in_flight);
+        self.execute_unchecked(action, priority).await
+    }
+
+    /// Execute an [`Action`] and return the result, unless too many actions
+    /// are already in flight.
+    ///
+    /// An action is in flight from the moment it is passed to
+    /// [`Self::execute`], [`Self::execute_priority`] or [`Self::try_execute`]
+    /// until its result has been returned (or the future has been dropped).
+    /// If the limit set via [`TokioVaultBuilder::with_max_in_flight`] has been
+    /// reached, this function returns [`Error::QueueFull`] immediately instead
+    /// of queueing the action. This allows shedding load instead of letting
+    /// latency grow.
+    ///
+    /// Actions passed to [`Self::execute`] or [`Self::execute_priority`] are
+    /// never rejected, but they still count towards the limit.
+    pub async fn try_execute<A>(&self, action: A) -> Result<A::Output, Error<A::Error>>
+    where
+        A: Action + Send + 'static,
+        A::Output: Send,
+        A::Error: Send,
+    {
+        let _in_flight =
+            InFlight::try_acquire(&self.in_flight, self.max_in_flight).ok_or(Error::QueueFull)?;
+        self.execute_unchecked(action, Priority::Normal).await
+    }
+
+    /// Execute an [`Action`] and return the result, interrupting it if it
+    /// hasn't finished by the `deadline`.
+    ///
+    /// If the action is running once the deadline passes, its connection is
+    /// interrupted via the vault's [`InterruptHandle`], so the statement
+    /// currently running aborts with a [`rusqlite::Error::SqliteFailure`] with
+    /// the error code [`rusqlite::ErrorCode::OperationInterrupted`]. What the
+    /// action returns then depends on how it handles that error. If the action
+    /// hasn't started running yet, it is skipped and this function immediately
+    /// returns [`Error::DeadlineExceeded`].
+    ///
+    /// Unlike wrapping [`Self::execute`] in [`tokio::time::timeout`], this
+    /// stops the query itself, not just the wait for its result.
+    pub async fn execute_deadline<A>(
+        &self,
+        action: A,
+        deadline: Instant,
+    ) -> Result<A::Output, Error<A::Error>>
+    where
+        A: Action + Send + 'static,
+        A::Output: Send,
+        A::Error: Send,
+    {
+        let state = Arc::new(Mutex::new(DeadlineState::Queued));
+        let action = DeadlineAction {
+            action,
+            state: state.clone(),
+        };
+        let mut execution = pin!(self.execute(action));
+        let result = match time::timeout_at(deadline, &mut execution).await {
+            Ok(result) => result,
+            Err(_) => {
+                {
+                    let mut state = state.lock().unwrap_or_else(|err| err.into_inner());
+                    match *state {
+                        DeadlineState::Queued => {
+                            *state = DeadlineState::Expired;
+                            return Err(Error::DeadlineExceeded);
+                        }
+                        DeadlineState::Running => self.interrupt.interrupt(),
+                        DeadlineState::Finished | DeadlineState::Expired => {}
+                    }
+                }
+                execution.await
+            }
+        };
+        result?.ok_or(Error::DeadlineExceeded)
+    }
+
+    async fn execute_unchecked<A>(
+        &self,
+        action: A,
+        priority: Priority,
+    ) -> Result<A::Output, Error<A::Error>>
+    where
+        A: Action + Send + 'static,
+        A::Output: Send,
+        A::Error: Send,
+    {
+        let rx = self.enqueue(action, priority)?;
+        let result = rx
+            .await
+            .map_err(|_| Error::Stopped)?
+            .map_err(Error::Transaction)?;
+
+        // The ActionWrapper runs Action::run, which returns
+        // Result<Action::Output, Action::Error>. It then wraps the
+        // Action::Output and Action::Error into Any, which we're now trying to
+        // downcast again to Action::Output and Action::Error. This should
+        // always work, but if it doesn't, we return an error instead of
+        // panicking.
+        match result {
+            Ok(result) => match result.downcast::<A::Output>() {
+                Ok(result) => Ok(*result),
+                Err(_) => Err(Error::TypeMismatch(type_name::<A::Output>())),
+            },
+            Err(err) => match err.downcast::<A::Error>() {
+                Ok(err) => Err(Error::Action(*err)),
+                Err(_) => Err(Error::TypeMismatch(type_name::<A::Error>())),
+            },
+        }
+    }
+
+    /// Run a closure with exclusive access to the vault's [`Connection`] and
+    /// return its result.
+    ///
+    /// The closure runs on the vault's thread just like an [`Action`] would.
+    /// This is useful for one-off multi-statement work that doesn't warrant
+    /// its own [`Action`] type.
+    pub async fn with_connection<F, T>(&self, f: F) -> Result<T, Error<Infallible>>
+    where
+        F: FnOnce(&mut Connection) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.execute(ClosureAction(f)).await
+    }
+
+    /// Check the schema version and integrity of the database.
+    ///
+    /// See [`HealthCheckAction`] for more details.
+    pub async fn health_check(&self) -> Result<HealthReport, Error<rusqlite::Error>> {
+        self.execute(HealthCheckAction {
+            expected_version: self.migrations,
+        })
+        .await
+    }
+
+    /// Execute an [`Action`] without waiting for its result.
+    ///
+    /// Returns as soon as the action has been queued. The action's output is
+    /// discarded, and its error, if any, is passed to the error sink set via
+    /// [`TokioVaultBuilder::with_error_sink`]. Actions still run in the order
+    /// they were queued in, regardless of whether they were executed via this
+    /// function or [`TokioVault::execute`].
+    pub fn execute_detached<A>(&self, action: A) -> Result<(), Error<Infallible>>
+    where
+        A: Action + Send + 'static,
+        A::Error: error::Error + Send + 'static,
+    {
+        self.tx
+            .send(Command::Detached(Box::new(DetachedAction(action))))
+            .map_err(|_| Error::Stopped)
+    }
+
+    /// Insert many serialized rows in chunks of `chunk_size` rows per
+    /// transaction and return the number of inserted rows.
+    ///
+    /// Each row is turned into named parameters via
+    /// [`to_named_params`](crate::to_named_params). The rows are pulled lazily
+    /// on the vault's thread. See [`BulkInsert`] for more details.
+    #[cfg(feature = "serde")]
+    pub async fn bulk_insert<I>(
+        &self,
+        sql: impl Into<String>,
+        rows: I,
+        chunk_size: usize,
+    ) -> Result<usize, Error<BulkInsertError>>
+    where
+        I: IntoIterator,
+        I::Item: serde::Serialize,
+        I::IntoIter: Send + 'static,
+    {
+        self.execute(BulkInsert::serialized(sql, rows, chunk_size))
+            .await
+    }
+
+    /// Execute a query and stream its rows over a bounded channel.
+    ///
+    /// Returns as soon as the query has been queued. See [`StreamAction`] for
+    /// more details, including why the stream should be consumed promptly.
+    pub fn stream<T, F>(
+        &self,
+        sql: impl Into<String>,
+        params: impl Into<OwnedParams>,
+        buffer: usize,
+        map: F,
+    ) -> Result<mpsc::Receiver<rusqlite::Result<T>>, Error<Infallible>>
+    where
+        T: Send + 'static,
+        F: FnMut(&Row<'_>) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let (action, rx) = StreamAction::new(sql, params, buffer, map);
+        self.enqueue(action, Priority::Normal)?;
+        Ok(rx)
+    }
+
+    /// Execute a query and stream its rows, deserialized via
+    /// [`from_row_via_name`](crate::from_row_via_name), as a [`Stream`].
+    ///
+    /// The rows are sent over a bounded channel with capacity `buffer`, just
+    /// like with [`TokioVault::stream`]. If the stream is dropped early, the
+    /// query stops and its statement is released once the next row has been
+    /// read.
+    #[cfg(feature = "serde")]
+    pub fn query_stream<T>(
+        &self,
+        sql: impl Into<String>,
+        params: impl Into<OwnedParams>,
+        buffer: usize,
+    ) -> Result<impl Stream<Item = rusqlite::Result<T>>, Error<Infallible>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let rx = self.stream(sql, params, buffer, |row| crate::from_row_via_name(row))?;
+        Ok(ReceiverStream::new(rx))
+    }
+
+    /// Execute a query and read its result into a [`DataFrame`].
+    ///
+    /// See [`rows_to_dataframe`](crate::rows_to_dataframe) for more details.
+    #[cfg(feature = "polars")]
+    pub async fn query_dataframe(
+        &self,
+        sql: impl Into<String>,
+        params: impl Into<OwnedParams>,
+    ) -> Result<DataFrame, Error<DataFrameError>> {
+        self.execute(QueryDataFrame::new(sql, params)).await
+    }
+
+    /// Send an [`Action`] to the vault's thread without waiting for its result.
+    fn enqueue<A, E>(
+        &self,
+        action: A,
+        priority: Priority,
+    ) -> Result<oneshot::Receiver<rusqlite::Result<ActionResult>>, Error<E>>
+    where
+        A: Action + Send + 'static,
+        A::Output: Send,
+        A::Error: Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        let command = Command::Action(Box::new(action), tx);
+        match priority {
+            Priority::Normal => self.tx.send(command),
+            Priority::High => self
+                .priority_tx
+                .send(command)
+                .and_then(|()| self.tx.send(Command::Wake)),
+        }
+        .map_err(|_| Error::Stopped)?;
+        Ok(rx)
+    }
+
+    /// Stop the vault's thread and close its sqlite connection.
+    ///
+    /// Returns once the vault has been stopped.
+    pub async fn stop(&self) {
+        self.lifecycle
+            .stopped
+            .store(true, atomic::Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        let _ = self.tx.send(Command::Stop(tx));
+        let _ = rx.await;
+    }
+}
+
+/// A function configuring the connection before the migrations are applied.
+///
+/// It is called again for every connection reopened via a
+/// [`ConnectionFactory`].
+type Setup = Box<dyn Fn(&Connection) -> rusqlite::Result<()> + Send>;
+
+/// A function spawning a periodic task once the vault has been launched.
+#[cfg(feature = "periodic")]
+type Periodic = Box<dyn FnOnce(&Handle, mpsc::WeakUnboundedSender<Command>) + Send>;
+
+#[cfg(feature = "hooks")]
+type ProgressHandler = Arc<Mutex<dyn FnMut() -> bool + Send>>;
+
+/// Lock a mutex, ignoring poisoning.
+///
+/// Used for functions shared between all connections of a vault, which should
+/// keep working after a call has panicked, just like unshared ones would.
+#[cfg(any(feature = "functions", feature = "collation", feature = "hooks"))]
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// An [`Aggregate`] shared between all connections of a vault.
+#[cfg(feature = "functions")]
+struct SharedAggregate<D>(Arc<Mutex<D>>);
+
+#[cfg(feature = "functions")]
+impl<A, D, T> Aggregate<A, T> for SharedAggregate<D>
+where
+    A: RefUnwindSafe + UnwindSafe,
+    D: Aggregate<A, T>,
+    T: SqlFnOutput,
+{
+    fn init(&self, ctx: &mut Context<'_>) -> rusqlite::Result<A> {
+        lock(&self.0).init(ctx)
+    }
+
+    fn step(&self, ctx: &mut Context<'_>, acc: &mut A) -> rusqlite::Result<()> {
+        lock(&self.0).step(ctx, acc)
+    }
+
+    fn finalize(&self, ctx: &mut Context<'_>, acc: Option<A>) -> rusqlite::Result<T> {
+        lock(&self.0).finalize(ctx, acc)
+    }
+}
+
+/// The settings of a connection, configured via [`TokioVaultBuilder`].
+///
+/// These are kept by the vault's thread so connections reopened via a
+/// [`ConnectionFactory`] can be configured just like the original one.
+#[derive(Default)]
+struct ConnectionConfig {
+    #[cfg(feature = "trace")]
+    trace: Option<fn(&str)>,
+    #[cfg(feature = "trace")]
+    profile: Option<fn(&str, Duration)>,
+    page_size: Option<u32>,
+    pragmas: Vec<(String, Box<dyn ToSql + Send>)>,
+    setup: Vec<Setup>,
+    application_id: Option<i32>,
+    statement_cache_capacity: Option<usize>,
+    #[cfg(feature = "hooks")]
+    progress_handler: Option<(c_int, ProgressHandler)>,
+}
+
+impl ConnectionConfig {
+    /// Configure a connection before the migrations are applied.
+    fn before_migrations(&self, conn: &mut Connection) -> rusqlite::Result<()> {
+        #[cfg(feature = "trace")]
+        if let Some(trace) = self.trace {
+            conn.trace(Some(trace));
+        }
+        #[cfg(feature = "trace")]
+        if let Some(profile) = self.profile {
+            conn.profile(Some(profile));
+        }
+        if let Some(page_size) = self.page_size {
+            conn.pragma_update(None, "page_size", page_size)?;
+        }
+        for (name, value) in &self.pragmas {
+            conn.pragma_update(None, name, value)?;
+        }
+        for setup in &self.setup {
+            setup(conn)?;
+        }
+        Ok(())
+    }
+
+    /// Configure a connection after the migrations have been applied.
+    fn after_migrations(&self, conn: &Connection) {
+        if let Some(capacity) = self.statement_cache_capacity {
+            conn.set_prepared_statement_cache_capacity(capacity);
+        }
+        #[cfg(feature = "hooks")]
+        if let Some((num_ops, handler)) = &self.progress_handler {
+            let handler = handler.clone();
+            conn.progress_handler(*num_ops, Some(move || lock(&handler)()));
+        }
+    }
+
+    /// Configure a connection reopened via a [`ConnectionFactory`].
+    ///
+    /// The migrations are not applied again, but the `application_id` is
+    /// checked in case the factory opened a different database.
+    fn reopened(&self, conn: &mut Connection) -> Result<(), MigrateError> {
+        self.before_migrations(conn)?;
+        crate::claim_application_id(conn, self.application_id)?;
+        self.after_migrations(conn);
+        Ok(())
+    }
+}
+
+/// A builder for configuring and launching a [`TokioVault`].
+#[derive(Default)]
+pub struct TokioVaultBuilder {
+    config: ConnectionConfig,
+    verifier: Option<MigrationVerifier>,
+    error_sink: Option<ErrorSink>,
+    optimize_on_stop: bool,
+    checkpoint_on_stop: bool,
+    transaction_per_action: bool,
+    transaction_behavior: Option<TransactionBehavior>,
+    factory: Option<Box<dyn ConnectionFactory>>,
+    max_in_flight: Option<usize>,
+    #[cfg(feature = "periodic")]
+    periodic: Vec<Periodic>,
+}
+
+impl TokioVaultBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a pragma on the connection before applying the migrations.
+    ///
+    /// Pragmas are set in the order they were added, before the migration
+    /// transaction begins. This matters for pragmas like `journal_mode` or
+    /// `foreign_keys` that can't be changed inside a transaction.
+    pub fn with_pragma(
+        mut self,
+        name: impl Into<String>,
+        value: impl ToSql + Send + 'static,
+    ) -> Self {
+        self.config.pragmas.push((name.into(), Box::new(value)));
+        self
+    }
+
+    /// Set the number of WAL pages after which SQLite automatically runs a
+    /// checkpoint.
+    ///
+    /// This sets `PRAGMA wal_autocheckpoint` like [`Self::with_pragma`] would.
+    /// SQLite's default is 1000 pages. A value of 0 disables automatic
+    /// checkpoints entirely.
+    pub fn with_wal_autocheckpoint(self, pages: u32) -> Self {
+        self.with_pragma("wal_autocheckpoint", pages)
+    }
+
+    /// Set the database's page size in bytes.
+    ///
+    /// This sets `PRAGMA page_size` before any of the pragmas set via
+    /// [`Self::with_pragma`] and before the migrations are applied. The page
+    /// size only takes effect if the database is still empty, so it can't be
+    /// changed for existing databases this way. It must be a power of two
+    /// between 512 and 65536, otherwise SQLite ignores it. Larger pages may
+    /// improve performance for databases storing large blobs.
+    pub fn with_page_size(mut self, bytes: u32) -> Self {
+        self.config.page_size = Some(bytes);
+        self
+    }
+
+    /// Set the maximum number of pages in the connection's page cache.
+    ///
+    /// This sets `PRAGMA cache_size`. A larger cache allows
+    /// [`WarmCacheAction`](crate::actions::WarmCacheAction) to keep more of the
+    /// database in memory.
+    pub fn with_cache_size(self, pages: u32) -> Self {
+        self.with_pragma("cache_size", pages)
+    }
+
+    /// Set the capacity of the connection's prepared statement cache.
+    pub fn with_statement_cache_capacity(mut self, capacity: usize) -> Self {
+        self.config.statement_cache_capacity = Some(capacity);
+        self
+    }
+
+    /// Require the database to have a specific `application_id`.
+    ///
+    /// Before applying the migrations, the database's `application_id` is
+    /// compared to the given one. If it differs, launching the vault fails with
+    /// [`MigrateError::ApplicationIdMismatch`] and the database is left
+    /// untouched. If it has never been set, it is set to the given one in the
+    /// same transaction as the migrations, so databases whose migrations fail
+    /// are not claimed.
+    pub fn with_application_id(mut self, application_id: i32) -> Self {
+        self.config.application_id = Some(application_id);
+        self
+    }
+
+    /// Set a function to verify the schema of a partially migrated database
+    /// before applying the remaining migrations.
+    ///
+    /// See [`MigrationVerifier`] for more details.
+    pub fn with_migration_verifier(mut self, verifier: MigrationVerifier) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    /// Set a function to be called with the errors of [`Action`]s executed via
+    /// [`TokioVault::execute_detached`].
+    ///
+    /// The function runs on the vault's thread, so it should return quickly.
+    /// Without an error sink, these errors are discarded.
+    pub fn with_error_sink(mut self, sink: impl FnMut(DetachedError) + Send + 'static) -> Self {
+        self.error_sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Run `PRAGMA optimize` before closing the connection.
+    ///
+    /// The connection is closed when the vault is stopped via
+    /// [`TokioVault::stop`] or once all copies of the vault have been dropped.
+    /// See [`OptimizeAction`] for more details.
+    pub fn with_optimize_on_stop(mut self, optimize: bool) -> Self {
+        self.optimize_on_stop = optimize;
+        self
+    }
+
+    /// Run `PRAGMA wal_checkpoint(TRUNCATE)` before closing the connection.
+    ///
+    /// This copies all changes from the WAL file into the database file and
+    /// truncates the WAL file, so that the database file is complete on its
+    /// own once the vault has stopped. Like with
+    /// [`Self::with_optimize_on_stop`], this happens when the vault is stopped
+    /// or all of its copies have been dropped.
+    pub fn with_checkpoint_on_stop(mut self, checkpoint: bool) -> Self {
+        self.checkpoint_on_stop = checkpoint;
+        self
+    }
+
+    /// Wrap every [`Action`] in its own transaction.
+    ///
+    /// The transaction is committed if the action succeeds and rolled back if
+    /// it fails, so a failing action never leaves partial changes behind. If
+    /// the transaction can't be started or committed, [`TokioVault::execute`]
+    /// returns [`Error::Transaction`].
+    ///
+    /// Actions must not start transactions of their own via
+    /// [`Connection::transaction`] when this is enabled, but they can use
+    /// [`Connection::savepoint`] instead. Statements that can't run inside a
+    /// transaction, like `VACUUM`, fail.
+    pub fn with_transaction_per_action(mut self, transaction: bool) -> Self {
+        self.transaction_per_action = transaction;
+        self
+    }
+
+    /// Set the [`TransactionBehavior`] of the transactions enabled via
+    /// [`Self::with_transaction_per_action`].
+    ///
+    /// By default, transactions are [`TransactionBehavior::Deferred`] and only
+    /// acquire a write lock once the action first writes. Under contention
+    /// with other connections, this may fail with `SQLITE_BUSY` regardless of
+    /// the busy timeout. [`TransactionBehavior::Immediate`] acquires the write
+    /// lock right away instead, which is usually preferable if most actions
+    /// write. See
+    /// [`SimpleVault::transaction_with_behavior`](crate::simple::SimpleVault::transaction_with_behavior)
+    /// for more details.
+    pub fn with_transaction_behavior(mut self, behavior: TransactionBehavior) -> Self {
+        self.transaction_behavior = Some(behavior);
+        self
+    }
+
+    /// Limit the number of actions in flight for [`TokioVault::try_execute`].
+    ///
+    /// Once `limit` actions are in flight, [`TokioVault::try_execute`] rejects
+    /// further actions with [`Error::QueueFull`]. [`TokioVault::execute`] is
+    /// not affected by the limit. By default, there is no limit.
+    pub fn with_max_in_flight(mut self, limit: usize) -> Self {
+        self.max_in_flight = Some(limit);
+        self
+    }
+
+    /// Periodically execute a maintenance [`Action`], for example
+    /// [`OptimizeAction`].
+    ///
+    /// Once the vault has been launched, a tokio task calls `action_factory`
+    /// every `interval` and queues the resulting action just like
+    /// [`TokioVault::execute_detached`], so its error, if any, is passed to the
+    /// error sink. The first action is queued after one `interval` has passed.
+    /// The task stops once the vault has been stopped or all of its handles
+    /// have been dropped.
+    ///
+    /// The vault must be launched from within a tokio runtime, otherwise
+    /// launching fails with [`MigrateError::NoRuntime`].
+    #[cfg(feature = "periodic")]
+    pub fn with_periodic<A>(
+        mut self,
+        interval: Duration,
+        mut action_factory: impl FnMut() -> A + Send + 'static,
+    ) -> Self
+    where
+        A: Action + Send + 'static,
+        A::Error: error::Error + Send + 'static,
+    {
+        self.periodic.push(Box::new(move |handle, tx| {
+            handle.spawn(async move {
+                let mut interval = time::interval_at(Instant::now() + interval, interval);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    interval.tick().await;
+                    let Some(tx) = tx.upgrade() else { break };
+                    let action = DetachedAction(action_factory());
+                    if tx.send(Command::Detached(Box::new(action))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }));
+        self
+    }
+
+    /// Set a [`ConnectionFactory`] to reopen the connection with if it fails.
+    ///
+    /// If an [`Action`] whose error type is [`rusqlite::Error`] fails with an
+    /// error indicating that the connection is no longer usable, for example
+    /// because the database is corrupt or its file has been replaced, the
+    /// vault's thread opens a new connection via the factory and uses it for
+    /// all following actions. The error is still returned to the failed
+    /// action. Other errors don't trigger a reopen.
+    ///
+    /// The new connection is configured with the settings of this builder, like
+    /// pragmas, custom functions or the trace callback, just like the original
+    /// one. Its `application_id` is checked too, but migrations are not applied
+    /// to it. Settings outside of this builder, like the key of an encrypted
+    /// database, must be applied by the factory itself. If the factory or the
+    /// configuration fails, the old connection is kept.
+    pub fn with_connection_factory(mut self, factory: impl ConnectionFactory + 'static) -> Self {
+        self.factory = Some(Box::new(factory));
+        self
+    }
+
+    /// Register a custom scalar SQL function on the connection.
+    ///
+    /// The function is registered before the migrations are applied, so they
+    /// can use it too. It runs on the vault's thread whenever a statement calls
+    /// it. See [`Connection::create_scalar_function`] for the meaning of the
+    /// parameters.
+    #[cfg(feature = "functions")]
+    pub fn with_scalar_function<F, T>(
+        mut self,
+        name: impl Into<String>,
+        n_arg: c_int,
+        flags: FunctionFlags,
+        func: F,
+    ) -> Self
+    where
+        F: FnMut(&Context<'_>) -> rusqlite::Result<T> + Send + 'static,
+        T: SqlFnOutput,
+    {
+        let name = name.into();
+        let func = Arc::new(Mutex::new(func));
+        self.config.setup.push(Box::new(move |conn| {
+            let func = func.clone();
+            conn.create_scalar_function(&name, n_arg, flags, move |ctx| lock(&func)(ctx))
+        }));
+        self
+    }
+
+    /// Register a custom aggregate SQL function on the connection.
+    ///
+    /// Like [`Self::with_scalar_function`], the function is registered before
+    /// the migrations are applied and runs on the vault's thread. See
+    /// [`Connection::create_aggregate_function`] and [`Aggregate`] for the
+    /// meaning of the parameters.
+    #[cfg(feature = "functions")]
+    pub fn with_aggregate_function<A, D, T>(
+        mut self,
+        name: impl Into<String>,
+        n_arg: c_int,
+        flags: FunctionFlags,
+        aggregate: D,
+    ) -> Self
+    where
+        A: RefUnwindSafe + UnwindSafe,
+        D: Aggregate<A, T> + Send + 'static,
+        T: SqlFnOutput,
+    {
+        let name = name.into();
+        let aggregate = Arc::new(Mutex::new(aggregate));
+        self.config.setup.push(Box::new(move |conn| {
+            let aggregate = SharedAggregate(aggregate.clone());
+            conn.create_aggregate_function(&name, n_arg, flags, aggregate)
+        }));
+        self
+    }
+
+    /// Register a custom collation sequence on the connection.
+    ///
+    /// The collation is registered before the migrations are applied, so they
+    /// can create indexes using it. It runs on the vault's thread whenever a
+    /// statement compares strings using it. See [`Connection::create_collation`]
+    /// for more details.
+    #[cfg(feature = "collation")]
+    pub fn with_collation<C>(mut self, name: impl Into<String>, cmp: C) -> Self
+    where
+        C: Fn(&str, &str) -> Ordering + Send + 'static,
+    {
+        let name = name.into();
+        let cmp = Arc::new(Mutex::new(cmp));
+        self.config.setup.push(Box::new(move |conn| {
+            let cmp = cmp.clone();
+            conn.create_collation(&name, move |a, b| lock(&cmp)(a, b))
+        }));
+        self
+    }
+
+    /// Load a SQLite extension on the connection.
+    ///
+    /// Extension loading is enabled only for the duration of the call to
+    /// [`Connection::load_extension`] and disabled again right afterwards, so
+    /// SQL run later (including the `load_extension()` SQL function) can't load
+    /// further extensions. The extension is loaded before the migrations are
+    /// applied, so they can use whatever it provides. See
+    /// [`Connection::load_extension`] for how `path` and `entry_point` are
+    /// interpreted.
+    ///
+    /// Extensions can register functions, virtual tables and collations that
+    /// may be referenced from the database schema. Consider setting the
+    /// `trusted_schema` pragma to `false` (see [`Self::with_pragma`]) so that a
+    /// malicious database file can't use them in views or triggers.
+    ///
+    /// Requires rusqlite to be built with extension loading support, which the
+    /// `load_extension` feature enables.
+    ///
+    /// # Safety
+    ///
+    /// Loading an extension runs arbitrary native code inside the process. The
+    /// extension must be trusted and must use the SQLite APIs correctly. See
+    /// [`Connection::load_extension`] for details.
+    #[cfg(feature = "load_extension")]
+    #[allow(unsafe_code)]
+    pub unsafe fn with_extension(
+        mut self,
+        path: impl Into<PathBuf>,
+        entry_point: Option<&str>,
+    ) -> Self {
+        let path = path.into();
+        let entry_point = entry_point.map(|s| s.to_string());
+        self.config.setup.push(Box::new(move |conn| unsafe {
+            // No SQL is run while the guard is alive.
+            let _guard = LoadExtensionGuard::new(conn)?;
+            conn.load_extension(&path, entry_point.as_deref())
+        }));
+        self
+    }
+
+    /// Install a progress handler on the connection.
+    ///
+    /// The handler is called roughly every `num_ops` virtual machine
+    /// instructions while a statement is running on the vault's thread. If it
+    /// returns `true`, the statement is interrupted and the running [`Action`]
+    /// receives a [`rusqlite::Error::SqliteFailure`] with the error code
+    /// [`rusqlite::ErrorCode::OperationInterrupted`]. This allows cooperative
+    /// cancellation of runaway queries.
+    ///
+    /// The handler is installed after all migrations have been applied.
+    #[cfg(feature = "hooks")]
+    pub fn with_progress_handler(
+        mut self,
+        num_ops: c_int,
+        handler: impl FnMut() -> bool + Send + 'static,
+    ) -> Self {
+        self.config.progress_handler = Some((num_ops, Arc::new(Mutex::new(handler))));
+        self
+    }
+
+    /// Install a trace callback on the connection.
+    ///
+    /// The callback is called with the SQL text of every statement run on the
+    /// connection, with bound parameters replaced by their values. This is
+    /// useful for finding out which SQL actually runs, for example by logging
+    /// it, without instrumenting every [`Action`].
+    ///
+    /// The callback is installed before any pragmas are set, so it also sees
+    /// the statements run during setup and migrations.
+    ///
+    /// SQLite doesn't support trace and profile callbacks on the same
+    /// connection at the same time, so this replaces any callback set via
+    /// [`Self::with_profile`].
+    #[cfg(feature = "trace")]
+    pub fn with_trace(mut self, trace: fn(&str)) -> Self {
+        self.config.trace = Some(trace);
+        self.config.profile = None;
+        self
+    }
+
+    /// Install a profile callback on the connection.
+    ///
+    /// The callback is called with the SQL text and run time of every
+    /// statement once it has finished running. Unlike with
+    /// [`Self::with_trace`], bound parameters are not replaced by their values.
+    /// The callback is installed before any pragmas are set.
+    ///
+    /// This replaces any callback set via [`Self::with_trace`].
+    #[cfg(feature = "trace")]
+    pub fn with_profile(mut self, profile: fn(&str, Duration)) -> Self {
+        self.config.profile = Some(profile);
+        self.config.trace = None;
+        self
+    }
+
+    /// Launch a new thread to run database queries on, and return a
+    /// [`TokioVault`] for communication with that thread.
+    ///
+    /// See [`TokioVault::launch`] for more details.
+    pub fn launch(
+        self,
+        conn: Connection,
+        migrations: &[Migration],
+    ) -> Result<TokioVault, MigrateError> {
+        self.launch_and_prepare(conn, migrations, |_| Ok(()))
+    }
+
+    /// Open a database using a specific VFS and launch a new thread to run
+    /// database queries on.
+    ///
+    /// See [`TokioVault::launch_with_vfs`] for more details.
+    pub fn launch_with_vfs(
+        self,
+        path: impl AsRef<Path>,
+        vfs: &str,
+        migrations: &[Migration],
+    ) -> Result<TokioVault, MigrateError> {
+        let conn = Connection::open_with_flags_and_vfs(path, OpenFlags::default(), vfs)?;
+        self.launch(conn, migrations)
+    }
+
+    /// Launch a new thread to run database queries on, running asynchronous
+    /// hooks before and after the migrations.
+    ///
+    /// See [`TokioVault::launch_with_hooks`] for more details.
+    pub async fn launch_with_hooks<E, B, BF, A, AF>(
+        self,
+        conn: Connection,
+        migrations: &[Migration],
+        before: B,
+        after: A,
+    ) -> Result<TokioVault, E>
+    where
+        E: From<MigrateError>,
+        B: FnOnce(Connection) -> BF,
+        BF: Future<Output = Result<Connection, E>>,
+        A: FnOnce(TokioVault) -> AF,
+        AF: Future<Output = Result<(), E>>,
+    {
+        let conn = before(conn).await?;
+        let vault = self.launch(conn, migrations)?;
+        if let Err(err) = after(vault.clone()).await {
+            vault.stop().await;
+            return Err(err);
+        }
+        Ok(vault)
+    }
+
+    /// Launch a new thread to run database queries on, and return a
+    /// [`TokioVault`] for communication with that thread.
+    ///
+    /// See [`TokioVault::launch_and_prepare`] for more details.
+    pub fn launch_and_prepare(
+        self,
+        conn: Connection,
+        migrations: &[Migration],
+        prepare: impl FnOnce(&mut Connection) -> rusqlite::Result<()>,
+    ) -> Result<TokioVault, MigrateError> {
+        let verifier = self.verifier;
+        let application_id = self.config.application_id;
+        self.launch_with(
+            conn,
+            migrations.len(),
+            |conn| crate::migrate(conn, migrations, verifier, application_id),
+            prepare,
+        )
+    }
+
+    /// Launch a new thread to run database queries on, applying a mix of
+    /// transactional and non-transactional migrations in the process.
+    ///
+    /// See [`TokioVault::launch_mixed`] for more details.
+    pub fn launch_mixed(
+        self,
+        conn: Connection,
+        steps: &[MigrationStep],
+    ) -> Result<TokioVault, MigrateError> {
+        let verifier = self.verifier;
+        let application_id = self.config.application_id;
+        self.launch_with(
+            conn,
+            steps.len(),
+            |conn| crate::migrate_steps(conn, steps, verifier, application_id),
+            |_| Ok(()),
+        )
+    }
+
+    /// Launch a new thread to run database queries on, using the connection of
+    /// an existing [`SimpleVault`].
+    ///
+    /// See [`TokioVault::from_simple`] for more details.
+    pub fn launch_simple(self, vault: SimpleVault) -> Result<TokioVault, MigrateError> {
+        let conn = vault.into_connection();
+        let migrations = crate::applied_migration_count(&conn)?;
+        let application_id = self.config.application_id;
+        self.launch_with(
+            conn,
+            migrations,
+            |conn| crate::claim_application_id(conn, application_id),
+            |_| Ok(()),
+        )
+    }
+
+    fn launch_with(
+        self,
+        mut conn: Connection,
+        migrations: usize,
+        migrate: impl FnOnce(&mut Connection) -> Result<(), MigrateError>,
+        prepare: impl FnOnce(&mut Connection) -> rusqlite::Result<()>,
+    ) -> Result<TokioVault, MigrateError> {
+        // Check this before touching the connection so a missing runtime
+        // doesn't leave a half-configured database behind.
+        #[cfg(feature = "periodic")]
+        let runtime = if self.periodic.is_empty() {
+            None
+        } else {
+            Some(Handle::try_current().map_err(|_| MigrateError::NoRuntime)?)
+        };
+
+        info!("Launching vault with {migrations} migrations");
+        self.config.before_migrations(&mut conn)?;
+        migrate(&mut conn)?;
+        prepare(&mut conn)?;
+        self.config.after_migrations(&conn);
+
+        let interrupt = InterruptHandle::new(conn.get_interrupt_handle());
+        let degraded = Arc::new(AtomicBool::new(false));
+        #[cfg(feature = "debug_current_action")]
+        let current_action = Arc::new(Mutex::new(None));
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (priority_tx, priority_rx) = mpsc::unbounded_channel();
+        let options = RunOptions {
+            error_sink: self.error_sink,
+            optimize_on_stop: self.optimize_on_stop,
+            checkpoint_on_stop: self.checkpoint_on_stop,
+            transaction_per_action: self.transaction_per_action,
+            transaction_behavior: self.transaction_behavior,
+            factory: self.factory,
+            config: self.config,
+            interrupt: interrupt.clone(),
+            degraded: degraded.clone(),
+            #[cfg(feature = "debug_current_action")]
+            current_action: current_action.clone(),
+        };
+        thread::spawn(move || run(conn, rx, priority_rx, options));
+        #[cfg(feature = "periodic")]
+        if let Some(runtime) = runtime {
+            for periodic in self.periodic {
+                periodic(&runtime, tx.downgrade());
+            }
+        }
+        Ok(TokioVault {
+            tx,
+            priority_tx,
+            lifecycle: Arc::default(),
+            interrupt,
+            migrations,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            max_in_flight: self.max_in_flight.unwrap_or(usize::MAX),
+            degraded,
+            #[cfg(feature = "debug_current_action")]
+            current_action,
+        })
+    }
+}
+
+impl AsyncVault for TokioVault {
+    type Error<E> = Error<E>;
+
+    async fn execute<A>(&self, action: A) -> Result<A::Output, Error<A::Error>>
+    where
+        A: Action + Send + 'static,
+        A::Output: Send,
+        A::Error: Send,
+    {
+        Self::execute(self, action).await
+    }
+}
+
+// Vaults are meant to be shared between tasks and threads.
+const _: () = {
+    fn assert_send_sync<T: Send + Sync>() {}
+    let _ = assert_send_sync::<TokioVault>;
+    let _ = assert_send_sync::<InterruptHandle>;
+};
+
+// The builder is held across await points in launch_with_hooks, whose future
+// should be usable with tokio::spawn.
+const _: () = {
+    fn assert_send<T: Send>() {}
+    let _ = assert_send::<TokioVaultBuilder>;
+};
@@ -0,0 +1,717 @@
+use std::path::PathBuf;
+
+use rusqlite::Connection;
+use vault::{
+    actions::{
+        AnalyzeAction, AnalyzeStat, AttachAction, Count, DbStatsAction, DetachAction,
+        ExplainAction, OptimizeAction, ResetAction, Timed, VacuumAction, VacuumIntoAction,
+    },
+    Action,
+};
+
+/// A path to a database file that doesn't exist yet.
+fn temp_db(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("vault-actions-{}-{name}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn schema_names(conn: &Connection) -> Vec<String> {
+    conn.prepare("SELECT name FROM sqlite_schema ORDER BY name")
+        .unwrap()
+        .query_map([], |r| r.get(0))
+        .unwrap()
+        .collect::<rusqlite::Result<_>>()
+        .unwrap()
+}
+
+#[test]
+fn reset_drops_everything() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", true).unwrap();
+    conn.execute_batch(
+        "
+        CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);
+        CREATE TABLE posts (
+            id INTEGER PRIMARY KEY,
+            user INTEGER NOT NULL REFERENCES users (id)
+        );
+        CREATE INDEX posts_user ON posts (user);
+        CREATE VIEW user_names AS SELECT name FROM users;
+        CREATE TRIGGER users_delete AFTER DELETE ON users BEGIN
+            DELETE FROM posts WHERE user = old.id;
+        END;
+        INSERT INTO users VALUES (1, 'foo');
+        INSERT INTO posts VALUES (1, 1);
+        ANALYZE;
+        PRAGMA user_version = 3;
+        ",
+    )
+    .unwrap();
+
+    ResetAction.run(&mut conn).unwrap();
+
+    assert!(schema_names(&conn).is_empty());
+    assert_eq!(vault::applied_migration_count(&conn).unwrap(), 0);
+    let foreign_keys: bool = conn
+        .query_row("SELECT * FROM pragma_foreign_keys", [], |r| r.get(0))
+        .unwrap();
+    assert!(foreign_keys);
+}
+
+#[test]
+fn reset_empties_sqlite_sequence() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        "
+        CREATE TABLE t (id INTEGER PRIMARY KEY AUTOINCREMENT);
+        INSERT INTO t DEFAULT VALUES;
+        ",
+    )
+    .unwrap();
+
+    ResetAction.run(&mut conn).unwrap();
+
+    // SQLite doesn't allow dropping sqlite_sequence
+    assert_eq!(schema_names(&conn), ["sqlite_sequence"]);
+    let sequences: i64 = conn
+        .query_row("SELECT count(*) FROM sqlite_sequence", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(sequences, 0);
+}
+
+#[test]
+fn join_across_attached_databases() {
+    let users = temp_db("attach-users");
+    let posts = temp_db("attach-posts");
+    Connection::open(&users)
+        .unwrap()
+        .execute_batch(
+            "
+            CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);
+            INSERT INTO users VALUES (1, 'foo'), (2, 'bar');
+            ",
+        )
+        .unwrap();
+    Connection::open(&posts)
+        .unwrap()
+        .execute_batch(
+            "
+            CREATE TABLE posts (user INTEGER, title TEXT);
+            INSERT INTO posts VALUES (2, 'hello'), (1, 'world'), (2, 'again');
+            ",
+        )
+        .unwrap();
+
+    let mut conn = Connection::open_in_memory().unwrap();
+    for (path, alias) in [(&users, "u"), (&posts, "p")] {
+        AttachAction {
+            path: path.to_str().unwrap().to_string(),
+            alias: alias.to_string(),
+        }
+        .run(&mut conn)
+        .unwrap();
+    }
+
+    let titles = conn
+        .prepare(
+            "
+            SELECT u.users.name || ': ' || p.posts.title
+            FROM p.posts JOIN u.users ON u.users.id = p.posts.user
+            ORDER BY p.posts.rowid
+            ",
+        )
+        .unwrap()
+        .query_map([], |r| r.get::<_, String>(0))
+        .unwrap()
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(titles, ["bar: hello", "foo: world", "bar: again"]);
+
+    DetachAction {
+        alias: "p".to_string(),
+    }
+    .run(&mut conn)
+    .unwrap();
+    assert!(conn.execute_batch("SELECT * FROM p.posts").is_err());
+    conn.execute_batch("SELECT * FROM u.users").unwrap();
+
+    drop(conn);
+    let _ = std::fs::remove_file(users);
+    let _ = std::fs::remove_file(posts);
+}
+
+#[test]
+fn vacuum_into_copy_can_be_reopened() {
+    let dest = temp_db("vacuum-into");
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        "
+        CREATE TABLE t (id INTEGER PRIMARY KEY, data BLOB);
+        WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100)
+        INSERT INTO t SELECT i, randomblob(1000) FROM n;
+        DELETE FROM t WHERE id > 10;
+        PRAGMA user_version = 7;
+        ",
+    )
+    .unwrap();
+
+    VacuumIntoAction {
+        dest: dest.to_str().unwrap().to_string(),
+    }
+    .run(&mut conn)
+    .unwrap();
+
+    let copy = Connection::open(&dest).unwrap();
+    let count: i64 = copy
+        .query_row("SELECT count(*) FROM t", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(count, 10);
+    assert_eq!(vault::applied_migration_count(&copy).unwrap(), 7);
+    let free: i64 = copy
+        .query_row("SELECT * FROM pragma_freelist_count", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(free, 0);
+
+    // The destination must not exist yet
+    let err = VacuumIntoAction {
+        dest: dest.to_str().unwrap().to_string(),
+    }
+    .run(&mut conn)
+    .unwrap_err();
+    assert!(
+        err.to_string().contains("output file already exists"),
+        "{err}"
+    );
+
+    drop(copy);
+    let _ = std::fs::remove_file(dest);
+}
+
+/// A database with an indexed table of 1000 rows.
+fn populated() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        "
+        CREATE TABLE items (id INTEGER PRIMARY KEY, category INTEGER, name TEXT);
+        CREATE INDEX items_category ON items (category);
+        WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000)
+        INSERT INTO items SELECT i, i % 10, 'item ' || i FROM n;
+        ",
+    )
+    .unwrap();
+    conn
+}
+
+#[test]
+fn optimize_populated_database() {
+    let mut conn = populated();
+    let count: i64 = conn
+        .query_row("SELECT count(*) FROM items WHERE category = 3", [], |r| {
+            r.get(0)
+        })
+        .unwrap();
+    assert_eq!(count, 100);
+
+    OptimizeAction.run(&mut conn).unwrap();
+    OptimizeAction.run(&mut conn).unwrap();
+
+    // The query above made the planner look at the index, so it was analyzed
+    let stat: String = conn
+        .query_row(
+            "SELECT stat FROM sqlite_stat1 WHERE idx = 'items_category'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert!(stat.starts_with("1000 "), "{stat}");
+
+    let check: String = conn
+        .query_row("PRAGMA integrity_check", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(check, "ok");
+    let count: i64 = conn
+        .query_row("SELECT count(*) FROM items", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(count, 1000);
+}
+
+#[test]
+fn analyze_populates_stat1() {
+    let mut conn = Connection::open_in_memory().unwrap();
+
+    // Without tables, there is nothing to analyze
+    let stats = AnalyzeAction { return_stats: true }.run(&mut conn).unwrap();
+    assert!(stats.is_empty());
+
+    let mut conn = populated();
+    conn.execute_batch("CREATE TABLE notes (body TEXT); INSERT INTO notes VALUES ('a'), ('b')")
+        .unwrap();
+    let stats = AnalyzeAction::default().run(&mut conn).unwrap();
+    assert!(stats.is_empty());
+
+    let stats = AnalyzeAction { return_stats: true }.run(&mut conn).unwrap();
+    assert_eq!(
+        stats,
+        [
+            AnalyzeStat {
+                table: "items".to_string(),
+                index: Some("items_category".to_string()),
+                stat: "1000 100".to_string(),
+            },
+            // Tables without indexes only get their row count
+            AnalyzeStat {
+                table: "notes".to_string(),
+                index: None,
+                stat: "2".to_string(),
+            },
+        ]
+    );
+
+    // The stats were written to the database
+    let rows: i64 = conn
+        .query_row("SELECT count(*) FROM sqlite_stat1", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(rows, 2);
+}
+
+#[test]
+fn stats_track_freed_pages() {
+    let mut conn = populated();
+    let stats = DbStatsAction.run(&mut conn).unwrap();
+    assert!(stats.page_count > 1, "{stats:?}");
+    assert!(stats.page_size > 0, "{stats:?}");
+    assert_eq!(stats.freelist_count, 0);
+    assert_eq!(stats.total_bytes(), stats.page_count * stats.page_size);
+
+    conn.execute("DELETE FROM items WHERE id > 100", [])
+        .unwrap();
+    let deleted = DbStatsAction.run(&mut conn).unwrap();
+    assert!(deleted.freelist_count > 0, "{deleted:?}");
+    assert_eq!(deleted.page_count, stats.page_count);
+    assert!(deleted.free_bytes() < deleted.total_bytes());
+
+    // Vacuuming reclaims the free pages
+    VacuumAction.run(&mut conn).unwrap();
+    let vacuumed = DbStatsAction.run(&mut conn).unwrap();
+    assert_eq!(vacuumed.freelist_count, 0);
+    assert!(vacuumed.page_count < stats.page_count, "{vacuumed:?}");
+}
+
+#[test]
+fn explain_scan_versus_index() {
+    let mut conn = populated();
+    let explain = |conn: &mut Connection, sql: &str| {
+        ExplainAction {
+            sql: sql.to_string(),
+        }
+        .run(conn)
+        .unwrap()
+    };
+
+    let steps = explain(&mut conn, "SELECT * FROM items WHERE name = 'item 5'");
+    assert_eq!(steps.len(), 1, "{steps:?}");
+    assert_eq!(steps[0].parent, 0);
+    assert!(steps[0].detail.starts_with("SCAN items"), "{steps:?}");
+
+    // Parameters don't need to be bound
+    let steps = explain(&mut conn, "SELECT * FROM items WHERE category = ?");
+    assert_eq!(steps.len(), 1, "{steps:?}");
+    assert!(
+        steps[0].detail.contains("USING INDEX items_category"),
+        "{steps:?}"
+    );
+
+    // Nested steps point to their parent
+    let steps = explain(
+        &mut conn,
+        "SELECT * FROM items WHERE id IN (SELECT id FROM items WHERE category = 1 LIMIT 2)",
+    );
+    assert!(steps.len() > 1, "{steps:?}");
+    assert!(
+        steps
+            .iter()
+            .filter(|s| s.parent != 0)
+            .all(|s| steps.iter().any(|p| p.id == s.parent)),
+        "{steps:?}"
+    );
+
+    // The statement itself doesn't run
+    explain(&mut conn, "DELETE FROM items");
+    let count: i64 = conn
+        .query_row("SELECT count(*) FROM items", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(count, 1000);
+}
+
+#[test]
+fn timed_slow_query() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    let sql = "
+        WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100000)
+        SELECT count(*) FROM n
+    ";
+    let (count, duration) = Timed(Count::new(sql, ())).run(&mut conn).unwrap();
+    assert_eq!(count, 100_000);
+    assert!(duration > std::time::Duration::ZERO);
+
+    // Errors are passed through
+    assert!(Timed(Count::new("SELECT x FROM missing", ()))
+        .run(&mut conn)
+        .is_err());
+}
+
+#[test]
+fn timed_keeps_name() {
+    struct Named;
+
+    impl Action for Named {
+        type Output = ();
+        type Error = rusqlite::Error;
+
+        fn run(self, _conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+            Ok(())
+        }
+
+        fn name(&self) -> &'static str {
+            "named"
+        }
+    }
+
+    assert_eq!(Timed(Named).name(), "named");
+    let count = Count::new("SELECT 1", ());
+    assert_eq!(Timed(count.clone()).name(), count.name());
+}
+
+#[cfg(feature = "serde")]
+mod query_action {
+    use rusqlite::Connection;
+    use serde::Deserialize;
+    use vault::Action;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    pub struct Post {
+        id: i64,
+        title: String,
+    }
+
+    vault::query_action! {
+        pub struct PostsInRange { min_id: i64, max_id: i64, title: String } -> Vec<Post>,
+        "SELECT id, title FROM posts WHERE id BETWEEN ? AND ? AND title <> ? ORDER BY id"
+    }
+
+    vault::query_action! {
+        struct PostById { id: i64 } -> Option<Post>,
+        "SELECT id, title FROM posts WHERE id = ?",
+    }
+
+    vault::query_action! {
+        struct FirstPost {} -> Post,
+        "SELECT id, title FROM posts ORDER BY id LIMIT 1"
+    }
+
+    fn assert_send_static<T: Send + 'static>() {}
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT NOT NULL);
+            INSERT INTO posts VALUES (1, 'a'), (2, 'b'), (3, 'c'), (4, 'd');
+            ",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn multiple_params() {
+        assert_send_static::<PostsInRange>();
+        let action = PostsInRange {
+            min_id: 2,
+            max_id: 4,
+            title: "c".to_string(),
+        };
+        let posts = action.run(&mut conn()).unwrap();
+        let ids = posts.iter().map(|p| p.id).collect::<Vec<_>>();
+        assert_eq!(ids, [2, 4]);
+    }
+
+    #[test]
+    fn single_param() {
+        let post = PostById { id: 3 }.run(&mut conn()).unwrap();
+        assert_eq!(
+            post,
+            Some(Post {
+                id: 3,
+                title: "c".to_string()
+            })
+        );
+        assert_eq!(PostById { id: 5 }.run(&mut conn()).unwrap(), None);
+    }
+
+    #[test]
+    fn zero_params() {
+        let post = FirstPost {}.run(&mut conn()).unwrap();
+        assert_eq!(post.id, 1);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod insert_returning {
+    use rusqlite::Connection;
+    use serde::Deserialize;
+    use vault::{
+        actions::{InsertReturning, InsertReturningOne},
+        Action,
+    };
+
+    #[derive(Debug, Clone, Deserialize)]
+    struct Event {
+        id: i64,
+        name: String,
+        created: i64,
+    }
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            CREATE TABLE events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                created INTEGER NOT NULL DEFAULT (unixepoch())
+            );
+            ",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn returns_generated_columns() {
+        let mut conn = conn();
+        let before: i64 = conn
+            .query_row("SELECT unixepoch()", [], |r| r.get(0))
+            .unwrap();
+
+        let events = InsertReturning::<Event>::new(
+            "INSERT INTO events (name) VALUES (?), (?) RETURNING *",
+            ("foo".to_string(), "bar".to_string()),
+        )
+        .run(&mut conn)
+        .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].id, events[0].name.as_str()), (1, "foo"));
+        assert_eq!((events[1].id, events[1].name.as_str()), (2, "bar"));
+        for event in &events {
+            assert!(event.created >= before, "{event:?}");
+        }
+
+        // The generated ids keep counting up
+        let events = InsertReturning::<Event>::new(
+            "INSERT INTO events (name) VALUES ('baz') RETURNING id, name, created",
+            (),
+        )
+        .run(&mut conn)
+        .unwrap();
+        assert_eq!(events[0].id, 3);
+    }
+
+    fn count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT count(*) FROM events", [], |r| r.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn returns_exactly_one_row() {
+        let mut conn = conn();
+        let before: i64 = conn
+            .query_row("SELECT unixepoch()", [], |r| r.get(0))
+            .unwrap();
+
+        let insert = InsertReturningOne::<Event>::new(
+            "INSERT INTO events (name) VALUES (?) RETURNING *",
+            ("foo".to_string(),),
+        );
+        let event = insert.clone().run(&mut conn).unwrap();
+        assert_eq!((event.id, event.name.as_str()), (1, "foo"));
+        assert!(event.created >= before, "{event:?}");
+        assert_eq!(insert.run(&mut conn).unwrap().id, 2);
+
+        // Without rows, nothing is inserted
+        let err = InsertReturningOne::<Event>::new(
+            "INSERT INTO events (name) SELECT 'bar' WHERE 0 RETURNING *",
+            (),
+        )
+        .run(&mut conn)
+        .unwrap_err();
+        assert!(matches!(err, rusqlite::Error::QueryReturnedNoRows), "{err}");
+        assert_eq!(count(&conn), 2);
+
+        // With too many rows, all of them are rolled back
+        let err = InsertReturningOne::<Event>::new(
+            "INSERT INTO events (name) VALUES ('bar'), ('baz') RETURNING *",
+            (),
+        )
+        .run(&mut conn)
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("query returned more than one row"),
+            "{err}"
+        );
+        assert_eq!(count(&conn), 2);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod bulk_insert {
+    use rusqlite::Connection;
+    use serde::Serialize;
+    use vault::{actions::BulkInsert, Action};
+
+    const INSERT: &str = "INSERT INTO items (id, name) VALUES (:id, :name)";
+
+    #[derive(Serialize)]
+    struct Item {
+        id: i64,
+        name: String,
+    }
+
+    fn item(id: i64) -> Item {
+        Item {
+            id,
+            name: format!("item {id}"),
+        }
+    }
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+            .unwrap();
+        conn
+    }
+
+    fn count(conn: &Connection) -> usize {
+        conn.query_row("SELECT count(*) FROM items", [], |r| r.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn inserts_ten_thousand_rows() {
+        let mut conn = conn();
+        let rows = (0..10_000).map(item);
+        let inserted = BulkInsert::serialized(INSERT, rows, 1000)
+            .run(&mut conn)
+            .unwrap();
+        assert_eq!(inserted, 10_000);
+        assert_eq!(count(&conn), 10_000);
+
+        let name: String = conn
+            .query_row("SELECT name FROM items WHERE id = 9999", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(name, "item 9999");
+    }
+
+    #[test]
+    fn failing_row_keeps_earlier_chunks() {
+        let mut conn = conn();
+        // Row 25 collides with row 5, so the third chunk is rolled back
+        let rows = (0..25).chain([5]).chain(26..40).map(item);
+        let err = BulkInsert::serialized(INSERT, rows, 10)
+            .run(&mut conn)
+            .unwrap_err();
+        assert_eq!(err.committed, 20);
+        assert_eq!(count(&conn), 20);
+    }
+
+    #[test]
+    fn rows_are_pulled_lazily() {
+        let mut conn = conn();
+        // An endless iterator would never finish if it were collected first
+        let rows = (0..).map(|id| if id < 30 { item(id) } else { item(0) });
+        let err = BulkInsert::serialized(INSERT, rows, 10)
+            .run(&mut conn)
+            .unwrap_err();
+        assert_eq!(err.committed, 30);
+        assert_eq!(count(&conn), 30);
+    }
+
+    #[test]
+    fn zero_chunk_size_is_an_error() {
+        let mut conn = conn();
+        let err = BulkInsert::serialized(INSERT, (0..10).map(item), 0)
+            .run(&mut conn)
+            .unwrap_err();
+        assert_eq!(err.committed, 0);
+        assert_eq!(count(&conn), 0);
+    }
+}
+
+mod chunked_delete {
+    use rusqlite::{Connection, ErrorCode};
+    use vault::{actions::ChunkedDeleteAction, Action};
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+            WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 6000)
+            INSERT INTO items SELECT i, 'item ' || i FROM n;
+            ",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn count(conn: &Connection) -> usize {
+        conn.query_row("SELECT count(*) FROM items", [], |r| r.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn deletes_five_thousand_rows() {
+        let mut conn = conn();
+        let deleted = ChunkedDeleteAction::new("items", "id <= ?", (5000,), 500)
+            .run(&mut conn)
+            .unwrap();
+        assert_eq!(deleted, 5000);
+        assert_eq!(count(&conn), 1000);
+
+        // Nothing left to delete
+        let deleted = ChunkedDeleteAction::new("items", "id <= ?", (5000,), 500)
+            .run(&mut conn)
+            .unwrap();
+        assert_eq!(deleted, 0);
+    }
+
+    #[test]
+    fn failing_chunk_keeps_earlier_chunks() {
+        let mut conn = conn();
+        conn.execute_batch(
+            "
+            CREATE TRIGGER protect BEFORE DELETE ON items WHEN old.id = 1200
+            BEGIN SELECT RAISE(ABORT, 'protected'); END;
+            ",
+        )
+        .unwrap();
+        let err = ChunkedDeleteAction::new("items", "id <= ?", (5000,), 500)
+            .run(&mut conn)
+            .unwrap_err();
+        assert_eq!(err.committed, 1000);
+        assert!(err.to_string().contains("protected"), "{err}");
+        assert_eq!(count(&conn), 5000);
+    }
+
+    #[test]
+    fn zero_chunk_size() {
+        let mut conn = conn();
+        let err = ChunkedDeleteAction::new("items", "1", (), 0)
+            .run(&mut conn)
+            .unwrap_err();
+        assert_eq!(err.committed, 0);
+        assert_eq!(err.source.sqlite_error_code(), Some(ErrorCode::ApiMisuse));
+        assert_eq!(count(&conn), 6000);
+    }
+}
@@ -0,0 +1,105 @@
+#![cfg(feature = "arrow")]
+
+use arrow_array::{cast::AsArray, types::Float64Type, types::Int64Type, Array};
+use arrow_schema::DataType;
+use rusqlite::Connection;
+use vault::ArrowError;
+
+fn mixed_table() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        "
+        CREATE TABLE items (
+            id INTEGER,
+            price REAL,
+            name TEXT,
+            data BLOB,
+            amount NUMERIC
+        );
+        INSERT INTO items VALUES
+            (1, 1.5, 'a', x'01', 2),
+            (2, NULL, NULL, NULL, 2.5),
+            (3, 3, 'c', x'0302', NULL);
+        ",
+    )
+    .unwrap();
+    conn
+}
+
+fn record_batch(conn: &Connection, sql: &str) -> Result<arrow_array::RecordBatch, ArrowError> {
+    let mut stmt = conn.prepare(sql)?;
+    let batch = vault::rows_to_record_batch(stmt.query([])?);
+    batch
+}
+
+#[test]
+fn small_table() {
+    let conn = mixed_table();
+    let batch = record_batch(&conn, "SELECT *, NULL AS empty FROM items ORDER BY id").unwrap();
+    assert_eq!(batch.num_rows(), 3);
+
+    let schema = batch.schema();
+    let types = schema
+        .fields()
+        .iter()
+        .map(|f| (f.name().as_str(), f.data_type().clone(), f.is_nullable()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        [
+            ("id", DataType::Int64, true),
+            ("price", DataType::Float64, true),
+            ("name", DataType::Utf8, true),
+            ("data", DataType::Binary, true),
+            ("amount", DataType::Float64, true),
+            ("empty", DataType::Null, true),
+        ]
+    );
+
+    let ids = batch.column(0).as_primitive::<Int64Type>();
+    assert_eq!(ids.values().as_ref(), [1, 2, 3]);
+
+    let prices = batch.column(1).as_primitive::<Float64Type>();
+    assert_eq!(prices.value(0), 1.5);
+    assert!(prices.is_null(1));
+    assert_eq!(prices.value(2), 3.0);
+
+    let names = batch.column(2).as_string::<i32>();
+    assert_eq!(
+        names.iter().collect::<Vec<_>>(),
+        [Some("a"), None, Some("c")]
+    );
+
+    let data = batch.column(3).as_binary::<i32>();
+    assert_eq!(
+        data.iter().collect::<Vec<_>>(),
+        [Some(&[1][..]), None, Some(&[3, 2][..])]
+    );
+
+    let amounts = batch.column(4).as_primitive::<Float64Type>();
+    assert_eq!(amounts.value(0), 2.0);
+    assert_eq!(amounts.value(1), 2.5);
+    assert!(amounts.is_null(2));
+
+    assert_eq!(batch.column(5).len(), 3);
+}
+
+#[test]
+fn empty_result() {
+    let conn = mixed_table();
+    let batch = record_batch(&conn, "SELECT id, name FROM items WHERE id > 10").unwrap();
+    assert_eq!(batch.num_rows(), 0);
+    assert_eq!(batch.num_columns(), 2);
+}
+
+#[test]
+fn type_mismatch() {
+    let conn = mixed_table();
+    conn.execute("INSERT INTO items (id) VALUES ('four')", [])
+        .unwrap();
+    let err = record_batch(&conn, "SELECT id FROM items").unwrap_err();
+    assert!(
+        matches!(&err, ArrowError::TypeMismatch { column, .. } if column == "id"),
+        "{err}"
+    );
+}
@@ -0,0 +1,111 @@
+#![cfg(feature = "json")]
+
+use rusqlite::{types::ValueRef, Connection};
+use serde_json::json;
+
+#[test]
+fn row_to_json_types() {
+    let conn = Connection::open_in_memory().unwrap();
+    let value = conn
+        .query_row(
+            "
+            SELECT
+                NULL AS empty,
+                42 AS integer,
+                1.5 AS real,
+                9e999 AS infinity,
+                -9e999 AS neg_infinity,
+                'foo' AS text,
+                x'00ff10' AS blob,
+                x'' AS empty_blob
+            ",
+            [],
+            vault::row_to_json,
+        )
+        .unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "empty": null,
+            "integer": 42,
+            "real": 1.5,
+            "infinity": null,
+            "neg_infinity": null,
+            "text": "foo",
+            "blob": "AP8Q",
+            "empty_blob": "",
+        })
+    );
+}
+
+#[test]
+fn value_to_json_non_finite_reals() {
+    for v in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+        assert_eq!(
+            vault::value_to_json(ValueRef::Real(v)).unwrap(),
+            json!(null)
+        );
+    }
+    assert_eq!(
+        vault::value_to_json(ValueRef::Real(-0.25)).unwrap(),
+        json!(-0.25)
+    );
+}
+
+fn json_each(conn: &Connection, sql: &str) -> Vec<vault::JsonEachRow> {
+    conn.prepare(sql)
+        .unwrap()
+        .query_map([], vault::json_each_row)
+        .unwrap()
+        .collect::<rusqlite::Result<_>>()
+        .unwrap()
+}
+
+#[test]
+fn json_each_array() {
+    let conn = Connection::open_in_memory().unwrap();
+    let rows = json_each(&conn, "SELECT * FROM json_each('[1,2,3]')");
+    let values = rows.iter().map(|r| &r.value).collect::<Vec<_>>();
+    assert_eq!(values, [&json!(1), &json!(2), &json!(3)]);
+    let keys = rows.iter().map(|r| &r.key).collect::<Vec<_>>();
+    assert_eq!(keys, [&json!(0), &json!(1), &json!(2)]);
+    for row in &rows {
+        assert_eq!(row.json_type, "integer");
+        assert_eq!(row.parent, None);
+        assert_eq!(row.path, "$");
+    }
+    assert_eq!(rows[2].fullkey, "$[2]");
+}
+
+#[test]
+fn json_each_mixed_types() {
+    let conn = Connection::open_in_memory().unwrap();
+    let rows = json_each(
+        &conn,
+        r#"SELECT * FROM json_each('{"a":null,"b":true,"c":false,"d":1.5,"e":"x","f":[1,{"g":2}],"h":{}}')"#,
+    );
+    let values = rows
+        .iter()
+        .map(|r| (r.key.as_str().unwrap(), r.json_type.as_str(), &r.value))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        values,
+        [
+            ("a", "null", &json!(null)),
+            ("b", "true", &json!(true)),
+            ("c", "false", &json!(false)),
+            ("d", "real", &json!(1.5)),
+            ("e", "text", &json!("x")),
+            ("f", "array", &json!([1, {"g": 2}])),
+            ("h", "object", &json!({})),
+        ]
+    );
+
+    // json_tree descends into nested values and reports their parents
+    let rows = json_each(&conn, r#"SELECT * FROM json_tree('{"f":[1,{"g":2}]}')"#);
+    let g = rows.iter().find(|r| r.key == json!("g")).unwrap();
+    assert_eq!(g.value, json!(2));
+    assert_eq!(g.fullkey, "$.f[1].g");
+    let parent = rows.iter().find(|r| Some(r.id) == g.parent).unwrap();
+    assert_eq!(parent.value, json!({"g": 2}));
+}
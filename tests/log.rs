@@ -0,0 +1,174 @@
+use std::process::Command;
+
+/// All features except `log` itself and `r2d2`, whose dependencies use `log`.
+const FEATURES: &str = "arrow,blob,collation,csv,debug_current_action,functions,hooks,json,\
+    load_extension,periodic,polars,serde,sqlcipher,testing,trace,tokio";
+
+#[test]
+fn no_log_dependency_without_feature() {
+    let output = Command::new(env!("CARGO"))
+        .args([
+            "tree", "--edges", "normal", "--prefix", "none", "--format", "{p}",
+        ])
+        .args(["--no-default-features", "--features", FEATURES])
+        .arg("--manifest-path")
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let tree = String::from_utf8(output.stdout).unwrap();
+    assert!(tree.lines().any(|p| p.starts_with("rusqlite ")), "{tree}");
+    assert!(!tree.lines().any(|p| p.starts_with("log ")), "{tree}");
+}
+
+/// A logger capturing the messages of this crate, since a logger can only be
+/// installed once per process.
+#[cfg(feature = "log")]
+mod capture {
+    use std::{
+        sync::{Mutex, Once},
+        thread::{self, ThreadId},
+    };
+
+    use log::{Level, Log, Metadata, Record};
+
+    static LOGGER: Logger = Logger(Mutex::new(vec![]));
+
+    struct Logger(Mutex<Vec<(ThreadId, Level, String)>>);
+
+    impl Log for Logger {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            if record.target().starts_with("vault") {
+                let message = record.args().to_string();
+                let thread = thread::current().id();
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((thread, record.level(), message));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    pub fn install() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            log::set_logger(&LOGGER).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+    }
+
+    /// All messages logged so far, from any thread.
+    #[cfg(feature = "tokio")]
+    pub fn all() -> Vec<(Level, String)> {
+        let logs = LOGGER.0.lock().unwrap();
+        logs.iter()
+            .map(|(_, level, message)| (*level, message.clone()))
+            .collect()
+    }
+
+    /// The messages logged so far by the current thread.
+    pub fn this_thread() -> Vec<(Level, String)> {
+        let thread = thread::current().id();
+        let logs = LOGGER.0.lock().unwrap();
+        logs.iter()
+            .filter(|(t, _, _)| *t == thread)
+            .map(|(_, level, message)| (*level, message.clone()))
+            .collect()
+    }
+}
+
+#[cfg(feature = "log")]
+mod migrations {
+    use log::Level;
+    use rusqlite::{Connection, Transaction};
+    use vault::simple::SimpleVault;
+
+    use super::capture;
+
+    fn create_table(tx: &mut Transaction<'_>, _: usize, _: usize) -> rusqlite::Result<()> {
+        tx.execute_batch("CREATE TABLE t (id INTEGER PRIMARY KEY)")
+    }
+
+    fn fail(tx: &mut Transaction<'_>, _: usize, _: usize) -> rusqlite::Result<()> {
+        tx.execute_batch("SELECT * FROM missing")
+    }
+
+    #[test]
+    fn migration_progress_is_logged() {
+        capture::install();
+
+        let conn = Connection::open_in_memory().unwrap();
+        SimpleVault::new(conn, &[create_table, fail]).unwrap_err();
+
+        assert_eq!(
+            capture::this_thread(),
+            [
+                (
+                    Level::Debug,
+                    "Database has 0/2 migrations applied".to_string()
+                ),
+                (Level::Info, "Applying migration 1/2".to_string()),
+                (Level::Info, "Applying migration 2/2".to_string()),
+                (
+                    Level::Warn,
+                    "Migration 2/2 failed: no such table: missing".to_string()
+                ),
+            ]
+        );
+    }
+}
+
+#[cfg(all(feature = "log", feature = "tokio"))]
+mod actions {
+    use log::Level;
+    use rusqlite::Connection;
+    use vault::{actions::Timed, tokio::TokioVault, Action};
+
+    use super::capture;
+
+    struct Named(&'static str);
+
+    impl Action for Named {
+        type Output = ();
+        type Error = rusqlite::Error;
+
+        fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+            conn.execute_batch(self.0)
+        }
+
+        fn name(&self) -> &'static str {
+            "named action"
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_name_is_logged() {
+        capture::install();
+
+        let vault = TokioVault::builder()
+            .with_transaction_per_action(true)
+            .launch(Connection::open_in_memory().unwrap(), &[])
+            .unwrap();
+        vault.execute(Named("SELECT 1")).await.unwrap();
+        vault.execute(Timed(Named("SELECT 2"))).await.unwrap();
+        vault.stop().await;
+
+        let logs = capture::all()
+            .into_iter()
+            .filter(|(_, message)| message.contains("named action"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            logs,
+            [
+                (Level::Debug, "Running action named action".to_string()),
+                (Level::Debug, "Running action named action".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use rusqlite::Connection;
+use vault::{
+    actions::{Count, Exec},
+    Action, OwnedParams,
+};
+
+fn users() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch("CREATE TABLE users (id INTEGER, name TEXT, age INTEGER)")
+        .unwrap();
+    conn
+}
+
+#[test]
+fn named_params() {
+    let mut conn = users();
+    let params = vault::owned_named_params! { ":name": "foo".to_string(), ":id": 1, ":age": 30 };
+    let sql = "INSERT INTO users VALUES (:id, :name, :age)";
+    assert_eq!(Exec::new(sql, params).run(&mut conn).unwrap(), 1);
+
+    let params = OwnedParams::named([("@name", "bar".to_string()), ("@id", "2".to_string())]);
+    let sql = "INSERT INTO users (id, name) VALUES (@id, @name)";
+    assert_eq!(Exec::new(sql, params).run(&mut conn).unwrap(), 1);
+
+    let (id, name, age): (i64, String, i64) = conn
+        .query_row("SELECT * FROM users WHERE name = 'foo'", [], |r| {
+            Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+        })
+        .unwrap();
+    assert_eq!((id, name.as_str(), age), (1, "foo", 30));
+}
+
+#[test]
+fn mixed_params() {
+    let mut conn = users();
+    conn.execute_batch("INSERT INTO users VALUES (1, 'foo', 30), (2, 'bar', 40)")
+        .unwrap();
+
+    let mut params = OwnedParams::from((20,));
+    params.push_named(":name", "bar".to_string());
+    let sql = "SELECT count(*) FROM users WHERE age > ? AND name = :name";
+    assert_eq!(Count::new(sql, params).run(&mut conn).unwrap(), 1);
+}
+
+#[test]
+fn named_param_before_positional_one() {
+    let mut conn = users();
+
+    // SQLite gives :name the index 1, which is already used by the 20
+    let mut params = OwnedParams::from((20,));
+    params.push_named(":name", "bar".to_string());
+    let sql = "SELECT count(*) FROM users WHERE name = :name AND age > ?";
+    let err = Count::new(sql, params).run(&mut conn).unwrap_err();
+    assert!(matches!(err, rusqlite::Error::InvalidParameterName(ref n) if n == ":name"));
+}
+
+#[test]
+fn unknown_named_param() {
+    let mut conn = users();
+    let params = vault::owned_named_params! { ":id": 1 };
+    let err = Count::new("SELECT count(*) FROM users WHERE id = :user", params)
+        .run(&mut conn)
+        .unwrap_err();
+    assert!(matches!(err, rusqlite::Error::InvalidParameterName(ref n) if n == ":id"));
+}
+
+#[test]
+fn bind_in_custom_action() {
+    struct Names(OwnedParams);
+
+    impl Action for Names {
+        type Output = Vec<String>;
+        type Error = rusqlite::Error;
+
+        fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+            let mut stmt = conn.prepare("SELECT name FROM users WHERE age >= ? ORDER BY name")?;
+            self.0.bind(&mut stmt)?;
+            let mut rows = stmt.raw_query();
+            let mut names = vec![];
+            while let Some(row) = rows.next()? {
+                names.push(row.get(0)?);
+            }
+            Ok(names)
+        }
+    }
+
+    let mut conn = users();
+    conn.execute_batch("INSERT INTO users VALUES (1, 'foo', 30), (2, 'bar', 40), (3, 'baz', 20)")
+        .unwrap();
+    let names = Names(OwnedParams::from((30,))).run(&mut conn).unwrap();
+    assert_eq!(names, ["bar", "foo"]);
+
+    let err = Names(OwnedParams::new()).run(&mut conn).unwrap_err();
+    assert!(matches!(err, rusqlite::Error::InvalidParameterCount(0, 1)));
+}
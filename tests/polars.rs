@@ -0,0 +1,84 @@
+#![cfg(feature = "polars")]
+
+use polars::prelude::{AnyValue, DataType};
+use rusqlite::Connection;
+use vault::DataFrameError;
+
+fn mixed_table() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        "
+        CREATE TABLE items (
+            id INTEGER,
+            price REAL,
+            name TEXT,
+            data BLOB,
+            amount NUMERIC
+        );
+        INSERT INTO items VALUES
+            (1, 1.5, 'a', x'01', 2),
+            (2, NULL, NULL, NULL, 2.5),
+            (3, 3, 'c', x'0302', NULL);
+        ",
+    )
+    .unwrap();
+    conn
+}
+
+#[test]
+fn mixed_types() {
+    let conn = mixed_table();
+    let df = vault::query_dataframe(&conn, "SELECT *, NULL AS empty FROM items", []).unwrap();
+    assert_eq!(df.shape(), (3, 6));
+
+    let dtypes = df.dtypes();
+    assert_eq!(
+        dtypes,
+        [
+            DataType::Int64,
+            DataType::Float64,
+            DataType::String,
+            DataType::Binary,
+            // Inferred from the values, which contain an integer and a real
+            DataType::Float64,
+            DataType::Null,
+        ]
+    );
+
+    let price = df.column("price").unwrap();
+    assert_eq!(price.get(0).unwrap(), AnyValue::Float64(1.5));
+    assert_eq!(price.get(1).unwrap(), AnyValue::Null);
+    assert_eq!(price.get(2).unwrap(), AnyValue::Float64(3.0));
+
+    let name = df.column("name").unwrap();
+    assert_eq!(name.get(0).unwrap(), AnyValue::String("a"));
+    assert_eq!(name.get(1).unwrap(), AnyValue::Null);
+
+    let data = df.column("data").unwrap();
+    assert_eq!(data.get(2).unwrap(), AnyValue::Binary(&[3, 2]));
+
+    let amount = df.column("amount").unwrap();
+    assert_eq!(amount.get(0).unwrap(), AnyValue::Float64(2.0));
+    assert_eq!(amount.get(1).unwrap(), AnyValue::Float64(2.5));
+    assert_eq!(amount.get(2).unwrap(), AnyValue::Null);
+}
+
+#[test]
+fn type_mismatch() {
+    let conn = mixed_table();
+    conn.execute("INSERT INTO items (id) VALUES ('four')", [])
+        .unwrap();
+
+    let err = vault::query_dataframe(&conn, "SELECT id FROM items", []).unwrap_err();
+    let DataFrameError::TypeMismatch {
+        column,
+        expected,
+        actual,
+    } = err
+    else {
+        panic!("unexpected error {err:?}");
+    };
+    assert_eq!(column, "id");
+    assert_eq!(expected, DataType::Int64);
+    assert_eq!(actual, rusqlite::types::Type::Text);
+}
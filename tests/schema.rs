@@ -0,0 +1,147 @@
+use rusqlite::Connection;
+use vault::{
+    actions::{IndexExistsAction, SchemaDumpAction, TableExistsAction},
+    Action, SchemaDifference, VerifySchemaError,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+    CREATE TABLE posts (
+        id INTEGER PRIMARY KEY,
+        author INTEGER REFERENCES users (id),
+        body TEXT
+    );
+    CREATE INDEX posts_author ON posts (author);
+    CREATE VIEW post_counts AS SELECT author, count(*) AS posts FROM posts GROUP BY author;
+    CREATE TRIGGER users_delete AFTER DELETE ON users BEGIN
+        DELETE FROM posts WHERE author = old.id;
+    END;
+";
+
+fn with_schema() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(SCHEMA).unwrap();
+    conn
+}
+
+#[test]
+fn dump_round_trip() {
+    let mut conn = with_schema();
+    conn.execute_batch("INSERT INTO users VALUES (1, 'Alice')")
+        .unwrap();
+    let dump = SchemaDumpAction.run(&mut conn).unwrap();
+    assert_eq!(dump, vault::dump_schema(&conn).unwrap());
+
+    // Tables come first, then views, indexes and triggers
+    let order = [
+        "users",
+        "posts",
+        "post_counts",
+        "posts_author",
+        "users_delete",
+    ]
+    .map(|name| dump.find(&format!(" {name} ")).unwrap());
+    assert!(order.windows(2).all(|w| w[0] < w[1]), "{dump}");
+
+    // Automatically created indexes are left out
+    assert!(!dump.contains("sqlite_autoindex"), "{dump}");
+
+    // The dump creates the same schema in a fresh database, but no data
+    let copy = Connection::open_in_memory().unwrap();
+    copy.execute_batch(&dump).unwrap();
+    assert_eq!(vault::dump_schema(&copy).unwrap(), dump);
+    let users: i64 = copy
+        .query_row("SELECT count(*) FROM users", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(users, 0);
+}
+
+#[test]
+fn verify_schema_reports_differences() {
+    let conn = with_schema();
+
+    // Whitespace and comments don't matter
+    let reformatted = SCHEMA
+        .replace("    ", "  ")
+        .replace("body TEXT", "body TEXT -- the post's content\n/* more */");
+    vault::verify_schema(&conn, &reformatted).unwrap();
+
+    let expected = SCHEMA
+        .replace("body TEXT", "body TEXT NOT NULL")
+        .replace("CREATE INDEX posts_author ON posts (author);", "")
+        + "CREATE TABLE tags (name TEXT);";
+    let err = vault::verify_schema(&conn, &expected).unwrap_err();
+    let VerifySchemaError::Mismatch(differences) = &err else {
+        panic!("{err}");
+    };
+    let names = differences
+        .iter()
+        .map(|d| match d {
+            SchemaDifference::Missing { name, .. } => format!("missing {name}"),
+            SchemaDifference::Unexpected { name, .. } => format!("unexpected {name}"),
+            SchemaDifference::Changed { name, .. } => format!("changed {name}"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["changed posts", "missing tags", "unexpected posts_author"]
+    );
+
+    // The message shows the changed column like a diff
+    let message = err.to_string();
+    assert!(
+        message.starts_with("schema doesn't match expected schema\n"),
+        "{message}"
+    );
+    assert!(
+        message.contains("\n-        body TEXT NOT NULL\n"),
+        "{message}"
+    );
+    assert!(message.contains("\n+        body TEXT\n"), "{message}");
+    assert!(
+        message.contains("\n-CREATE TABLE tags (name TEXT)"),
+        "{message}"
+    );
+    assert!(
+        message.contains("\n+CREATE INDEX posts_author ON posts (author)"),
+        "{message}"
+    );
+}
+
+#[test]
+fn tables_and_indexes_exist() {
+    let empty = Connection::open_in_memory().unwrap();
+    assert!(!vault::table_exists(&empty, "users").unwrap());
+    assert!(!vault::index_exists(&empty, "posts_author").unwrap());
+
+    let mut conn = with_schema();
+    assert!(vault::table_exists(&conn, "users").unwrap());
+    assert!(vault::index_exists(&conn, "posts_author").unwrap());
+
+    // Names are case-insensitive
+    assert!(vault::table_exists(&conn, "USERS").unwrap());
+    assert!(vault::index_exists(&conn, "Posts_Author").unwrap());
+
+    // Other kinds of objects don't count
+    assert!(!vault::table_exists(&conn, "posts_author").unwrap());
+    assert!(!vault::table_exists(&conn, "post_counts").unwrap());
+    assert!(!vault::index_exists(&conn, "users").unwrap());
+
+    // Temporary tables aren't part of the main database
+    conn.execute_batch("CREATE TEMP TABLE scratch (x)").unwrap();
+    assert!(!vault::table_exists(&conn, "scratch").unwrap());
+
+    assert!(TableExistsAction("posts".to_string())
+        .run(&mut conn)
+        .unwrap());
+    assert!(!IndexExistsAction("missing".to_string())
+        .run(&mut conn)
+        .unwrap());
+
+    // Usable from within a migration to make it idempotent
+    let tx = conn.transaction().unwrap();
+    assert!(vault::table_exists(&tx, "posts").unwrap());
+    tx.execute_batch("DROP TABLE posts").unwrap();
+    assert!(!vault::table_exists(&tx, "posts").unwrap());
+    assert!(!vault::index_exists(&tx, "posts_author").unwrap());
+}
@@ -0,0 +1,1251 @@
+#![cfg(feature = "serde")]
+
+use std::fmt;
+
+use rusqlite::Connection;
+use serde::{
+    de::{DeserializeOwned, Visitor},
+    Deserialize, Deserializer,
+};
+use vault::FromRowOptions;
+
+fn via_index<T: DeserializeOwned>(conn: &Connection, sql: &str) -> rusqlite::Result<T> {
+    conn.query_row(sql, [], |r| vault::from_row_via_index(r))
+}
+
+fn via_name<T: DeserializeOwned>(conn: &Connection, sql: &str) -> rusqlite::Result<T> {
+    conn.query_row(sql, [], |r| vault::from_row_via_name(r))
+}
+
+fn via_index_with<T: DeserializeOwned>(
+    conn: &Connection,
+    sql: &str,
+    options: FromRowOptions,
+) -> rusqlite::Result<T> {
+    conn.query_row(sql, [], |r| vault::from_row_via_index_with(r, options))
+}
+
+fn memory() -> Connection {
+    Connection::open_in_memory().unwrap()
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct UserId(i64);
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct User {
+    id: UserId,
+    name: String,
+}
+
+#[test]
+fn newtype_field_via_index() {
+    let user: User = via_index(&memory(), "SELECT 42, 'foo'").unwrap();
+    assert_eq!(user.id, UserId(42));
+    assert_eq!(user.name, "foo");
+
+    let (id,): (UserId,) = via_index(&memory(), "SELECT 42").unwrap();
+    assert_eq!(id, UserId(42));
+}
+
+#[test]
+fn newtype_field_via_name() {
+    let user: User = via_name(&memory(), "SELECT 'foo' AS name, 42 AS id").unwrap();
+    assert_eq!(user.id, UserId(42));
+    assert_eq!(user.name, "foo");
+}
+
+/// Deserializes via `deserialize_bytes`, like `serde_bytes::ByteBuf`.
+struct ByteBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ByteBufVisitor;
+
+        impl Visitor<'_> for ByteBufVisitor {
+            type Value = ByteBuf;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("bytes")
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(ByteBuf(v.to_vec()))
+            }
+        }
+
+        deserializer.deserialize_bytes(ByteBufVisitor)
+    }
+}
+
+fn strict_table() -> Connection {
+    let conn = memory();
+    conn.execute_batch(
+        "
+        CREATE TABLE flags (
+            id INTEGER PRIMARY KEY,
+            enabled INTEGER NOT NULL,
+            payload BLOB NOT NULL,
+            ratio REAL NOT NULL
+        ) STRICT;
+        INSERT INTO flags VALUES (1, 0, x'00ff', 1), (2, 1, x'', 2.5);
+        ",
+    )
+    .unwrap();
+    conn
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Flag {
+    id: i64,
+    enabled: bool,
+    payload: Vec<u8>,
+    ratio: f64,
+}
+
+#[test]
+fn strict_table_round_trip() {
+    let conn = strict_table();
+    let flag: Flag = via_name(&conn, "SELECT * FROM flags WHERE id = 1").unwrap();
+    assert_eq!(
+        flag,
+        Flag {
+            id: 1,
+            enabled: false,
+            payload: vec![0x00, 0xff],
+            ratio: 1.0,
+        }
+    );
+    let flag: Flag = via_name(&conn, "SELECT * FROM flags WHERE id = 2").unwrap();
+    assert!(flag.enabled);
+    assert!(flag.payload.is_empty());
+
+    let (payload,): (ByteBuf,) =
+        via_index(&conn, "SELECT payload FROM flags WHERE id = 1").unwrap();
+    assert_eq!(payload.0, [0x00, 0xff]);
+}
+
+#[test]
+fn strict_table_type_mismatch() {
+    let conn = strict_table();
+
+    // STRICT tables store integers in REAL columns as REAL
+    let err = via_index::<(i64,)>(&conn, "SELECT ratio FROM flags WHERE id = 1").unwrap_err();
+    assert!(err.to_string().contains("floating point"), "{err}");
+    let err = via_index::<(bool,)>(&conn, "SELECT ratio FROM flags WHERE id = 1").unwrap_err();
+    assert!(err.to_string().contains("floating point"), "{err}");
+    let err = via_index::<(bool,)>(&conn, "SELECT payload FROM flags WHERE id = 1").unwrap_err();
+    assert!(err.to_string().contains("byte array"), "{err}");
+
+    // Values of the wrong type are rejected by SQLite itself
+    let err = conn
+        .execute("INSERT INTO flags VALUES (3, 'yes', x'', 0)", [])
+        .unwrap_err();
+    assert_eq!(
+        err.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::ConstraintViolation)
+    );
+}
+
+#[test]
+fn to_named_params_binds_fields_by_name() {
+    #[derive(serde::Serialize)]
+    struct NewUser<'a> {
+        id: UserIdParam,
+        name: &'a str,
+        admin: bool,
+        email: Option<&'a str>,
+    }
+
+    #[derive(serde::Serialize)]
+    struct UserIdParam(i64);
+
+    let mut conn = memory();
+    conn.execute_batch("CREATE TABLE users (id INTEGER, name TEXT, admin INTEGER, email TEXT)")
+        .unwrap();
+    let params = vault::to_named_params(&NewUser {
+        id: UserIdParam(7),
+        name: "foo",
+        admin: true,
+        email: None,
+    })
+    .unwrap();
+    let action = vault::actions::Exec::new(
+        "INSERT INTO users VALUES (:id, :name, :admin, :email)",
+        params,
+    );
+    vault::Action::run(action, &mut conn).unwrap();
+
+    let row: (i64, String, bool, Option<String>) = via_index(&conn, "SELECT * FROM users").unwrap();
+    assert_eq!(row, (7, "foo".to_string(), true, None));
+
+    assert!(vault::to_named_params(&42).is_err());
+    assert!(vault::to_named_params(&NewUserTooLarge { id: u64::MAX }).is_err());
+
+    #[derive(serde::Serialize)]
+    struct NewUserTooLarge {
+        id: u64,
+    }
+}
+
+#[test]
+fn borrowed_str_field() {
+    #[derive(Deserialize)]
+    struct Borrowed<'a> {
+        name: &'a str,
+        data: &'a [u8],
+    }
+
+    let conn = memory();
+    let mut stmt = conn
+        .prepare("SELECT 'foo' AS name, x'0102' AS data")
+        .unwrap();
+    let mut rows = stmt.query([]).unwrap();
+    let row = rows.next().unwrap().unwrap();
+
+    let borrowed: Borrowed<'_> = vault::from_row_via_name(row).unwrap();
+    assert_eq!(borrowed.name, "foo");
+    assert_eq!(borrowed.data, [1, 2]);
+
+    // The fields point directly into SQLite's buffers for the row
+    let name = row.get_ref("name").unwrap().as_str().unwrap();
+    let data = row.get_ref("data").unwrap().as_blob().unwrap();
+    assert_eq!(borrowed.name.as_ptr(), name.as_ptr());
+    assert_eq!(borrowed.data.as_ptr(), data.as_ptr());
+
+    let (name,): (&str,) = vault::from_row_via_index(row).unwrap();
+    assert_eq!(name.as_ptr(), borrowed.name.as_ptr());
+}
+
+#[cfg(feature = "json")]
+mod tagged_enum {
+    use serde::Deserialize;
+
+    use super::{memory, via_name, FromRowOptions};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Shape {
+        Circle { radius: f64 },
+        Square(f64),
+    }
+
+    #[test]
+    fn default_columns() {
+        let conn = memory();
+        let shape: Shape = via_name(
+            &conn,
+            r#"SELECT 'Circle' AS kind, '{"radius":1.5}' AS data"#,
+        )
+        .unwrap();
+        assert_eq!(shape, Shape::Circle { radius: 1.5 });
+
+        let shape: Shape = via_name(&conn, "SELECT 'Square' AS kind, '2.0' AS data").unwrap();
+        assert_eq!(shape, Shape::Square(2.0));
+
+        let err = via_name::<Shape>(&conn, "SELECT 'Square' AS kind").unwrap_err();
+        assert!(err.to_string().contains("\"data\""), "{err}");
+    }
+
+    #[test]
+    fn custom_columns() {
+        let conn = memory();
+        let options = FromRowOptions {
+            enum_tag_column: Some("shape"),
+            enum_content_column: Some("params"),
+            ..FromRowOptions::default()
+        };
+        let query = |sql: &str| {
+            conn.query_row(sql, [], |r| {
+                vault::from_row_via_name_with::<Shape>(r, options)
+            })
+        };
+
+        let shape = query(r#"SELECT 'Circle' AS shape, '{"radius":1.5}' AS params"#).unwrap();
+        assert_eq!(shape, Shape::Circle { radius: 1.5 });
+        let shape = query("SELECT 'Square' AS shape, '2.0' AS params").unwrap();
+        assert_eq!(shape, Shape::Square(2.0));
+
+        // The default columns are no longer used
+        assert!(query("SELECT 'Square' AS kind, '2.0' AS data").is_err());
+        let err = query("SELECT 'Square' AS shape, '2.0' AS data").unwrap_err();
+        assert!(err.to_string().contains("\"params\""), "{err}");
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Stats {
+    total: i64,
+    max_id: i64,
+}
+
+fn stats_table() -> Connection {
+    let conn = memory();
+    conn.execute_batch(
+        "
+        CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT);
+        INSERT INTO items (id, name) VALUES (3, 'a'), (7, 'b'), (5, 'c');
+        ",
+    )
+    .unwrap();
+    conn
+}
+
+#[test]
+fn named_aggregate_columns() {
+    let conn = stats_table();
+    let stats: Stats = via_name(
+        &conn,
+        "SELECT COUNT(*) AS total, MAX(id) AS max_id FROM items",
+    )
+    .unwrap();
+    assert_eq!(
+        stats,
+        Stats {
+            total: 3,
+            max_id: 7
+        }
+    );
+
+    // Extra columns are ignored by default
+    let stats: Stats = via_name(
+        &conn,
+        "SELECT MIN(id) AS min_id, MAX(id) AS max_id, COUNT(*) AS total FROM items",
+    )
+    .unwrap();
+    assert_eq!(
+        stats,
+        Stats {
+            total: 3,
+            max_id: 7
+        }
+    );
+
+    // Missing columns are always an error
+    let err =
+        via_name::<Stats>(&conn, "SELECT COUNT(*) AS total, 1 AS other FROM items").unwrap_err();
+    assert!(err.to_string().contains("max_id"), "{err}");
+    via_name::<Stats>(&conn, "SELECT COUNT(*) AS total FROM items").unwrap_err();
+}
+
+#[test]
+fn named_strict_column_count() {
+    let conn = stats_table();
+    let options = FromRowOptions {
+        strict_column_count: true,
+        ..Default::default()
+    };
+    let query = |sql: &str| {
+        conn.query_row(sql, [], |r| {
+            vault::from_row_via_name_with::<Stats>(r, options)
+        })
+    };
+
+    let stats = query("SELECT COUNT(*) AS total, MAX(id) AS max_id FROM items").unwrap();
+    assert_eq!(
+        stats,
+        Stats {
+            total: 3,
+            max_id: 7
+        }
+    );
+    let err = query("SELECT MIN(id) AS min_id, MAX(id) AS max_id, COUNT(*) AS total FROM items")
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("expected 2 columns, found 3"),
+        "{err}"
+    );
+}
+
+#[test]
+fn cow_fields() {
+    use std::borrow::Cow;
+
+    #[derive(Deserialize)]
+    struct Names<'a> {
+        #[serde(borrow)]
+        borrowed: Cow<'a, str>,
+        owned: Cow<'a, str>,
+        #[serde(borrow)]
+        optional: Option<Cow<'a, str>>,
+    }
+
+    let conn = memory();
+    let mut stmt = conn
+        .prepare("SELECT 'a' AS borrowed, 'b' AS owned, 'c' AS optional")
+        .unwrap();
+    let mut rows = stmt.query([]).unwrap();
+    let row = rows.next().unwrap().unwrap();
+
+    let names: Names<'_> = vault::from_row_via_name(row).unwrap();
+    assert!(matches!(names.borrowed, Cow::Borrowed("a")));
+    assert!(matches!(names.owned, Cow::Owned(ref s) if s == "b"));
+    // Serde never borrows inside an Option
+    assert!(matches!(names.optional, Some(Cow::Owned(ref s)) if s == "c"));
+}
+
+#[test]
+fn null_versus_missing_column_via_index() {
+    let conn = memory();
+
+    let row: (i64, Option<String>) = via_index(&conn, "SELECT 1, NULL").unwrap();
+    assert_eq!(row, (1, None));
+    let row: (i64, Option<String>) = via_index(&conn, "SELECT 1, 'foo'").unwrap();
+    assert_eq!(row, (1, Some("foo".to_string())));
+
+    // A missing column is an error, even for an Option
+    let err = via_index::<(i64, Option<String>)>(&conn, "SELECT 1").unwrap_err();
+    assert!(
+        err.to_string().contains("expected 2 columns, found 1"),
+        "{err}"
+    );
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn typed_output_through_tokio_vault() {
+    use vault::{actions::QueryAll, tokio::TokioVault};
+
+    let vault = TokioVault::launch(memory(), &[]).unwrap();
+    let users: Vec<User> = vault
+        .execute(QueryAll::new(
+            "SELECT 1 AS id, 'foo' AS name UNION ALL SELECT 2, 'bar'",
+            (),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(
+        users,
+        [
+            User {
+                id: UserId(1),
+                name: "foo".to_string()
+            },
+            User {
+                id: UserId(2),
+                name: "bar".to_string()
+            },
+        ]
+    );
+
+    // Errors are passed through with their original type as well
+    let err = vault
+        .execute(QueryAll::<User>::new("SELECT 1 AS id", ()))
+        .await
+        .unwrap_err();
+    let vault::tokio::Error::Action(err) = err else {
+        panic!("unexpected error {err:?}");
+    };
+    assert!(
+        err.to_string().contains("expected 2 columns, found 1"),
+        "{err}"
+    );
+    vault.stop().await;
+}
+
+#[test]
+fn text_booleans() {
+    let conn = memory();
+    let row: (bool, bool, bool, bool) =
+        via_index(&conn, "SELECT 'true', 'FALSE', '1', '0'").unwrap();
+    assert_eq!(row, (true, false, true, false));
+    let row: (bool, bool) = via_index(&conn, "SELECT 1, 0").unwrap();
+    assert_eq!(row, (true, false));
+
+    let err = via_index::<(bool,)>(&conn, "SELECT 'yes'").unwrap_err();
+    assert!(
+        err.to_string().contains("invalid boolean text \"yes\""),
+        "{err}"
+    );
+    let err = via_index::<(bool,)>(&conn, "SELECT ''").unwrap_err();
+    assert!(err.to_string().contains("invalid boolean text"), "{err}");
+}
+
+#[test]
+fn indexed_column_count() {
+    let conn = memory();
+    let strict = FromRowOptions {
+        strict_column_count: true,
+        ..FromRowOptions::default()
+    };
+
+    // Too many columns are ignored unless strict
+    let row: (i64, String) = via_index(&conn, "SELECT 1, 'foo', 'bar'").unwrap();
+    assert_eq!(row, (1, "foo".to_string()));
+    let user: User = via_index(&conn, "SELECT 1, 'foo', 'bar'").unwrap();
+    assert_eq!(user.id, UserId(1));
+    let err = via_index_with::<(i64, String)>(&conn, "SELECT 1, 'foo', 'bar'", strict).unwrap_err();
+    assert!(
+        err.to_string().contains("expected 2 columns, found 3"),
+        "{err}"
+    );
+    let err = via_index_with::<User>(&conn, "SELECT 1, 'foo', 'bar'", strict).unwrap_err();
+    assert!(
+        err.to_string().contains("expected 2 columns, found 3"),
+        "{err}"
+    );
+
+    // Too few columns are always an error
+    for options in [FromRowOptions::default(), strict] {
+        let err = via_index_with::<(i64, String)>(&conn, "SELECT 1", options).unwrap_err();
+        assert!(
+            err.to_string().contains("expected 2 columns, found 1"),
+            "{err}"
+        );
+        let err = via_index_with::<User>(&conn, "SELECT 1", options).unwrap_err();
+        assert!(
+            err.to_string().contains("expected 2 columns, found 1"),
+            "{err}"
+        );
+    }
+
+    // Exactly matching columns always work
+    let row: (i64, String) = via_index_with(&conn, "SELECT 1, 'foo'", strict).unwrap();
+    assert_eq!(row, (1, "foo".to_string()));
+}
+
+#[test]
+fn floats_from_real_and_text() {
+    let conn = memory();
+    let row: (f64, f32, f64) = via_index(&conn, "SELECT 1.5, 0.25, 3").unwrap();
+    assert_eq!(row, (1.5, 0.25, 3.0));
+    let row: (f64, f32, f64) = via_index(&conn, "SELECT '1.5', ' 0.25 ', '-1e3'").unwrap();
+    assert_eq!(row, (1.5, 0.25, -1000.0));
+
+    #[derive(Deserialize)]
+    struct Measurement {
+        value: f32,
+    }
+    let m: Measurement = via_name(&conn, "SELECT '2.5' AS value").unwrap();
+    assert_eq!(m.value, 2.5);
+
+    let err = via_index::<(f64,)>(&conn, "SELECT 'abc'").unwrap_err();
+    assert!(
+        err.to_string().contains("invalid float text \"abc\""),
+        "{err}"
+    );
+}
+
+#[test]
+fn optional_blob_round_trip() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Attachment {
+        id: i64,
+        data: Option<Vec<u8>>,
+    }
+
+    let conn = memory();
+    conn.execute_batch("CREATE TABLE attachments (id INTEGER PRIMARY KEY, data BLOB)")
+        .unwrap();
+    let attachments = [
+        Attachment {
+            id: 1,
+            data: Some(vec![0, 1, 255]),
+        },
+        Attachment { id: 2, data: None },
+        Attachment {
+            id: 3,
+            data: Some(vec![]),
+        },
+    ];
+    for attachment in &attachments {
+        conn.execute(
+            "INSERT INTO attachments VALUES (?, ?)",
+            (attachment.id, &attachment.data),
+        )
+        .unwrap();
+    }
+
+    let types: String = conn
+        .query_row(
+            "SELECT group_concat(t) FROM (SELECT typeof(data) AS t FROM attachments ORDER BY id)",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(types, "blob,null,blob");
+    for attachment in &attachments {
+        let sql = format!("SELECT * FROM attachments WHERE id = {}", attachment.id);
+        assert_eq!(&via_name::<Attachment>(&conn, &sql).unwrap(), attachment);
+    }
+
+    // Via deserialize_bytes on the Some path, like serde_bytes does
+    let (data,): (Option<ByteBuf>,) =
+        via_index(&conn, "SELECT data FROM attachments WHERE id = 1").unwrap();
+    assert_eq!(data.unwrap().0, [0, 1, 255]);
+    let (data,): (Option<ByteBuf>,) =
+        via_index(&conn, "SELECT data FROM attachments WHERE id = 2").unwrap();
+    assert!(data.is_none());
+}
+
+#[test]
+fn str_and_string_via_index() {
+    let conn = memory();
+    let mut stmt = conn.prepare("SELECT 'foo', 'bar', ''").unwrap();
+    let mut rows = stmt.query([]).unwrap();
+    let row = rows.next().unwrap().unwrap();
+
+    let (borrowed, owned, empty): (&str, String, String) = vault::from_row_via_index(row).unwrap();
+    assert_eq!(borrowed, "foo");
+    assert_eq!(owned, "bar");
+    assert_eq!(empty, "");
+    let text = row.get_ref(0).unwrap().as_str().unwrap();
+    assert_eq!(borrowed.as_ptr(), text.as_ptr());
+
+    // Owned strings don't borrow from the row
+    let (owned,): (String,) = via_index(&conn, "SELECT 'baz'").unwrap();
+    assert_eq!(owned, "baz");
+
+    let err = via_index::<(String,)>(&conn, "SELECT 42").unwrap_err();
+    assert!(err.to_string().contains("expected a string"), "{err}");
+}
+
+#[test]
+fn empty_string_as_null() {
+    let conn = memory();
+    let options = FromRowOptions {
+        empty_string_as_null: true,
+        ..FromRowOptions::default()
+    };
+
+    // By default, only NULL is None
+    let row: (Option<String>, Option<String>, Option<String>) =
+        via_index(&conn, "SELECT '', NULL, 'foo'").unwrap();
+    assert_eq!(row, (Some(String::new()), None, Some("foo".to_string())));
+
+    let row: (Option<String>, Option<String>, Option<String>) =
+        via_index_with(&conn, "SELECT '', NULL, 'foo'", options).unwrap();
+    assert_eq!(row, (None, None, Some("foo".to_string())));
+
+    // Non-optional fields and empty blobs are unaffected
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Legacy {
+        name: String,
+        email: Option<String>,
+        avatar: Option<Vec<u8>>,
+    }
+    let legacy = conn
+        .query_row("SELECT '' AS name, '' AS email, x'' AS avatar", [], |r| {
+            vault::from_row_via_name_with::<Legacy>(r, options)
+        })
+        .unwrap();
+    assert_eq!(
+        legacy,
+        Legacy {
+            name: String::new(),
+            email: None,
+            avatar: Some(vec![]),
+        }
+    );
+}
+
+#[test]
+fn named_column_count() {
+    let conn = stats_table();
+    let strict = FromRowOptions {
+        strict_column_count: true,
+        ..FromRowOptions::default()
+    };
+    let query = |sql: &str, options| {
+        conn.query_row(sql, [], |r| {
+            vault::from_row_via_name_with::<Stats>(r, options)
+        })
+    };
+
+    // Too few columns are always an error
+    for options in [FromRowOptions::default(), strict] {
+        let err = query("SELECT COUNT(*) AS total FROM items", options).unwrap_err();
+        assert!(
+            err.to_string().contains("expected 2 columns, found 1"),
+            "{err}"
+        );
+    }
+
+    // Enough columns with the wrong names are an error too
+    let err = query(
+        "SELECT COUNT(*) AS total, MAX(id) AS maximum FROM items",
+        strict,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("max_id"), "{err}");
+
+    // Too many columns are only an error if strict
+    let sql = "SELECT COUNT(*) AS total, MAX(id) AS max_id, MIN(id) AS min_id FROM items";
+    assert!(query(sql, FromRowOptions::default()).is_ok());
+    let err = query(sql, strict).unwrap_err();
+    assert!(
+        err.to_string().contains("expected 2 columns, found 3"),
+        "{err}"
+    );
+}
+
+#[test]
+fn wide_integers_round_trip() {
+    let conn = memory();
+    conn.execute_batch("CREATE TABLE wide (signed TEXT, unsigned TEXT)")
+        .unwrap();
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Wide {
+        signed: i128,
+        unsigned: u128,
+    }
+
+    let values = [(i128::MIN, u128::MIN), (i128::MAX, u128::MAX), (-1, 1)];
+    for (signed, unsigned) in values {
+        conn.execute(
+            "INSERT INTO wide (signed, unsigned) VALUES (?, ?)",
+            (signed.to_string(), unsigned.to_string()),
+        )
+        .unwrap();
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT signed, unsigned FROM wide ORDER BY rowid")
+        .unwrap();
+    let rows = stmt
+        .query_map([], |r| vault::from_row_via_name::<Wide>(r))
+        .unwrap()
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .unwrap();
+    let expected = values
+        .into_iter()
+        .map(|(signed, unsigned)| Wide { signed, unsigned })
+        .collect::<Vec<_>>();
+    assert_eq!(rows, expected);
+
+    // INTEGER values widen as well
+    let row: (i128, u128) = via_index(&conn, "SELECT -5, 5").unwrap();
+    assert_eq!(row, (-5, 5));
+
+    // Text out of range is an error
+    let err = via_index::<(u128,)>(&conn, "SELECT '-1'").unwrap_err();
+    assert!(err.to_string().contains("\"-1\""), "{err}");
+}
+
+#[test]
+fn split_row_between_two_structs() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Person {
+        id: i64,
+        name: String,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Address {
+        street: String,
+        city: String,
+        zip: Option<String>,
+    }
+
+    let conn = memory();
+    conn.execute_batch(
+        "
+        CREATE TABLE people (id INTEGER PRIMARY KEY, name TEXT);
+        CREATE TABLE addresses (person INTEGER, street TEXT, city TEXT, zip TEXT);
+        INSERT INTO people VALUES (1, 'Alice'), (2, 'Bob');
+        INSERT INTO addresses VALUES (1, 'Main St', 'Springfield', '12345');
+        INSERT INTO addresses VALUES (2, 'Side St', 'Shelbyville', NULL);
+        ",
+    )
+    .unwrap();
+
+    let mut stmt = conn
+        .prepare(
+            "
+            SELECT p.id, p.name, a.street, a.city, a.zip
+            FROM people p JOIN addresses a ON a.person = p.id
+            ORDER BY p.id
+            ",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map([], |r| vault::from_row_split::<Person, Address>(r, 2))
+        .unwrap()
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(
+        rows,
+        [
+            (
+                Person {
+                    id: 1,
+                    name: "Alice".to_string()
+                },
+                Address {
+                    street: "Main St".to_string(),
+                    city: "Springfield".to_string(),
+                    zip: Some("12345".to_string())
+                }
+            ),
+            (
+                Person {
+                    id: 2,
+                    name: "Bob".to_string()
+                },
+                Address {
+                    street: "Side St".to_string(),
+                    city: "Shelbyville".to_string(),
+                    zip: None
+                }
+            ),
+        ]
+    );
+
+    // If the split is past the last column, all columns go to the first value
+    let split = conn
+        .query_row("SELECT 1, 2", [], |r| {
+            vault::from_row_split::<(i64, i64), Vec<i64>>(r, 5)
+        })
+        .unwrap();
+    assert_eq!(split, ((1, 2), vec![]));
+
+    // Each half only sees its own columns
+    let err = conn
+        .query_row("SELECT 1, 'Alice', 'Main St'", [], |r| {
+            vault::from_row_split::<Person, Address>(r, 2)
+        })
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("expected 3 columns, found 1"),
+        "{err}"
+    );
+}
+
+#[test]
+fn unix_timestamp_round_trip() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use vault::UnixTimestamp;
+
+    let conn = memory();
+    conn.execute_batch("CREATE TABLE events (at INTEGER)")
+        .unwrap();
+
+    let times = [
+        UNIX_EPOCH,
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        // Before 1970
+        UNIX_EPOCH - Duration::from_secs(86_400 * 365),
+    ];
+    for time in times {
+        conn.execute("INSERT INTO events VALUES (?)", [UnixTimestamp(time)])
+            .unwrap();
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT at FROM events ORDER BY rowid")
+        .unwrap();
+    let stored = stmt
+        .query_map([], |r| r.get::<_, i64>(0))
+        .unwrap()
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(stored, [0, 1_700_000_000, -31_536_000]);
+
+    let read = stmt
+        .query_map([], |r| vault::from_row_via_index::<(UnixTimestamp,)>(r))
+        .unwrap()
+        .map(|row| row.map(|(t,)| t.0))
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(read, times);
+
+    // Sub-second precision rounds towards the past
+    let before = UnixTimestamp(UNIX_EPOCH - Duration::from_millis(1500));
+    assert_eq!(before.as_secs(), Some(-2));
+    let after = UnixTimestamp(UNIX_EPOCH + Duration::from_millis(1500));
+    assert_eq!(after.as_secs(), Some(1));
+}
+
+#[test]
+fn flatten_remaining_columns() {
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Player {
+        id: i64,
+        name: String,
+        #[serde(flatten)]
+        extra: HashMap<String, i64>,
+    }
+
+    let conn = memory();
+    let player: Player = via_name(
+        &conn,
+        "SELECT 10 AS score, 1 AS id, 3 AS level, 'Alice' AS name",
+    )
+    .unwrap();
+    assert_eq!(
+        player,
+        Player {
+            id: 1,
+            name: "Alice".to_string(),
+            extra: HashMap::from([("score".to_string(), 10), ("level".to_string(), 3)]),
+        }
+    );
+
+    // Without remaining columns, the map stays empty
+    let player: Player = via_name(&conn, "SELECT 2 AS id, 'Bob' AS name").unwrap();
+    assert!(player.extra.is_empty());
+
+    // The known fields are still required
+    let err = via_name::<Player>(&conn, "SELECT 3 AS id, 4 AS score").unwrap_err();
+    assert!(err.to_string().contains("name"), "{err}");
+}
+
+#[test]
+fn internally_tagged_enum_on_shared_table() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(tag = "shape")]
+    enum Shape {
+        Circle { radius: f64 },
+        Rect { width: i64, height: i64 },
+    }
+
+    let conn = memory();
+    conn.execute_batch(
+        "
+        CREATE TABLE shapes (shape TEXT, radius REAL, width INTEGER, height INTEGER);
+        INSERT INTO shapes VALUES ('Circle', 1.5, NULL, NULL);
+        INSERT INTO shapes VALUES ('Rect', NULL, 2, 3);
+        ",
+    )
+    .unwrap();
+
+    let mut stmt = conn.prepare("SELECT * FROM shapes ORDER BY rowid").unwrap();
+    let shapes = stmt
+        .query_map([], |r| vault::from_row_via_name::<Shape>(r))
+        .unwrap()
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(
+        shapes,
+        [
+            Shape::Circle { radius: 1.5 },
+            Shape::Rect {
+                width: 2,
+                height: 3
+            },
+        ]
+    );
+
+    let err = via_name::<Shape>(&conn, "SELECT 'Triangle' AS shape").unwrap_err();
+    assert!(err.to_string().contains("Triangle"), "{err}");
+
+    // The columns are buffered before the variant sees them, so the value
+    // must already have the right storage class
+    #[derive(Debug, Deserialize)]
+    #[serde(tag = "kind")]
+    enum Flag {
+        #[allow(dead_code)]
+        Toggle { enabled: bool },
+    }
+
+    let err = via_name::<Flag>(&conn, "SELECT 'Toggle' AS kind, 1 AS enabled").unwrap_err();
+    assert!(err.to_string().contains("expected a boolean"), "{err}");
+}
+
+#[test]
+fn f64_blob_round_trip() {
+    use vault::F64Blob;
+
+    let conn = memory();
+    conn.execute_batch("CREATE TABLE vectors (v BLOB)").unwrap();
+    let values = vec![1.0, -2.5, f64::MAX, f64::MIN_POSITIVE, 0.0];
+    conn.execute("INSERT INTO vectors VALUES (?)", [F64Blob(values.clone())])
+        .unwrap();
+    conn.execute("INSERT INTO vectors VALUES (?)", [F64Blob::default()])
+        .unwrap();
+
+    // The bytes are little-endian regardless of the platform
+    let bytes: Vec<u8> = conn
+        .query_row("SELECT v FROM vectors WHERE rowid = 1", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(bytes.len(), 40);
+    assert_eq!(bytes[..8], [0, 0, 0, 0, 0, 0, 0xf0, 0x3f]);
+    assert_eq!(bytes[8..16], [0, 0, 0, 0, 0, 0, 0x04, 0xc0]);
+
+    let mut stmt = conn
+        .prepare("SELECT v FROM vectors ORDER BY rowid")
+        .unwrap();
+    let read = stmt
+        .query_map([], |r| vault::from_row_via_index::<(F64Blob,)>(r))
+        .unwrap()
+        .map(|row| row.map(|(v,)| v.0))
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(read, [values, vec![]]);
+
+    // Blobs whose length isn't a multiple of 8 are rejected
+    let err = via_index::<(F64Blob,)>(&conn, "SELECT x'0102030405'").unwrap_err();
+    assert!(err.to_string().contains("multiple of 8"), "{err}");
+}
+
+#[test]
+fn integers_from_numeric_text() {
+    let conn = memory();
+    let options = FromRowOptions {
+        parse_numeric_text: true,
+        ..FromRowOptions::default()
+    };
+
+    // By default, text is never an integer
+    let err = via_index::<(i64,)>(&conn, "SELECT '42'").unwrap_err();
+    assert!(err.to_string().contains("invalid type: string"), "{err}");
+
+    let row: (i64, i32, u16, i64) =
+        via_index_with(&conn, "SELECT '42', ' -7 ', '65535', 3", options).unwrap();
+    assert_eq!(row, (42, -7, 65535, 3));
+
+    // A NUMERIC column keeps text that doesn't look like a number as text
+    conn.execute_batch(
+        "
+        CREATE TABLE amounts (amount NUMERIC);
+        INSERT INTO amounts VALUES ('12'), ('12 apples');
+        ",
+    )
+    .unwrap();
+    let amount = conn
+        .query_row("SELECT amount FROM amounts WHERE rowid = 1", [], |r| {
+            vault::from_row_via_index_with::<(i64,)>(r, options)
+        })
+        .unwrap();
+    assert_eq!(amount, (12,));
+    let err = conn
+        .query_row("SELECT amount FROM amounts WHERE rowid = 2", [], |r| {
+            vault::from_row_via_index_with::<(i64,)>(r, options)
+        })
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("invalid or out of range integer text \"12 apples\""),
+        "{err}"
+    );
+
+    // Values must fit into the requested type
+    for sql in [
+        "SELECT '4.5'",
+        "SELECT '-1'",
+        "SELECT '99999999999999999999'",
+    ] {
+        assert!(
+            via_index_with::<(u32,)>(&conn, sql, options).is_err(),
+            "{sql}"
+        );
+    }
+    assert!(via_index_with::<(u8,)>(&conn, "SELECT '300'", options).is_err());
+}
+
+#[cfg(feature = "json")]
+mod json_text {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+    use vault::Json;
+
+    use super::{memory, via_index, via_index_with, FromRowOptions};
+
+    fn parse() -> FromRowOptions {
+        FromRowOptions {
+            parse_json_text: true,
+            ..FromRowOptions::default()
+        }
+    }
+
+    #[test]
+    fn json_wrapper_by_default() {
+        let conn = memory();
+        let err = via_index::<(Vec<i32>,)>(&conn, "SELECT '[1, 2, 3]'").unwrap_err();
+        assert!(err.to_string().contains("invalid type: string"), "{err}");
+
+        let (Json(numbers),): (Json<Vec<i32>>,) = via_index(&conn, "SELECT '[1, 2, 3]'").unwrap();
+        assert_eq!(numbers, [1, 2, 3]);
+    }
+
+    #[test]
+    fn parse_json_text() {
+        let conn = memory();
+        let (numbers,): (Vec<i32>,) = via_index_with(&conn, "SELECT '[1, 2, 3]'", parse()).unwrap();
+        assert_eq!(numbers, [1, 2, 3]);
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Settings {
+            tags: Vec<String>,
+            labels: HashMap<String, String>,
+            point: (i64, i64),
+        }
+        let settings = conn
+            .query_row(
+                r#"
+                SELECT
+                    '["a", "b"]' AS tags,
+                    '{"color": "red"}' AS labels,
+                    '[1, 2]' AS point
+                "#,
+                [],
+                |r| vault::from_row_via_name_with::<Settings>(r, parse()),
+            )
+            .unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                tags: vec!["a".to_string(), "b".to_string()],
+                labels: HashMap::from([("color".to_string(), "red".to_string())]),
+                point: (1, 2),
+            }
+        );
+
+        // Plain strings are unaffected
+        let (text,): (String,) = via_index_with(&conn, "SELECT 'plain text'", parse()).unwrap();
+        assert_eq!(text, "plain text");
+
+        // Text that isn't JSON can't be read as a sequence
+        let err = via_index_with::<(Vec<i32>,)>(&conn, "SELECT 'plain text'", parse()).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("expected value at line 1 column 1"),
+            "{err}"
+        );
+
+        // Blobs are still byte sequences
+        let (bytes,): (Vec<u8>,) = via_index_with(&conn, "SELECT x'0102'", parse()).unwrap();
+        assert_eq!(bytes, [1, 2]);
+    }
+}
+
+#[test]
+fn case_insensitive_enums() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Status {
+        Active,
+        Inactive,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Legacy {
+        Active,
+        #[serde(rename = "ACTIVE")]
+        Shouting,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Role {
+        Admin,
+        User,
+    }
+
+    let conn = memory();
+    let options = FromRowOptions {
+        case_insensitive_enums: true,
+        ..FromRowOptions::default()
+    };
+
+    // By default, the case must match
+    let err = via_index::<(Status,)>(&conn, "SELECT 'ACTIVE'").unwrap_err();
+    assert!(
+        err.to_string().contains("unknown variant `ACTIVE`"),
+        "{err}"
+    );
+
+    let row: (Status, Status, Role, Role) = via_index_with(
+        &conn,
+        "SELECT 'ACTIVE', 'inactive', 'ADMIN', 'user'",
+        options,
+    )
+    .unwrap();
+    assert_eq!(
+        row,
+        (Status::Active, Status::Inactive, Role::Admin, Role::User)
+    );
+
+    // An exact match wins
+    let row: (Legacy, Legacy) =
+        via_index_with(&conn, "SELECT 'ACTIVE', 'active'", options).unwrap();
+    assert_eq!(row, (Legacy::Shouting, Legacy::Active));
+
+    // Names still have to match apart from their case
+    let err = via_index_with::<(Role,)>(&conn, "SELECT 'admins'", options).unwrap_err();
+    assert!(
+        err.to_string().contains("unknown variant `admins`"),
+        "{err}"
+    );
+}
+
+#[test]
+fn strict_bools() {
+    let conn = memory();
+    let strict = FromRowOptions {
+        strict_bools: true,
+        ..FromRowOptions::default()
+    };
+
+    // Like in SQL, any nonzero integer is true by default
+    let row: (bool, bool, bool) = via_index(&conn, "SELECT 2, -1, 0").unwrap();
+    assert_eq!(row, (true, true, false));
+
+    let row: (bool, bool, bool, bool) =
+        via_index_with(&conn, "SELECT 1, 0, 'true', '0'", strict).unwrap();
+    assert_eq!(row, (true, false, true, false));
+    for sql in ["SELECT 2", "SELECT -1"] {
+        let err = via_index_with::<(bool,)>(&conn, sql, strict).unwrap_err();
+        assert!(
+            err.to_string().contains("invalid boolean integer"),
+            "{sql}: {err}"
+        );
+    }
+
+    // Text is handled the same either way
+    let err = via_index_with::<(bool,)>(&conn, "SELECT 'yes'", strict).unwrap_err();
+    assert!(
+        err.to_string().contains("invalid boolean text \"yes\""),
+        "{err}"
+    );
+}
+
+#[test]
+fn query_scalar_all() {
+    let mut conn = memory();
+    conn.execute_batch(
+        "
+        CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);
+        INSERT INTO users VALUES (3, 'carol'), (1, 'alice'), (2, 'bob');
+        ",
+    )
+    .unwrap();
+
+    let ids: Vec<i64> =
+        vault::query_scalar_all(&conn, "SELECT id FROM users ORDER BY id", []).unwrap();
+    assert_eq!(ids, [1, 2, 3]);
+
+    // Additional columns are ignored
+    let names: Vec<String> = vault::query_scalar_all(
+        &conn,
+        "SELECT name, id FROM users WHERE id > ? ORDER BY name",
+        [1],
+    )
+    .unwrap();
+    assert_eq!(names, ["bob", "carol"]);
+
+    let none: Vec<i64> =
+        vault::query_scalar_all(&conn, "SELECT id FROM users WHERE 0", []).unwrap();
+    assert!(none.is_empty());
+
+    // Values are checked like tuple elements
+    let err = vault::query_scalar_all::<i64, _>(&conn, "SELECT name FROM users", []).unwrap_err();
+    assert!(err.to_string().contains("invalid type: string"), "{err}");
+
+    // Statements without columns are rejected before they run
+    let err = vault::query_scalar_all::<i64, _>(&conn, "DELETE FROM users", []).unwrap_err();
+    assert!(
+        err.to_string().contains("expected 1 columns, found 0"),
+        "{err}"
+    );
+    let count: i64 = conn
+        .query_row("SELECT count(*) FROM users", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(count, 3);
+
+    let names = vault::QueryScalarAll::<String>::new("SELECT name FROM users ORDER BY id", ());
+    let names = vault::Action::run(names, &mut conn).unwrap();
+    assert_eq!(names, ["alice", "bob", "carol"]);
+}
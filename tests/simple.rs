@@ -0,0 +1,170 @@
+use std::path::PathBuf;
+
+use rusqlite::{Connection, Transaction};
+use vault::{simple::SimpleVault, MigrateError, Migration, MigrationStep};
+
+const APPLICATION_ID: i32 = 0x7661_756c;
+
+fn create_table(tx: &mut Transaction<'_>, _: usize, _: usize) -> rusqlite::Result<()> {
+    tx.execute_batch("CREATE TABLE t (id INTEGER PRIMARY KEY)")
+}
+
+fn fail(tx: &mut Transaction<'_>, _: usize, _: usize) -> rusqlite::Result<()> {
+    tx.execute_batch("SELECT * FROM missing")
+}
+
+/// A path to a database file that doesn't exist yet.
+fn temp_db(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("vault-{}-{name}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn application_id(conn: &Connection) -> i32 {
+    conn.query_row("SELECT * FROM pragma_application_id", [], |r| r.get(0))
+        .unwrap()
+}
+
+#[test]
+fn application_id_is_claimed() {
+    let conn = Connection::open_in_memory().unwrap();
+    let vault = SimpleVault::builder()
+        .with_application_id(APPLICATION_ID)
+        .build(conn, &[create_table])
+        .unwrap();
+    let conn = vault.into_connection();
+    assert_eq!(application_id(&conn), APPLICATION_ID);
+
+    // Opening it again with the same id works
+    SimpleVault::builder()
+        .with_application_id(APPLICATION_ID)
+        .build(conn, &[create_table])
+        .unwrap();
+}
+
+#[test]
+fn application_id_mismatch_is_rejected() {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "application_id", 1).unwrap();
+    let err = SimpleVault::builder()
+        .with_application_id(APPLICATION_ID)
+        .build_mixed(conn, &[MigrationStep::Transactional(create_table)])
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        MigrateError::ApplicationIdMismatch {
+            expected: APPLICATION_ID,
+            actual: 1
+        }
+    ));
+}
+
+#[test]
+fn application_id_is_not_claimed_if_migrations_fail() {
+    let path = temp_db("unclaimed");
+    let migrations: [Migration; 2] = [create_table, fail];
+    let err = SimpleVault::builder()
+        .with_application_id(APPLICATION_ID)
+        .build(Connection::open(&path).unwrap(), &migrations)
+        .unwrap_err();
+    assert!(matches!(err, MigrateError::Migration { index: 1, .. }));
+
+    let conn = Connection::open(&path).unwrap();
+    assert_eq!(application_id(&conn), 0);
+    assert_eq!(vault::applied_migration_count(&conn).unwrap(), 0);
+    drop(conn);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn failing_migration_reports_its_index() {
+    fn create_other_table(tx: &mut Transaction<'_>, _: usize, _: usize) -> rusqlite::Result<()> {
+        tx.execute_batch("CREATE TABLE u (id INTEGER PRIMARY KEY)")
+    }
+
+    fn create_third_table(tx: &mut Transaction<'_>, _: usize, _: usize) -> rusqlite::Result<()> {
+        tx.execute_batch("CREATE TABLE v (id INTEGER PRIMARY KEY)")
+    }
+
+    let path = temp_db("failing-migration");
+    SimpleVault::new(
+        Connection::open(&path).unwrap(),
+        &[create_table, create_other_table],
+    )
+    .unwrap();
+
+    let migrations: [Migration; 4] = [create_table, create_other_table, fail, create_third_table];
+    let err = SimpleVault::new(Connection::open(&path).unwrap(), &migrations).unwrap_err();
+    let MigrateError::Migration { index, source } = err else {
+        panic!("unexpected error {err:?}");
+    };
+    assert_eq!(index, 2);
+    assert!(
+        source.to_string().contains("no such table: missing"),
+        "{source}"
+    );
+
+    // The first two migrations stay applied
+    let conn = Connection::open(&path).unwrap();
+    assert_eq!(vault::applied_migration_count(&conn).unwrap(), 2);
+    conn.execute_batch("SELECT * FROM t; SELECT * FROM u")
+        .unwrap();
+    assert!(conn.execute_batch("SELECT * FROM v").is_err());
+    drop(conn);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn transaction_rolls_back_on_error() {
+    #[derive(Debug)]
+    enum Error {
+        Aborted,
+        Rusqlite(rusqlite::Error),
+    }
+
+    impl From<rusqlite::Error> for Error {
+        fn from(value: rusqlite::Error) -> Self {
+            Self::Rusqlite(value)
+        }
+    }
+
+    fn count(vault: &mut SimpleVault) -> i64 {
+        vault
+            .transaction(|tx| tx.query_row("SELECT count(*) FROM t", [], |r| r.get(0)))
+            .unwrap()
+    }
+
+    let mut vault =
+        SimpleVault::new(Connection::open_in_memory().unwrap(), &[create_table]).unwrap();
+    vault
+        .transaction(|tx| tx.execute_batch("INSERT INTO t VALUES (1)"))
+        .unwrap();
+    assert_eq!(count(&mut vault), 1);
+
+    // Errors returned by the closure itself roll back its changes
+    let err = vault
+        .transaction(|tx| {
+            tx.execute_batch("INSERT INTO t VALUES (2); INSERT INTO t VALUES (3)")?;
+            Err::<(), _>(Error::Aborted)
+        })
+        .unwrap_err();
+    assert!(matches!(err, Error::Aborted));
+    assert_eq!(count(&mut vault), 1);
+
+    // So do errors from rusqlite, even after earlier statements succeeded
+    let err = vault
+        .transaction(|tx| {
+            tx.execute_batch("INSERT INTO t VALUES (4)")?;
+            tx.execute_batch("INSERT INTO t VALUES (1)")?;
+            Ok::<_, Error>(())
+        })
+        .unwrap_err();
+    let Error::Rusqlite(err) = err else {
+        panic!("unexpected error {err:?}");
+    };
+    assert_eq!(
+        err.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::ConstraintViolation)
+    );
+    assert_eq!(count(&mut vault), 1);
+}
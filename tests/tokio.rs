@@ -0,0 +1,1566 @@
+#![cfg(feature = "tokio")]
+
+use std::{path::PathBuf, time::Duration};
+
+use rusqlite::{Connection, ErrorCode};
+use vault::{tokio::TokioVault, Action};
+
+/// Count forever, until interrupted.
+const ENDLESS_QUERY: &str = "
+    WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n)
+    SELECT count(*) FROM n
+";
+
+struct Query(&'static str);
+
+impl Action for Query {
+    type Output = i64;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.query_row(self.0, [], |r| r.get(0))
+    }
+}
+
+/// Execute several statements at once.
+struct Batch(&'static str);
+
+impl Action for Batch {
+    type Output = ();
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.execute_batch(self.0)
+    }
+}
+
+fn launch() -> TokioVault {
+    TokioVault::launch(Connection::open_in_memory().unwrap(), &[]).unwrap()
+}
+
+fn temp_db(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("vault-tokio-{}-{name}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn is_code(err: &rusqlite::Error, code: ErrorCode) -> bool {
+    err.sqlite_error_code() == Some(code)
+}
+
+#[tokio::test]
+async fn interrupt_aborts_running_query() {
+    let vault = launch();
+    let handle = vault.interrupt_handle();
+
+    let running = tokio::spawn({
+        let vault = vault.clone();
+        async move { vault.execute(Query(ENDLESS_QUERY)).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    handle.interrupt();
+
+    let err = running.await.unwrap().unwrap_err();
+    let vault::tokio::Error::Action(err) = err else {
+        panic!("unexpected error {err:?}");
+    };
+    assert!(is_code(&err, ErrorCode::OperationInterrupted));
+
+    // Interrupting while idle doesn't affect later actions
+    handle.interrupt();
+    assert_eq!(vault.execute(Query("SELECT 42")).await.unwrap(), 42);
+    vault.stop().await;
+}
+
+#[cfg(feature = "serde")]
+#[tokio::test]
+async fn bulk_insert_serialized_rows() {
+    #[derive(serde::Serialize)]
+    struct Item {
+        id: i64,
+        name: String,
+    }
+
+    let vault = launch();
+    vault
+        .execute(vault::actions::Exec::new(
+            "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL)",
+            (),
+        ))
+        .await
+        .unwrap();
+
+    let rows = (0..10_000).map(|id| Item {
+        id,
+        name: format!("item {id}"),
+    });
+    let inserted = vault
+        .bulk_insert("INSERT INTO items VALUES (:id, :name)", rows, 500)
+        .await
+        .unwrap();
+    assert_eq!(inserted, 10_000);
+    assert_eq!(
+        vault
+            .execute(Query("SELECT count(*) FROM items"))
+            .await
+            .unwrap(),
+        10_000
+    );
+
+    let err = vault
+        .bulk_insert(
+            "INSERT INTO items VALUES (:id, :name)",
+            Vec::<Item>::new(),
+            0,
+        )
+        .await
+        .unwrap_err();
+    let vault::tokio::Error::Action(err) = err else {
+        panic!("unexpected error {err:?}");
+    };
+    assert_eq!(err.committed, 0);
+    vault.stop().await;
+}
+
+#[cfg(feature = "serde")]
+#[tokio::test]
+async fn insert_returning_through_vault() {
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Item {
+        id: i64,
+        name: String,
+    }
+
+    let vault = launch();
+    vault
+        .execute(Batch(
+            "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL DEFAULT 'new')",
+        ))
+        .await
+        .unwrap();
+
+    // The action and its output are sent between threads
+    let items = vault
+        .execute(vault::actions::InsertReturning::<Item>::new(
+            "INSERT INTO items (id) VALUES (NULL), (NULL) RETURNING id, name",
+            (),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(
+        items,
+        [
+            Item {
+                id: 1,
+                name: "new".to_string()
+            },
+            Item {
+                id: 2,
+                name: "new".to_string()
+            },
+        ]
+    );
+    vault.stop().await;
+}
+
+#[tokio::test]
+async fn fatal_error_reopens_connection() {
+    let broken = temp_db("reopen-broken");
+    let fallback = temp_db("reopen-fallback");
+    Connection::open(&fallback)
+        .unwrap()
+        .execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (42)")
+        .unwrap();
+
+    let conn = Connection::open(&broken).unwrap();
+    conn.execute_batch("CREATE TABLE t (x)").unwrap();
+    let vault = TokioVault::builder()
+        .with_pragma("foreign_keys", true)
+        .with_application_id(7)
+        .with_connection_factory({
+            let fallback = fallback.clone();
+            move || Connection::open(&fallback)
+        })
+        .launch(conn, &[])
+        .unwrap();
+
+    // Errors that aren't fatal keep the connection
+    let err = vault
+        .execute(Query("SELECT x FROM missing"))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, vault::tokio::Error::Action(_)));
+    let err = vault.execute(Query("SELECT x FROM t")).await.unwrap_err();
+    let vault::tokio::Error::Action(err) = err else {
+        panic!("unexpected error {err:?}");
+    };
+    assert!(matches!(err, rusqlite::Error::QueryReturnedNoRows));
+
+    std::fs::write(&broken, vec![0xff; 4096]).unwrap();
+    let err = vault.execute(Query("SELECT x FROM t")).await.unwrap_err();
+    let vault::tokio::Error::Action(err) = err else {
+        panic!("unexpected error {err:?}");
+    };
+    assert!(is_code(&err, ErrorCode::NotADatabase), "{err}");
+
+    // The failed action's error was fatal, so the fallback is used now
+    assert_eq!(vault.execute(Query("SELECT x FROM t")).await.unwrap(), 42);
+
+    // It was configured just like the original connection
+    let foreign_keys = vault
+        .execute(Query("SELECT * FROM pragma_foreign_keys"))
+        .await
+        .unwrap();
+    assert_eq!(foreign_keys, 1);
+    let application_id = vault
+        .execute(Query("SELECT * FROM pragma_application_id"))
+        .await
+        .unwrap();
+    assert_eq!(application_id, 7);
+    vault.stop().await;
+
+    let _ = std::fs::remove_file(broken);
+    let _ = std::fs::remove_file(fallback);
+}
+
+#[tokio::test]
+async fn reopened_connection_with_other_application_id_is_rejected() {
+    let broken = temp_db("reopen-rejected-broken");
+    let other = temp_db("reopen-rejected-other");
+    Connection::open(&other)
+        .unwrap()
+        .execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (42); PRAGMA application_id = 8")
+        .unwrap();
+
+    let conn = Connection::open(&broken).unwrap();
+    conn.execute_batch("CREATE TABLE t (x)").unwrap();
+    let vault = TokioVault::builder()
+        .with_application_id(7)
+        .with_connection_factory({
+            let other = other.clone();
+            move || Connection::open(&other)
+        })
+        .launch(conn, &[])
+        .unwrap();
+
+    // The other database isn't used, so the broken connection is kept
+    std::fs::write(&broken, vec![0xff; 4096]).unwrap();
+    for _ in 0..2 {
+        let err = vault.execute(Query("SELECT x FROM t")).await.unwrap_err();
+        let vault::tokio::Error::Action(err) = err else {
+            panic!("unexpected error {err:?}");
+        };
+        assert!(is_code(&err, ErrorCode::NotADatabase), "{err}");
+    }
+    vault.stop().await;
+
+    let _ = std::fs::remove_file(broken);
+    let _ = std::fs::remove_file(other);
+}
+
+/// The size of a database's WAL file, if it exists.
+fn wal_size(path: &std::path::Path) -> Option<u64> {
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+    std::fs::metadata(wal).ok().map(|m| m.len())
+}
+
+#[tokio::test]
+async fn checkpoint_on_stop_truncates_wal() {
+    async fn stop_while_open(path: &std::path::Path, checkpoint: bool) -> Option<u64> {
+        let vault = TokioVault::builder()
+            .with_pragma("journal_mode", "wal")
+            .with_checkpoint_on_stop(checkpoint)
+            .launch(Connection::open(path).unwrap(), &[])
+            .unwrap();
+        vault
+            .execute(Batch(
+                "CREATE TABLE IF NOT EXISTS t (x); INSERT INTO t VALUES (1)",
+            ))
+            .await
+            .unwrap();
+
+        // Another connection keeps the WAL file from being deleted on close
+        let other = Connection::open(path).unwrap();
+        other.execute_batch("SELECT * FROM t").unwrap();
+        assert!(wal_size(path).unwrap() > 0);
+        vault.stop().await;
+        let size = wal_size(path);
+
+        // Once the last connection is closed, the WAL file is gone
+        drop(other);
+        assert_eq!(wal_size(path), None);
+        size
+    }
+
+    let path = temp_db("checkpoint-on-stop");
+    assert!(stop_while_open(&path, false).await.unwrap() > 0);
+    assert_eq!(stop_while_open(&path, true).await, Some(0));
+
+    // Without a WAL file, the database file contains all changes
+    let conn = Connection::open(&path).unwrap();
+    let count: i64 = conn
+        .query_row("SELECT count(*) FROM t", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(count, 2);
+    drop(conn);
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn execute_from_many_tasks() {
+    let vault = launch();
+    vault
+        .execute(vault::actions::Exec::new("CREATE TABLE t (x INTEGER)", ()))
+        .await
+        .unwrap();
+
+    // The vault is shared between tasks running on different threads
+    let tasks = (0..16)
+        .map(|task| {
+            let vault = vault.clone();
+            tokio::spawn(async move {
+                for i in 0..25 {
+                    let x = task * 100 + i;
+                    vault
+                        .execute(vault::actions::Exec::new("INSERT INTO t VALUES (?)", [x]))
+                        .await
+                        .unwrap();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let count = vault
+        .execute(Query("SELECT count(*) FROM t"))
+        .await
+        .unwrap();
+    assert_eq!(count, 16 * 25);
+    let distinct = vault
+        .execute(Query("SELECT count(DISTINCT x) FROM t"))
+        .await
+        .unwrap();
+    assert_eq!(distinct, 16 * 25);
+    vault.stop().await;
+}
+
+/// The numbers from 1 to 1000.
+const THOUSAND_ROWS: &str = "
+    WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000)
+    SELECT i FROM n
+";
+
+#[tokio::test]
+async fn stream_rows() {
+    let vault = launch();
+    let mut rx = vault
+        .stream(THOUSAND_ROWS, (), 16, |row| row.get::<_, i64>(0))
+        .unwrap();
+
+    // The worker waits while the channel is full
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(rx.len(), 16);
+
+    let mut rows = vec![];
+    while let Some(row) = rx.recv().await {
+        rows.push(row.unwrap());
+    }
+    assert_eq!(rows, (1..=1000).collect::<Vec<_>>());
+    vault.stop().await;
+}
+
+#[tokio::test]
+async fn stream_dropped_early() {
+    let vault = launch();
+    let mut rx = vault
+        .stream(THOUSAND_ROWS, (), 1, |row| row.get::<_, i64>(0))
+        .unwrap();
+    assert_eq!(rx.recv().await.unwrap().unwrap(), 1);
+    assert_eq!(rx.recv().await.unwrap().unwrap(), 2);
+    drop(rx);
+
+    // The worker stops streaming and is free for other actions
+    let result = tokio::time::timeout(Duration::from_secs(5), vault.execute(Query("SELECT 42")));
+    assert_eq!(result.await.unwrap().unwrap(), 42);
+    vault.stop().await;
+}
+
+#[tokio::test]
+async fn stream_zero_buffer() {
+    let vault = launch();
+    let mut rx = vault
+        .stream(THOUSAND_ROWS, (), 0, |row| row.get::<_, i64>(0))
+        .unwrap();
+    let err = rx.recv().await.unwrap().unwrap_err();
+    assert!(is_code(&err, ErrorCode::ApiMisuse), "{err}");
+    assert!(rx.recv().await.is_none());
+    vault.stop().await;
+}
+
+#[cfg(feature = "periodic")]
+mod periodic {
+    use std::time::Duration;
+
+    use rusqlite::Connection;
+    use vault::{actions::Exec, tokio::TokioVault, MigrateError};
+
+    use super::Query;
+
+    fn builder() -> (Connection, vault::tokio::TokioVaultBuilder) {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE ticks (at INTEGER)")
+            .unwrap();
+        let builder = TokioVault::builder().with_periodic(Duration::from_millis(20), || {
+            Exec::new("INSERT INTO ticks VALUES (unixepoch())", ())
+        });
+        (conn, builder)
+    }
+
+    #[tokio::test]
+    async fn runs_periodically() {
+        let (conn, builder) = builder();
+        let vault = builder.launch(conn, &[]).unwrap();
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let ticks = vault
+            .execute(Query("SELECT count(*) FROM ticks"))
+            .await
+            .unwrap();
+        assert!(ticks >= 1, "no periodic action ran");
+        vault.stop().await;
+    }
+
+    #[test]
+    fn requires_runtime() {
+        let (conn, builder) = builder();
+        let err = builder.launch(conn, &[]).unwrap_err();
+        assert!(matches!(err, MigrateError::NoRuntime), "{err}");
+    }
+}
+
+#[cfg(feature = "blob")]
+#[tokio::test(flavor = "multi_thread")]
+async fn blob_round_trip() {
+    use std::io::{Read, Write};
+
+    use vault::tokio::{BlobReadAction, BlobWriteAction};
+
+    // Neither chunk size divides the length, so the last chunks are partial
+    const LEN: usize = 3 * 1024 * 1024 + 12345;
+    let data = (0..LEN).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+
+    let vault = launch();
+    vault
+        .execute(vault::actions::Exec::new(
+            "CREATE TABLE files (id INTEGER PRIMARY KEY, content BLOB)",
+            (),
+        ))
+        .await
+        .unwrap();
+    vault
+        .execute(vault::actions::Exec::new(
+            "INSERT INTO files VALUES (1, zeroblob(?))",
+            [LEN as i64],
+        ))
+        .await
+        .unwrap();
+
+    let (action, mut writer) = BlobWriteAction::new("files", "content", 1, 64 * 1024 + 7);
+    let writing = tokio::task::spawn_blocking({
+        let data = data.clone();
+        move || writer.write_all(&data)
+    });
+    let written = vault.execute(action).await.unwrap();
+    writing.await.unwrap().unwrap();
+    assert_eq!(written, LEN);
+
+    let (action, mut reader) = BlobReadAction::new("files", "content", 1, 100_003);
+    let reading = tokio::task::spawn_blocking(move || {
+        let mut read = vec![];
+        reader.read_to_end(&mut read).map(|_| read)
+    });
+    vault.execute(action).await.unwrap();
+    let read = reading.await.unwrap().unwrap();
+    assert_eq!(read.len(), LEN);
+    assert!(read == data, "blob was not read back unchanged");
+    vault.stop().await;
+}
+
+#[tokio::test]
+async fn transaction_per_action_rolls_back_failed_action() {
+    let vault = TokioVault::builder()
+        .with_transaction_per_action(true)
+        .launch(Connection::open_in_memory().unwrap(), &[])
+        .unwrap();
+    vault.execute(Batch("CREATE TABLE t (x)")).await.unwrap();
+
+    // The first insert succeeds, but the action fails afterwards
+    let err = vault
+        .execute(Batch(
+            "INSERT INTO t VALUES (1); INSERT INTO missing VALUES (2)",
+        ))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, vault::tokio::Error::Action(_)), "{err}");
+    assert_eq!(
+        vault
+            .execute(Query("SELECT count(*) FROM t"))
+            .await
+            .unwrap(),
+        0
+    );
+
+    vault
+        .execute(Batch("INSERT INTO t VALUES (1)"))
+        .await
+        .unwrap();
+    assert_eq!(
+        vault
+            .execute(Query("SELECT count(*) FROM t"))
+            .await
+            .unwrap(),
+        1
+    );
+    vault.stop().await;
+}
+
+#[tokio::test]
+async fn pragmas_apply_before_migrations() {
+    fn record_pragmas(
+        tx: &mut rusqlite::Transaction<'_>,
+        _: usize,
+        _: usize,
+    ) -> rusqlite::Result<()> {
+        tx.execute_batch(
+            "
+            CREATE TABLE seen (foreign_keys INTEGER, synchronous INTEGER);
+            INSERT INTO seen VALUES (
+                (SELECT * FROM pragma_foreign_keys),
+                (SELECT * FROM pragma_synchronous)
+            );
+            ",
+        )
+    }
+
+    let vault = TokioVault::builder()
+        .with_pragma("foreign_keys", true)
+        .with_pragma("synchronous", "OFF")
+        .launch(Connection::open_in_memory().unwrap(), &[record_pragmas])
+        .unwrap();
+    assert_eq!(
+        vault
+            .execute(Query("SELECT * FROM pragma_foreign_keys"))
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        vault
+            .execute(Query("SELECT * FROM pragma_synchronous"))
+            .await
+            .unwrap(),
+        0
+    );
+
+    // The migrations already saw the pragmas
+    assert_eq!(
+        vault
+            .execute(Query("SELECT foreign_keys * 10 + synchronous FROM seen"))
+            .await
+            .unwrap(),
+        10
+    );
+    vault.stop().await;
+}
+
+#[tokio::test]
+async fn wal_autocheckpoint_is_set() {
+    let vault = TokioVault::builder()
+        .with_wal_autocheckpoint(100)
+        .launch(Connection::open_in_memory().unwrap(), &[])
+        .unwrap();
+    assert_eq!(
+        vault
+            .execute(Query("PRAGMA wal_autocheckpoint"))
+            .await
+            .unwrap(),
+        100
+    );
+    vault.stop().await;
+
+    let vault = TokioVault::builder()
+        .with_wal_autocheckpoint(0)
+        .launch(Connection::open_in_memory().unwrap(), &[])
+        .unwrap();
+    assert_eq!(
+        vault
+            .execute(Query("PRAGMA wal_autocheckpoint"))
+            .await
+            .unwrap(),
+        0
+    );
+    vault.stop().await;
+}
+
+#[tokio::test]
+async fn transaction_per_action_reports_failed_commit() {
+    let vault = TokioVault::builder()
+        .with_pragma("foreign_keys", true)
+        .with_transaction_per_action(true)
+        .launch(Connection::open_in_memory().unwrap(), &[])
+        .unwrap();
+    vault
+        .execute(Batch(
+            "
+            CREATE TABLE parent (id INTEGER PRIMARY KEY);
+            CREATE TABLE child (
+                parent INTEGER REFERENCES parent (id) DEFERRABLE INITIALLY DEFERRED
+            );
+            ",
+        ))
+        .await
+        .unwrap();
+
+    // Deferred foreign keys are only checked when committing
+    let err = vault
+        .execute(Batch("INSERT INTO child VALUES (1)"))
+        .await
+        .unwrap_err();
+    let vault::tokio::Error::Transaction(err) = err else {
+        panic!("unexpected error {err:?}");
+    };
+    assert!(is_code(&err, ErrorCode::ConstraintViolation), "{err}");
+    assert_eq!(
+        vault
+            .execute(Query("SELECT count(*) FROM child"))
+            .await
+            .unwrap(),
+        0
+    );
+    vault.stop().await;
+}
+
+/// Block the vault's thread for a while.
+struct Sleep(Duration);
+
+impl Action for Sleep {
+    type Output = ();
+    type Error = rusqlite::Error;
+
+    fn run(self, _conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        std::thread::sleep(self.0);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn deadline_interrupts_running_action() {
+    let vault = launch();
+    let start = tokio::time::Instant::now();
+    let deadline = start + Duration::from_millis(200);
+
+    let err = vault
+        .execute_deadline(Query(ENDLESS_QUERY), deadline)
+        .await
+        .unwrap_err();
+    let vault::tokio::Error::Action(err) = err else {
+        panic!("unexpected error {err:?}");
+    };
+    assert!(is_code(&err, ErrorCode::OperationInterrupted), "{err}");
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(200), "{elapsed:?}");
+    assert!(elapsed < Duration::from_secs(2), "{elapsed:?}");
+
+    // Finishing in time isn't affected by the deadline
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    let result = vault.execute_deadline(Query("SELECT 42"), deadline).await;
+    assert_eq!(result.unwrap(), 42);
+    vault.stop().await;
+}
+
+#[tokio::test]
+async fn deadline_expires_queued_action() {
+    let vault = launch();
+    vault.execute(Batch("CREATE TABLE t (x)")).await.unwrap();
+
+    let blocking = tokio::spawn({
+        let vault = vault.clone();
+        async move { vault.execute(Sleep(Duration::from_millis(500))).await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let start = tokio::time::Instant::now();
+    let deadline = start + Duration::from_millis(100);
+    let err = vault
+        .execute_deadline(Batch("INSERT INTO t VALUES (1)"), deadline)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, vault::tokio::Error::DeadlineExceeded),
+        "{err}"
+    );
+    // The action didn't wait for the vault to become idle
+    assert!(start.elapsed() < Duration::from_millis(400));
+
+    // Once the vault is idle again, the expired action is skipped
+    blocking.await.unwrap().unwrap();
+    assert_eq!(
+        vault
+            .execute(Query("SELECT count(*) FROM t"))
+            .await
+            .unwrap(),
+        0
+    );
+    vault.stop().await;
+}
+
+mod hooks {
+    use std::sync::{Arc, Mutex};
+
+    use rusqlite::{Connection, Transaction};
+    use vault::{tokio::TokioVault, MigrateError};
+
+    use super::Query;
+
+    fn create_table(tx: &mut Transaction<'_>, _: usize, _: usize) -> rusqlite::Result<()> {
+        tx.execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (1)")
+    }
+
+    fn table_count(conn: &Connection) -> i64 {
+        conn.query_row(
+            "SELECT count(*) FROM sqlite_schema WHERE name = 't'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn run_around_migrations() {
+        let log = Arc::new(Mutex::new(vec![]));
+
+        // Spawning requires the future to be Send
+        let launching = tokio::spawn({
+            let log = log.clone();
+            let after_log = log.clone();
+            TokioVault::launch_with_hooks(
+                Connection::open_in_memory().unwrap(),
+                &[create_table],
+                move |conn| async move {
+                    tokio::task::yield_now().await;
+                    log.lock()
+                        .unwrap()
+                        .push(format!("before: {}", table_count(&conn)));
+                    Ok::<_, MigrateError>(conn)
+                },
+                move |vault| async move {
+                    let rows = vault
+                        .execute(Query("SELECT count(*) FROM t"))
+                        .await
+                        .unwrap();
+                    after_log.lock().unwrap().push(format!("after: {rows}"));
+                    Ok(())
+                },
+            )
+        });
+        let vault = launching.await.unwrap().unwrap();
+
+        assert_eq!(*log.lock().unwrap(), ["before: 0", "after: 1"]);
+        vault.stop().await;
+    }
+
+    #[tokio::test]
+    async fn failing_after_stops_vault() {
+        let launched = Arc::new(Mutex::new(None));
+        let err = TokioVault::launch_with_hooks(
+            Connection::open_in_memory().unwrap(),
+            &[create_table],
+            |conn| async move { Ok(conn) },
+            |vault| {
+                let launched = launched.clone();
+                async move {
+                    *launched.lock().unwrap() = Some(vault);
+                    Err(MigrateError::Rusqlite(rusqlite::Error::InvalidQuery))
+                }
+            },
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            MigrateError::Rusqlite(rusqlite::Error::InvalidQuery)
+        ));
+
+        let vault = launched.lock().unwrap().take().unwrap();
+        let err = vault.execute(Query("SELECT 1")).await.unwrap_err();
+        assert!(matches!(err, vault::tokio::Error::Stopped), "{err}");
+    }
+}
+
+#[cfg(feature = "debug_current_action")]
+#[tokio::test]
+async fn current_action_name_during_long_action() {
+    let vault = launch();
+    assert_eq!(vault.current_action_name(), None);
+
+    let running = tokio::spawn({
+        let vault = vault.clone();
+        async move { vault.execute(Sleep(Duration::from_millis(300))).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let name = vault.current_action_name().expect("no action running");
+    assert!(name.ends_with("::Sleep"), "{name}");
+
+    running.await.unwrap().unwrap();
+    assert_eq!(vault.current_action_name(), None);
+    vault.stop().await;
+}
+
+mod priority {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use rusqlite::Connection;
+    use vault::{
+        tokio::{Priority, TokioVault},
+        Action,
+    };
+
+    use super::{launch, Sleep};
+
+    type Log = Arc<Mutex<Vec<&'static str>>>;
+
+    /// Record that the action has run.
+    struct Record(Log, &'static str);
+
+    impl Action for Record {
+        type Output = ();
+        type Error = rusqlite::Error;
+
+        fn run(self, _conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+            self.0.lock().unwrap().push(self.1);
+            Ok(())
+        }
+    }
+
+    /// Keep the vault's thread busy while queueing the actions, then return
+    /// the tasks waiting for their results.
+    async fn queue(
+        vault: &TokioVault,
+        log: &Log,
+        actions: &[(&'static str, Priority)],
+    ) -> Vec<tokio::task::JoinHandle<()>> {
+        let mut tasks = vec![tokio::spawn({
+            let vault = vault.clone();
+            async move {
+                vault
+                    .execute(Sleep(Duration::from_millis(200)))
+                    .await
+                    .unwrap()
+            }
+        })];
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        for &(name, priority) in actions {
+            let vault = vault.clone();
+            let action = Record(log.clone(), name);
+            tasks.push(tokio::spawn(async move {
+                vault.execute_priority(action, priority).await.unwrap()
+            }));
+            // Make sure the action is queued before the next one
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        tasks
+    }
+
+    #[tokio::test]
+    async fn high_overtakes_normal() {
+        let vault = launch();
+        let log = Log::default();
+        let tasks = queue(
+            &vault,
+            &log,
+            &[
+                ("normal 1", Priority::Normal),
+                ("normal 2", Priority::Normal),
+                ("high 1", Priority::High),
+                ("normal 3", Priority::Normal),
+                ("high 2", Priority::High),
+            ],
+        )
+        .await;
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["high 1", "high 2", "normal 1", "normal 2", "normal 3"]
+        );
+        vault.stop().await;
+    }
+
+    #[tokio::test]
+    async fn stop_drains_queue() {
+        let vault = launch();
+        let log = Log::default();
+        let tasks = queue(
+            &vault,
+            &log,
+            &[("normal", Priority::Normal), ("high", Priority::High)],
+        )
+        .await;
+
+        vault.stop().await;
+        assert_eq!(*log.lock().unwrap(), ["high", "normal"]);
+        for task in tasks {
+            task.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn dropping_handles_drains_queue() {
+        // The error sink is dropped along with the vault's thread
+        let thread_alive = Arc::new(());
+        let vault = TokioVault::builder()
+            .with_error_sink({
+                let thread_alive = thread_alive.clone();
+                move |_| {
+                    let _ = &thread_alive;
+                }
+            })
+            .launch(Connection::open_in_memory().unwrap(), &[])
+            .unwrap();
+        assert_eq!(Arc::strong_count(&thread_alive), 2);
+        let log = Log::default();
+        let tasks = queue(
+            &vault,
+            &log,
+            &[("normal", Priority::Normal), ("high", Priority::High)],
+        )
+        .await;
+
+        // Only the tasks waiting for their actions keep the vault alive now
+        drop(vault);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*log.lock().unwrap(), ["high", "normal"]);
+
+        for _ in 0..100 {
+            if Arc::strong_count(&thread_alive) == 1 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("vault's thread didn't stop");
+    }
+}
+
+#[cfg(feature = "functions")]
+#[tokio::test]
+async fn scalar_function_called_from_query() {
+    use rusqlite::functions::FunctionFlags;
+
+    fn use_function(
+        tx: &mut rusqlite::Transaction<'_>,
+        _: usize,
+        _: usize,
+    ) -> rusqlite::Result<()> {
+        tx.execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (double(5))")
+    }
+
+    let vault = TokioVault::builder()
+        .with_scalar_function(
+            "double",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |ctx| Ok(ctx.get::<i64>(0)? * 2),
+        )
+        .launch(Connection::open_in_memory().unwrap(), &[use_function])
+        .unwrap();
+    assert_eq!(vault.execute(Query("SELECT double(21)")).await.unwrap(), 42);
+
+    // Migrations can use the function too
+    assert_eq!(vault.execute(Query("SELECT x FROM t")).await.unwrap(), 10);
+
+    // Errors from the function are reported by the query
+    let err = vault
+        .execute(Query("SELECT double('abc')"))
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("Invalid function parameter type"),
+        "{err}"
+    );
+    vault.stop().await;
+}
+
+#[cfg(feature = "collation")]
+#[tokio::test]
+async fn index_with_custom_collation() {
+    /// Compare by length first, then alphabetically.
+    fn by_length(a: &str, b: &str) -> std::cmp::Ordering {
+        a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+    }
+
+    fn create_index(
+        tx: &mut rusqlite::Transaction<'_>,
+        _: usize,
+        _: usize,
+    ) -> rusqlite::Result<()> {
+        tx.execute_batch(
+            "
+            CREATE TABLE words (word TEXT COLLATE by_length);
+            CREATE INDEX words_word ON words (word);
+            INSERT INTO words VALUES ('ccc'), ('a'), ('bb'), ('aaaa'), ('b');
+            ",
+        )
+    }
+
+    struct Words;
+
+    impl Action for Words {
+        type Output = Vec<String>;
+        type Error = rusqlite::Error;
+
+        fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+            conn.prepare("SELECT word FROM words ORDER BY word")?
+                .query_map([], |r| r.get(0))?
+                .collect()
+        }
+    }
+
+    let vault = TokioVault::builder()
+        .with_collation("by_length", by_length)
+        .launch(Connection::open_in_memory().unwrap(), &[create_index])
+        .unwrap();
+    assert_eq!(
+        vault.execute(Words).await.unwrap(),
+        ["a", "b", "bb", "ccc", "aaaa"]
+    );
+
+    // The index uses the collation, so the rows come out sorted without an
+    // extra sorting step
+    let steps = vault
+        .execute(vault::actions::ExplainAction {
+            sql: "SELECT word FROM words ORDER BY word".to_string(),
+        })
+        .await
+        .unwrap();
+    assert!(
+        steps.iter().any(|s| s.detail.contains("INDEX words_word")),
+        "{steps:?}"
+    );
+    assert!(
+        !steps.iter().any(|s| s.detail.contains("TEMP B-TREE")),
+        "{steps:?}"
+    );
+
+    // Comparisons in queries use the collation as well
+    vault
+        .execute(Batch("INSERT INTO words VALUES ('dd')"))
+        .await
+        .unwrap();
+    assert_eq!(
+        vault
+            .execute(Query("SELECT count(*) FROM words WHERE word < 'ccc'"))
+            .await
+            .unwrap(),
+        4
+    );
+    vault.stop().await;
+}
+
+#[cfg(feature = "functions")]
+#[tokio::test]
+async fn median_aggregate() {
+    use rusqlite::functions::{Aggregate, Context, FunctionFlags};
+
+    struct Median;
+
+    impl Aggregate<Vec<f64>, Option<f64>> for Median {
+        fn init(&self, _: &mut Context<'_>) -> rusqlite::Result<Vec<f64>> {
+            Ok(vec![])
+        }
+
+        fn step(&self, ctx: &mut Context<'_>, values: &mut Vec<f64>) -> rusqlite::Result<()> {
+            if let Some(value) = ctx.get::<Option<f64>>(0)? {
+                values.push(value);
+            }
+            Ok(())
+        }
+
+        fn finalize(
+            &self,
+            _: &mut Context<'_>,
+            values: Option<Vec<f64>>,
+        ) -> rusqlite::Result<Option<f64>> {
+            let mut values = values.unwrap_or_default();
+            values.sort_by(f64::total_cmp);
+            let mid = values.len() / 2;
+            Ok(match values.len() {
+                0 => None,
+                n if n % 2 == 0 => Some((values[mid - 1] + values[mid]) / 2.0),
+                _ => Some(values[mid]),
+            })
+        }
+    }
+
+    struct QueryMedian(&'static str);
+
+    impl Action for QueryMedian {
+        type Output = Option<f64>;
+        type Error = rusqlite::Error;
+
+        fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+            conn.query_row(self.0, [], |r| r.get(0))
+        }
+    }
+
+    let vault = TokioVault::builder()
+        .with_aggregate_function(
+            "median",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            Median,
+        )
+        .launch(Connection::open_in_memory().unwrap(), &[])
+        .unwrap();
+    vault
+        .execute(Batch(
+            "
+            CREATE TABLE t (grp, x);
+            INSERT INTO t VALUES (1, 5), (1, 1), (1, 3), (2, 4), (2, 1), (2, NULL), (2, 2), (2, 10);
+            ",
+        ))
+        .await
+        .unwrap();
+
+    let median = |sql| vault.execute(QueryMedian(sql));
+    assert_eq!(
+        median("SELECT median(x) FROM t WHERE grp = 1")
+            .await
+            .unwrap(),
+        Some(3.0)
+    );
+    // NULLs are skipped, even counts average the middle values
+    assert_eq!(
+        median("SELECT median(x) FROM t WHERE grp = 2")
+            .await
+            .unwrap(),
+        Some(3.0)
+    );
+    assert_eq!(
+        median("SELECT median(x) FROM t WHERE grp = 3")
+            .await
+            .unwrap(),
+        None
+    );
+
+    // Each group gets its own accumulator
+    assert_eq!(
+        median("SELECT sum(m) FROM (SELECT median(x) AS m FROM t GROUP BY grp)")
+            .await
+            .unwrap(),
+        Some(6.0)
+    );
+    vault.stop().await;
+}
+
+#[tokio::test]
+async fn try_execute_beyond_max_in_flight() {
+    let vault = TokioVault::builder()
+        .with_max_in_flight(2)
+        .launch(Connection::open_in_memory().unwrap(), &[])
+        .unwrap();
+
+    // One action running and one queued behind it, both via execute
+    let running = (0..2)
+        .map(|_| {
+            let vault = vault.clone();
+            tokio::spawn(async move { vault.execute(Sleep(Duration::from_millis(300))).await })
+        })
+        .collect::<Vec<_>>();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let err = vault.try_execute(Query("SELECT 1")).await.unwrap_err();
+    assert!(matches!(err, vault::tokio::Error::QueueFull), "{err}");
+    assert!(is_code(
+        &rusqlite::Error::from(err),
+        ErrorCode::DatabaseBusy
+    ));
+
+    // Once the actions are done, there's room again
+    for handle in running {
+        handle.await.unwrap().unwrap();
+    }
+    assert_eq!(vault.try_execute(Query("SELECT 1")).await.unwrap(), 1);
+    vault.stop().await;
+}
+
+#[tokio::test]
+async fn page_size_applies_to_new_databases() {
+    fn create_table(
+        tx: &mut rusqlite::Transaction<'_>,
+        _: usize,
+        _: usize,
+    ) -> rusqlite::Result<()> {
+        tx.execute_batch("CREATE TABLE t (x)")
+    }
+
+    let path = temp_db("page-size");
+    let vault = TokioVault::builder()
+        .with_page_size(8192)
+        .launch(Connection::open(&path).unwrap(), &[create_table])
+        .unwrap();
+    assert_eq!(
+        vault.execute(Query("PRAGMA page_size")).await.unwrap(),
+        8192
+    );
+    vault.stop().await;
+
+    // Existing databases keep their page size
+    let vault = TokioVault::builder()
+        .with_page_size(1024)
+        .launch(Connection::open(&path).unwrap(), &[create_table])
+        .unwrap();
+    assert_eq!(
+        vault.execute(Query("PRAGMA page_size")).await.unwrap(),
+        8192
+    );
+    vault.stop().await;
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn dropping_last_clone_stops_thread() {
+    let path = temp_db("drop-last-clone");
+    let vault = TokioVault::builder()
+        .with_pragma("journal_mode", "wal")
+        .launch(Connection::open(&path).unwrap(), &[])
+        .unwrap();
+    vault
+        .execute(Batch("CREATE TABLE t (x); INSERT INTO t VALUES (1)"))
+        .await
+        .unwrap();
+    let clone = vault.clone();
+    drop(vault);
+
+    // The remaining clone still works
+    assert_eq!(
+        clone
+            .execute(Query("SELECT count(*) FROM t"))
+            .await
+            .unwrap(),
+        1
+    );
+    assert!(wal_size(&path).is_some());
+
+    // Closing the connection deletes the WAL file, which shows that the
+    // thread has stopped
+    drop(clone);
+    let start = tokio::time::Instant::now();
+    while wal_size(&path).is_some() {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "thread still running"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn shared_memory_connections_see_each_others_writes() {
+    fn create_table(
+        tx: &mut rusqlite::Transaction<'_>,
+        _: usize,
+        _: usize,
+    ) -> rusqlite::Result<()> {
+        tx.execute_batch("CREATE TABLE t (x)")
+    }
+
+    // Special characters in the name are escaped
+    let name = format!("vault-shared?{}#memory", std::process::id());
+    let vault = TokioVault::launch_shared_memory(&name, &[create_table]).unwrap();
+    let other = vault::open_shared_memory(&name).unwrap();
+
+    vault
+        .execute(Batch("INSERT INTO t VALUES (1)"))
+        .await
+        .unwrap();
+    let count: i64 = other
+        .query_row("SELECT count(*) FROM t", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(count, 1);
+
+    other.execute("INSERT INTO t VALUES (2)", []).unwrap();
+    assert_eq!(
+        vault.execute(Query("SELECT sum(x) FROM t")).await.unwrap(),
+        3
+    );
+
+    // Other names are other databases
+    let unrelated = vault::open_shared_memory(&format!("{name}-other")).unwrap();
+    assert!(unrelated.prepare("SELECT * FROM t").is_err());
+
+    vault.stop().await;
+}
+
+#[tokio::test]
+async fn cache_size_and_warm_cache() {
+    use vault::actions::WarmCacheAction;
+
+    let path = temp_db("warm-cache");
+    let vault = TokioVault::builder()
+        .with_cache_size(5000)
+        .launch(Connection::open(&path).unwrap(), &[])
+        .unwrap();
+    assert_eq!(
+        vault.execute(Query("PRAGMA cache_size")).await.unwrap(),
+        5000
+    );
+
+    // Works on an empty database too
+    vault.execute(WarmCacheAction).await.unwrap();
+
+    vault
+        .execute(Batch(
+            r#"
+            CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT);
+            CREATE INDEX items_name ON items (name);
+            CREATE TABLE "quoted ""name""" (x);
+            WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000)
+            INSERT INTO items SELECT i, 'item ' || i FROM n;
+            "#,
+        ))
+        .await
+        .unwrap();
+    vault.execute(WarmCacheAction).await.unwrap();
+    vault.stop().await;
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn full_disk_degrades_vault() {
+    fn create_table(
+        tx: &mut rusqlite::Transaction<'_>,
+        _: usize,
+        _: usize,
+    ) -> rusqlite::Result<()> {
+        tx.execute_batch("CREATE TABLE t (x)")
+    }
+
+    // Simulate a full disk by limiting the size of the database
+    let vault = TokioVault::builder()
+        .with_pragma("max_page_count", 4)
+        .with_transaction_per_action(true)
+        .launch(Connection::open_in_memory().unwrap(), &[create_table])
+        .unwrap();
+    assert!(!vault.is_degraded());
+
+    let err = vault
+        .execute(Batch(
+            "INSERT INTO t VALUES (1); INSERT INTO t VALUES (zeroblob(100000))",
+        ))
+        .await
+        .unwrap_err();
+    let err = rusqlite::Error::from(err);
+    assert!(is_code(&err, ErrorCode::DiskFull), "{err}");
+    assert!(vault.is_degraded());
+
+    // The whole action was rolled back
+    assert_eq!(
+        vault
+            .execute(Query("SELECT count(*) FROM t"))
+            .await
+            .unwrap(),
+        0
+    );
+
+    // Reads don't end the degraded state, writes do
+    assert!(vault.is_degraded());
+    vault
+        .execute(Batch("INSERT INTO t VALUES (2)"))
+        .await
+        .unwrap();
+    assert!(!vault.is_degraded());
+    assert_eq!(
+        vault.execute(Query("SELECT sum(x) FROM t")).await.unwrap(),
+        2
+    );
+    vault.stop().await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn launch_with_default_vfs() {
+    fn create_table(
+        tx: &mut rusqlite::Transaction<'_>,
+        _: usize,
+        _: usize,
+    ) -> rusqlite::Result<()> {
+        tx.execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (1)")
+    }
+
+    let path = temp_db("vfs");
+    let vault = TokioVault::launch_with_vfs(&path, "unix", &[create_table]).unwrap();
+    assert_eq!(
+        vault
+            .execute(Query("SELECT count(*) FROM t"))
+            .await
+            .unwrap(),
+        1
+    );
+    vault.stop().await;
+
+    // The database was written to the file as usual
+    let conn = Connection::open(&path).unwrap();
+    let count: i64 = conn
+        .query_row("SELECT count(*) FROM t", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(count, 1);
+    drop(conn);
+
+    let err = TokioVault::launch_with_vfs(&path, "no-such-vfs", &[create_table]).unwrap_err();
+    let vault::MigrateError::Rusqlite(err) = err else {
+        panic!("{err}");
+    };
+    assert!(is_code(&err, ErrorCode::Unknown), "{err}");
+    let _ = std::fs::remove_file(path);
+}
+
+#[cfg(feature = "trace")]
+mod trace {
+    use std::{sync::Mutex, time::Duration};
+
+    use rusqlite::Connection;
+    use vault::{actions::Exec, tokio::TokioVault};
+
+    use super::Query;
+
+    #[tokio::test]
+    async fn trace_callback_fires() {
+        static TRACED: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+        fn trace(sql: &str) {
+            TRACED.lock().unwrap().push(sql.to_string());
+        }
+
+        let vault = TokioVault::builder()
+            .with_pragma("foreign_keys", true)
+            .with_trace(trace)
+            .launch(Connection::open_in_memory().unwrap(), &[])
+            .unwrap();
+        vault
+            .execute(Exec::new("CREATE TABLE t (x)", ()))
+            .await
+            .unwrap();
+        vault
+            .execute(Exec::new("INSERT INTO t VALUES (?)", (42,)))
+            .await
+            .unwrap();
+        vault.stop().await;
+
+        let traced = TRACED.lock().unwrap().clone();
+        // Pragmas are traced too, since the callback is installed first
+        assert!(
+            traced.iter().any(|sql| sql.contains("foreign_keys")),
+            "{traced:?}"
+        );
+        // Parameters are replaced by their values
+        assert!(
+            traced.contains(&"INSERT INTO t VALUES (42)".to_string()),
+            "{traced:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn profile_callback_fires() {
+        static PROFILED: Mutex<Vec<(String, Duration)>> = Mutex::new(vec![]);
+
+        fn profile(sql: &str, duration: Duration) {
+            PROFILED.lock().unwrap().push((sql.to_string(), duration));
+        }
+
+        let sql = "
+            WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < ?)
+            SELECT count(*) FROM n
+        ";
+
+        let vault = TokioVault::builder()
+            .with_profile(profile)
+            .launch(Connection::open_in_memory().unwrap(), &[])
+            .unwrap();
+        let count = vault
+            .execute(vault::actions::Count::new(sql, (100_000,)))
+            .await
+            .unwrap();
+        assert_eq!(count, 100_000);
+        assert_eq!(vault.execute(Query("SELECT 1")).await.unwrap(), 1);
+        vault.stop().await;
+
+        let profiled = PROFILED.lock().unwrap().clone();
+        let (_, duration) = profiled
+            .iter()
+            .find(|(s, _)| s == sql)
+            .unwrap_or_else(|| panic!("{profiled:?}"));
+        assert!(*duration > Duration::ZERO);
+        assert!(
+            profiled.iter().any(|(s, _)| s == "SELECT 1"),
+            "{profiled:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn immediate_transactions_lock_eagerly() {
+    use rusqlite::TransactionBehavior;
+
+    fn create_table(
+        tx: &mut rusqlite::Transaction<'_>,
+        _: usize,
+        _: usize,
+    ) -> rusqlite::Result<()> {
+        tx.execute_batch("CREATE TABLE t (x)")
+    }
+
+    /// Try to write from another connection while an action that doesn't
+    /// write runs.
+    async fn write_while_running(path: &std::path::Path, behavior: TransactionBehavior) -> bool {
+        let vault = TokioVault::builder()
+            .with_pragma("journal_mode", "wal")
+            .with_transaction_per_action(true)
+            .with_transaction_behavior(behavior)
+            .launch(Connection::open(path).unwrap(), &[create_table])
+            .unwrap();
+
+        let reading = tokio::spawn({
+            let vault = vault.clone();
+            async move { vault.execute(Sleep(Duration::from_millis(300))).await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let other = Connection::open(path).unwrap();
+        other.busy_timeout(Duration::ZERO).unwrap();
+        let result = other.execute("INSERT INTO t VALUES (1)", []);
+        if let Err(err) = &result {
+            assert!(is_code(err, ErrorCode::DatabaseBusy), "{err}");
+        }
+
+        reading.await.unwrap().unwrap();
+        vault.stop().await;
+        result.is_ok()
+    }
+
+    let path = temp_db("immediate");
+    // A deferred transaction only takes the write lock once the action writes
+    assert!(write_while_running(&path, TransactionBehavior::Deferred).await);
+    assert!(!write_while_running(&path, TransactionBehavior::Immediate).await);
+    let _ = std::fs::remove_file(path);
+}